@@ -129,6 +129,15 @@ int main() {
         zufallszahlen.push_back(converter.take_intervall(intervall));
     }
 
+    // the Rust port of I2B, which takes the timestamps as they are
+    void *extractor = c_interval_extractor(10000);
+    // the SP 800-90B health tests, which halt the output if the detector stops producing random intervals
+    void *health = c_health_tests(1.0);
+    if (extractor == nullptr || health == nullptr) {
+        std::cerr << "Unable to create the extractor or the health tests, exiting" << std::endl;
+        return 1;
+    }
+
     ChannelPair pair = c_client_channel(MPSC_CHANNEL_SIZE);
     void *tx = pair.tx;
     void *rx = pair.rx;
@@ -136,24 +145,47 @@ int main() {
     int data_result = 0;
     std::thread client(c_data_wrapper, &data_result, 127, 0, 0, 1, 8888, tx);
 
-    for(int i = 0; i <= MPSC_CHANNEL_SIZE; i++) {
+    // receive until the server hangs up, since the extractor needs a baseline of 10000 intervals before it yields bins
+    for(int i = 0;; i++) {
         IncomingDataPacket out;
-        if (c_client_channel_recv(&out, rx) == 0) {
+        if (c_client_channel_recv(&out, rx)) {
             std::cerr << "got packet: " << i << std::endl;
-            // what the fuck am i supposed to do with a unsigned __int128 when the buffer is a vector<float>??
-            // TODO
+            uint32_t bin;
+            uint32_t bits = c_interval_extractor_push(extractor, out, &bin);
+            if (bits == 0) continue;
+
+            // 0: the bin may be used, 1: the startup test still runs, -1/-2: the RCT/APT failed
+            int32_t status = c_health_test(health, bin, bits);
+            if (status == 0) {
+                zufallszahlen.push_back(bin);
+            } else if (status == 1) {
+                continue;
+            } else {
+                std::cerr << "Health test failed (" << status << "), exiting" << std::endl;
+                break;
+            }
         } else {
             std::cerr << "Server hung up, exiting" << std::endl;
             break;
         }
     }
 
-    // free the receiver, since rust drop glue is not called
+    // the health tests stay failed until they are reset, so this tells a failure apart from the server hanging up
+    bool healthy = c_health_status(health) >= 0;
+
+    // free the receiver, the extractor and the health tests, since rust drop glue is not called
     c_free_client_receiver(rx);
+    c_free_interval_extractor(extractor);
+    c_free_health_tests(health);
 
     // try joining the thread, if not, exit since there's nothing we can do.
     if (client.joinable()) client.join(); else { std::cerr << "Unable to join client, exiting" << std::endl; return 1; }
 
+    if (!healthy) {
+        std::cerr << "Stopped because a health test failed" << std::endl;
+        return 1;
+    }
+
     // check the result of the thread.
     if (data_result != 0) {
         std::cerr << "Data client returned an error: " << data_result << std::endl;
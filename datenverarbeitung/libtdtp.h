@@ -16,6 +16,115 @@ constexpr static const uint8_t SIG_PACKET = ~EMP;
 /// The exit signal.
 constexpr static const uint8_t SIG_EXIT = 25;
 
+/// The clock synchronisation signal. Sent by the server to start a synchronisation round and echoed by the client,
+/// prefixed with [`CTRL`]. Outside of a round, the client may send `[CTRL, SIG_SYNC]` to request one.
+constexpr static const uint8_t SIG_SYNC = 170;
+
+/// The batch signal, indicating that a little-endian `u16` count follows, followed by that many timestamps.
+constexpr static const uint8_t SIG_BATCH = 187;
+
+/// The maximum amount of timestamps in a single [`SIG_BATCH`] or [`SIG_DELTA_BATCH`] frame.
+constexpr static const uint16_t MAX_BATCH = 1024;
+
+/// The delta signal, indicating that a LEB128 varint follows, which is to be added to the previously received timestamp.
+constexpr static const uint8_t SIG_DELTA = 222;
+
+/// The delta batch signal, indicating that a little-endian `u16` count follows, followed by that many LEB128 varints.
+/// Each of them is a delta to the timestamp preceding it, like in [`SIG_DELTA`].
+constexpr static const uint8_t SIG_DELTA_BATCH = 219;
+
+/// The sequence signal, indicating that the session ID of the server follows as a little-endian `u64`, followed by a
+/// LEB128 varint, which is the sequence number of the next packet. Every packet after it is numbered one higher than
+/// the one preceding it. The server sends it before the first packet of a connection and whenever the numbering skips
+/// ahead, i.e., when packets are missing. The session ID is chosen randomly whenever the server starts.
+constexpr static const uint8_t SIG_SEQUENCE = 94;
+
+/// The resume signal, sent by the client prefixed with [`CTRL`] as its first frame if [`CAP_RESUME`] was negotiated.
+/// It is followed by a LEB128 varint, which is `0` for a new connection. Otherwise, it is one more than the sequence
+/// number of the next packet the client expects, followed by the session ID of that number as a little-endian `u64`,
+/// and the server replays the packets the client missed, as far as they are still available.
+constexpr static const uint8_t SIG_RESUME = 78;
+
+/// The credit signal, sent by the client prefixed with [`CTRL`] if [`CAP_CREDIT`] was negotiated. It is followed by a
+/// LEB128 varint, which is the amount of further packets the client accepts. The server only sends packets while it
+/// has credit, and holds back the others until the client grants more, as far as they are still kept for replay. The
+/// client grants its first credit right after its resume request; `u64::MAX` grants unlimited credit.
+constexpr static const uint8_t SIG_CREDIT = 199;
+
+/// The event signal, indicating that a LEB128 varint follows, which is the length of the event body following it.
+/// The body starts with the version of its format ([`EVENT_VERSION`]) and a byte of field flags, followed by the
+/// timestamp as a little-endian `u128` and the fields whose flags are set, in the order of their flags. Since fields
+/// are only ever appended, a receiver skips the fields it does not know. The timestamp is the base for following
+/// deltas, like that of a [`SIG_PACKET`] frame.
+constexpr static const uint8_t SIG_EVENT = 238;
+
+/// The version of the event body format (see [`SIG_EVENT`]) implemented by this crate. It only changes if the format
+/// changes in a way which is incompatible with receivers of the previous version; new fields do not change it.
+constexpr static const uint8_t EVENT_VERSION = 1;
+
+/// The event field flag for the ID of the channel (detector) which registered the event, a little-endian `u16`.
+constexpr static const uint8_t FIELD_CHANNEL = (1 << 0);
+
+/// The event field flag for the width of the detector pulse in microseconds, a LEB128 varint.
+constexpr static const uint8_t FIELD_PULSE_WIDTH = (1 << 1);
+
+/// The event field flag for the GPIO tick of the event, i.e., the microseconds since the boot of the GPIO library as a
+/// little-endian `u32`, which wraps around.
+constexpr static const uint8_t FIELD_TICK = (1 << 2);
+
+/// The magic bytes opening the hello message of a client.
+constexpr static const uint8_t MAGIC[4] = { 84, 68, 84, 80 };
+
+/// The protocol version implemented by this crate. Clients which do not send a hello message are considered to speak version `0`.
+constexpr static const uint8_t PROTOCOL_VERSION = 1;
+
+/// The oldest protocol version which still supports version negotiation.
+constexpr static const uint8_t MIN_PROTOCOL_VERSION = 1;
+
+/// The capability flag for clock synchronisation (see [`SIG_SYNC`]).
+constexpr static const uint16_t CAP_SYNC = (1 << 0);
+
+/// The capability flag for batched packet frames (see [`SIG_BATCH`]).
+constexpr static const uint16_t CAP_BATCH = (1 << 1);
+
+/// The capability flag for delta-encoded timestamps (see [`SIG_DELTA`]).
+constexpr static const uint16_t CAP_DELTA = (1 << 2);
+
+/// The capability flag for sequence numbers and resuming connections (see [`SIG_SEQUENCE`] and [`SIG_RESUME`]).
+constexpr static const uint16_t CAP_RESUME = (1 << 3);
+
+/// The capability flag for events carrying detector metadata (see [`SIG_EVENT`]).
+constexpr static const uint16_t CAP_EVENT = (1 << 4);
+
+/// The capability flag for credit-based flow control (see [`SIG_CREDIT`]).
+constexpr static const uint16_t CAP_CREDIT = (1 << 5);
+
+/// The capability flag for timestamps sent as offsets from the epoch of the first clock synchronisation, rather than
+/// as unix timestamps (see [`SIG_SYNC`]). It is only negotiated along with [`CAP_SYNC`].
+constexpr static const uint16_t CAP_EPOCH = (1 << 6);
+
+/// All capabilities supported by this crate.
+constexpr static const uint16_t CAPABILITIES = ((((((CAP_SYNC | CAP_BATCH) | CAP_DELTA) | CAP_RESUME) | CAP_EVENT) | CAP_CREDIT) | CAP_EPOCH);
+
+/// The server's reply to a hello message, indicating that the connection was accepted.
+/// It is followed by the negotiated protocol version and the negotiated capabilities as a little-endian `u16`.
+constexpr static const uint8_t HELLO_ACCEPT = 6;
+
+/// The server's reply to a hello message, indicating that the connection was rejected. It is followed by a [`RejectReason`].
+constexpr static const uint8_t HELLO_REJECT = 21;
+
+/// The reject reason for a hello message not starting with [`MAGIC`].
+constexpr static const uint8_t REJECT_BAD_MAGIC = 1;
+
+/// The reject reason for an unsupported protocol version.
+constexpr static const uint8_t REJECT_UNSUPPORTED_VERSION = 2;
+
+/// The reject reason for an unsupported connection type.
+constexpr static const uint8_t REJECT_UNSUPPORTED_CONNECTION = 3;
+
+/// The reject reason for a server which already serves as many connections as it allows.
+constexpr static const uint8_t REJECT_SERVER_FULL = 4;
+
 /// The connection data flag.
 constexpr static const uint8_t CONN_DATA = 1;
 
@@ -37,6 +146,14 @@ extern "C" {
 
 /// A C-compatible wrapper for [`data`].
 ///
+/// Returns `0` if the server terminated the connection. Otherwise:
+///
+/// + A positive value is the OS error code of an I/O error.
+/// + `-1`: the I/O error was not constructed via `last_os_error` or `from_raw_os_error`.
+/// + `-2`: the server sent an unexpected signal or a frame which could not be decoded.
+/// + `-3`: the hello exchange with the server failed.
+/// + `-5`: the receiver hung up.
+///
 /// # Safety
 /// `sender` must be a valid pointer.
 int32_t c_data(uint8_t ip_a,
@@ -46,6 +163,82 @@ int32_t c_data(uint8_t ip_a,
                uint16_t port,
                void *sender);
 
+/// A C-compatible wrapper for [`data`], taking the address as a string.
+///
+/// `host` may be an IPv4 address (`127.0.0.1`), an IPv6 address (`::1`) or a hostname, each of whose addresses is
+/// tried in turn. Returns `0` if the server terminated the connection. Otherwise:
+///
+/// + A positive value is the OS error code of an I/O error.
+/// + `-1`: the I/O error was not constructed via `last_os_error` or `from_raw_os_error`.
+/// + `-2`: the server sent an unexpected signal or a frame which could not be decoded.
+/// + `-3`: the hello exchange with the server failed.
+/// + `-4`: `host` could not be resolved.
+/// + `-5`: the receiver hung up.
+///
+/// # Safety
+/// `host` must be a valid pointer to a nul-terminated string, and `sender` must be a valid pointer.
+int32_t c_data_host(const char *host, uint16_t port, void *sender);
+
+/// C-compatible constructor of an [`IntervalToBits`] extractor whose baseline consists of `baseline_len` intervals.
+/// Returns a null pointer if `baseline_len` is less than `2`. The extractor must be freed with
+/// [`c_free_interval_extractor`].
+void *c_interval_extractor(size_t baseline_len);
+
+/// C-compatible wrapper around [`IntervalToBits::push`]. Returns the amount of bits extracted from the interval ending
+/// at `timestamp`, which is `0` while the baseline is collected. Otherwise, the bits are written to `bin`, as the index
+/// of the bin the interval fell into.
+///
+/// # Safety
+/// `extractor` must be a valid pointer to an extractor, and `bin` a valid pointer.
+uint32_t c_interval_extractor_push(void *extractor, IncomingDataPacket timestamp, uint32_t *bin);
+
+/// Safely drop the passed extractor.
+///
+/// # Safety
+/// `extractor` must be a valid pointer.
+void c_free_interval_extractor(void *extractor);
+
+/// C-compatible wrapper around [`HealthTests::for_entropy`]. Returns a null pointer if `entropy` is not within
+/// `(0, 1]`. The tests must be freed with [`c_free_health_tests`].
+void *c_health_tests(double entropy);
+
+/// Run the health tests on the `bits` lowest bits of `bin`, most significant bit first, as extracted by
+/// [`c_interval_extractor_push`](crate::extract::c_interval_extractor_push).
+///
+/// Returns `0` if the bits may be output, `1` if they must be discarded because the startup test is running, and `-1`,
+/// `-2` or `-3` if the Repetition Count, Adaptive Proportion or starvation test failed, after which no bits may be
+/// output until [`c_health_reset`] is called.
+///
+/// # Safety
+/// `tests` must be a valid pointer to health tests.
+int32_t c_health_test(void *tests, uint32_t bin, uint32_t bits);
+
+/// C-compatible wrapper around [`HealthTests::idle`], which takes the microseconds elapsed without an event, e.g.
+/// between the timestamps of successive events, or since the latest event while only heartbeats arrive. Returns the
+/// state of the tests, as [`c_health_test`] does.
+///
+/// # Safety
+/// `tests` must be a valid pointer to health tests.
+int32_t c_health_idle(void *tests, uint64_t micros);
+
+/// The state of the health tests, as returned by [`c_health_test`].
+///
+/// # Safety
+/// `tests` must be a valid pointer to health tests.
+int32_t c_health_status(const void *tests);
+
+/// C-compatible wrapper around [`HealthTests::reset`].
+///
+/// # Safety
+/// `tests` must be a valid pointer to health tests.
+void c_health_reset(void *tests);
+
+/// Safely drop the passed health tests.
+///
+/// # Safety
+/// `tests` must be a valid pointer.
+void c_free_health_tests(void *tests);
+
 /// A C-compatible wrapper around [`Server::run`].
 ///
 /// If `-1` is returned, the I/O error returned by [`Server::run`] was not constructed via
@@ -62,6 +255,87 @@ int32_t c_server(uint8_t ip_a,
                  uint16_t port,
                  void *receiver);
 
+/// A C-compatible wrapper around [`server`], taking the address to bind to as a string.
+///
+/// `host` may be an IPv4 address (`0.0.0.0`), an IPv6 address (`::`) or a hostname, in which case the server listens
+/// on every address it resolves to. Returns the same values as [`c_server`], or `-4` if `host` could not be resolved.
+///
+/// # Safety
+/// `host` must be a valid pointer to a nul-terminated string, and `receiver` must be a valid pointer.
+int32_t c_server_host(const char *host, uint16_t port, void *receiver);
+
+/// A C-compatible wrapper around [`broadcast_server`]. The heartbeat interval is given in milliseconds. Returns the
+/// same values as [`c_server`].
+///
+/// # Safety
+/// `receiver` must be a valid pointer.
+int32_t c_broadcast_server(uint8_t ip_a,
+                           uint8_t ip_b,
+                           uint8_t ip_c,
+                           uint8_t ip_d,
+                           uint16_t port,
+                           void *receiver,
+                           size_t queue_size,
+                           uint64_t heartbeat_ms);
+
+/// A C-compatible wrapper around [`broadcast_server`], taking the address to bind to as a string. See
+/// [`c_server_host`] and [`c_broadcast_server`].
+///
+/// # Safety
+/// `host` must be a valid pointer to a nul-terminated string, and `receiver` must be a valid pointer.
+int32_t c_broadcast_server_host(const char *host,
+                                uint16_t port,
+                                void *receiver,
+                                size_t queue_size,
+                                uint64_t heartbeat_ms);
+
+/// A C-compatible wrapper around [`spawn_server`], taking the address to bind to as a string. The heartbeat interval is
+/// given in milliseconds.
+///
+/// If `0` is returned, the handle to the server was written to `handle`, which must be disposed of with
+/// [`c_server_join`]. Otherwise, the same values as [`c_server_host`] are returned. No error of a connection is fatal.
+///
+/// # Safety
+/// `host` must be a valid pointer to a nul-terminated string, and `receiver` and `handle` must be valid pointers.
+int32_t c_spawn_server_host(const char *host,
+                            uint16_t port,
+                            void *receiver,
+                            uint64_t heartbeat_ms,
+                            void **handle);
+
+/// A C-compatible wrapper around [`spawn_broadcast_server`], taking the address to bind to as a string. See
+/// [`c_spawn_server_host`] and [`c_broadcast_server`].
+///
+/// # Safety
+/// `host` must be a valid pointer to a nul-terminated string, and `receiver` and `handle` must be valid pointers.
+int32_t c_spawn_broadcast_server_host(const char *host,
+                                      uint16_t port,
+                                      void *receiver,
+                                      size_t queue_size,
+                                      uint64_t heartbeat_ms,
+                                      void **handle);
+
+/// A C-compatible wrapper around [`ServerHandle::shutdown`].
+///
+/// # Safety
+/// `handle` must be a valid pointer to a handle which was not joined yet.
+void c_server_shutdown(const void *handle);
+
+/// The amount of connections of a spawned server which failed with an error so far. See [`ServerHandle::stats`].
+///
+/// # Safety
+/// `handle` must be a valid pointer to a handle which was not joined yet.
+uint64_t c_server_failed_connections(const void *handle);
+
+/// A C-compatible wrapper around [`ServerHandle::join`], which also frees the handle.
+///
+/// Returns `0` if the server was shut down, or the same values as [`c_server`] otherwise, e.g. `-2` if the supplier
+/// hung up before.
+///
+/// # Safety
+/// `handle` must be a valid pointer to a handle which was not joined yet.
+int32_t c_server_join(void *handle);
+
 /// C-compatible wrapper for [`client_channel`]. This returns a `*mut ChannelPair` because `ChannelPair` is not FFI-safe.
 ///
 /// # Safety
@@ -70,6 +344,9 @@ ChannelPair c_client_channel(size_t buffer);
 
 /// Create an MPSC channel for the server.
 ///
+/// Packets are sent over it with [`c_server_channel_send`], or with [`c_server_channel_send_event`] to send detector
+/// metadata along with them.
+///
 /// # Safety
 /// The sender must be correctly disposed of with [`c_free_server_sender`].
 ChannelPair c_server_channel(size_t buffer);
@@ -98,6 +375,21 @@ void c_free_server_sender(void *sender);
 ///
 bool c_server_channel_send(OutgoingDataPacket packet, const void *sender);
 
+/// Send the given packet over the supplied server sender, along with detector metadata.
+///
+/// `fields` holds the flags of the fields which are set, i.e., [`FIELD_CHANNEL`](crate::consts::FIELD_CHANNEL),
+/// [`FIELD_PULSE_WIDTH`](crate::consts::FIELD_PULSE_WIDTH) and [`FIELD_TICK`](crate::consts::FIELD_TICK). The values of
+/// the other fields are ignored.
+///
+/// # Safety
+/// `sender` must be a valid pointer.
+bool c_server_channel_send_event(OutgoingDataPacket packet,
+                                 uint8_t fields,
+                                 uint16_t channel,
+                                 uint32_t pulse_width,
+                                 uint32_t tick,
+                                 const void *sender);
+
 /// Receive an incoming data packet from the given receiver. If the sender has hung up, this return `false`, else `true`.
 ///
 /// This will block. For a non-blocking alternative, see `c_client_channel_try_recv`.
@@ -1,10 +1,7 @@
-use std::{
-    net::{IpAddr, Ipv4Addr},
-    thread::spawn,
-};
+use std::thread::spawn;
 
 use tdtp::{
-    client::data,
+    client::{ClientError, data},
     client_mpsc::{ClientReceiver, client_channel},
 };
 
@@ -15,7 +12,10 @@ fn main() {
     });
 
     // once `rx` is dropped, data will end the connection
-    data(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8000, tx).expect("oops, I/O error");
+    match data("127.0.0.1:8000", tx) {
+        Ok(()) | Err(ClientError::ChannelTermination) => (),
+        Err(e) => panic!("oops, client error: {e}"),
+    }
 }
 
 fn package_consumer(receiver: ClientReceiver) {
@@ -1,19 +1,18 @@
 use std::{
     error::Error,
-    net::{IpAddr, Ipv4Addr},
     sync::mpsc::{self, Sender},
     thread::spawn,
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use tdtp::{
-    client::data,
+    client::{ClientError, data},
     client_mpsc::{ClientReceiver, client_channel},
     server::{OutgoingDataPacket, server},
 };
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let addr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let addr = "127.0.0.1:8000";
     // create a channel for the client and the package consumer to communicate
     let (client_tx, client_rx) = client_channel(8192);
     // create a channel for the server and the package producer to communicate
@@ -25,14 +24,19 @@ fn main() -> Result<(), Box<dyn Error>> {
     // this thread will receive packages from the client...
     let consumer_thread = spawn(move || package_consumer(client_rx));
     // and this one will be our server thread, running at 127.0.0.1:8000
-    let server_thread = spawn(move || server(addr, 8000, server_rx));
+    let server_thread = spawn(move || server(addr, server_rx));
 
-    // initiate the connection
-    data(addr, 8000, client_tx).expect("oh no, client error");
+    // initiate the connection. once the consumer is done, `data` will report that the receiver hung up
+    match data(addr, client_tx) {
+        Ok(()) | Err(ClientError::ChannelTermination) => (),
+        Err(e) => panic!("oh no, client error: {e}"),
+    }
 
     // and then wait for the threads to finish
     consumer_thread.join().unwrap();
-    server_thread.join().unwrap().expect("oh no, server error");
+    // the server only returns once something went wrong, which here is the supplier hanging up
+    let Err(e) = server_thread.join().unwrap();
+    println!("Server exited: {e}");
     Ok(())
 }
 
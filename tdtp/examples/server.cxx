@@ -1,12 +1,13 @@
 #include "libtdtp.h"
+#include <atomic>
 #include <thread>
 #include <iostream>
 #include <chrono>
 
-void packet_producer(void *tx, int *result) {
+void packet_producer(void *tx, std::atomic<bool> *running, int *result) {
     int count = 0;
 
-    while (true) {
+    while (running->load()) {
         std::chrono::time_point now = std::chrono::system_clock::now();
         auto us = std::chrono::duration_cast<std::chrono::microseconds>(now.time_since_epoch()).count();
 
@@ -20,6 +21,8 @@ void packet_producer(void *tx, int *result) {
         count += 1;
         printf("sent %ith packet", count);
     }
+
+    *result = 0;
 }
 
 int main() {
@@ -28,22 +31,38 @@ int main() {
     void *rx = pair.rx;
     void *tx = pair.tx;
 
-    int prod_result = -1;
-    std::thread producer(packet_producer, tx, &prod_result);
-
     std::cout << "starting server\n";
-    if (c_server(127, 0, 0, 1, 8888, rx) != 0) {
+    void *server = nullptr;
+    if (c_spawn_server_host("::", 8888, rx, 1000, &server) != 0) {
         perror("oops, server err");
-        producer.join();
+        c_free_server_sender(tx);
         return 1;
     }
 
-    std::cout << "joining thread\n";
+    std::atomic<bool> running(true);
+    int prod_result = -1;
+    std::thread producer(packet_producer, tx, &running, &prod_result);
+
+    std::cout << "press enter to shut the server down\n";
+    std::cin.get();
 
+    std::cout << "joining thread\n";
+    running = false;
     producer.join();
 
-    if (prod_result != 1) {
-        printf("producer returned an err %i", prod_result);
+    // the packets which are still queued are sent before the client is disconnected
+    std::cout << "shutting down server\n";
+    c_server_shutdown(server);
+    int server_result = c_server_join(server);
+    c_free_server_sender(tx);
+
+    if (server_result != 0) {
+        printf("server returned an err %i\n", server_result);
+        return 1;
+    }
+
+    if (prod_result != 0) {
+        printf("producer returned an err %i\n", prod_result);
         return 1;
     }
 
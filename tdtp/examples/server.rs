@@ -1,5 +1,4 @@
 use std::{
-    net::{IpAddr, Ipv4Addr},
     sync::{
         Arc,
         mpsc::{Sender, channel},
@@ -17,7 +16,8 @@ fn main() {
     // which may not be something you want. an Arc will keep it alive for the duration of `main`.
     let _producer_thread = spawn(move || package_producer(Arc::clone(&arc)));
 
-    server(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8000, rx).expect("oops, server error");
+    let Err(e) = server("127.0.0.1:8000", rx);
+    eprintln!("oops, server error: {e}");
 }
 
 fn package_producer(tx: Arc<Sender<OutgoingDataPacket>>) {
@@ -0,0 +1,122 @@
+//! Estimate the min-entropy of the intervals of a capture of timestamps, see [`tdtp::entropy`].
+//!
+//! ```text
+//! tdtp-entropy <capture> [--bins <baseline_len> | --low-bits <bits>]
+//! ```
+//!
+//! By default, the intervals are sorted into the bins of an `IntervalToBits` extractor with the default baseline.
+
+use std::{fs::File, io::BufReader, process::ExitCode};
+
+use tdtp::{
+    entropy::{Estimates, Report, bin_samples, intervals, low_bit_samples, read_capture},
+    extract::{DEFAULT_BASELINE_LEN, IntervalToBits},
+};
+
+/// The amount of samples SP 800-90B requires.
+const MIN_SAMPLES: usize = 1_000_000;
+
+/// How the intervals are turned into samples.
+enum Sampling {
+    /// Sort them into the bins of an `IntervalToBits` extractor with the given baseline length.
+    Bins(usize),
+    /// Take the given amount of their lowest bits.
+    LowBits(u32),
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (path, sampling) = match args.as_slice() {
+        [path] => (path, Sampling::Bins(DEFAULT_BASELINE_LEN)),
+        [path, flag, value] if flag == "--bins" => match value.parse() {
+            Ok(baseline_len) if baseline_len >= 2 => (path, Sampling::Bins(baseline_len)),
+            _ => return usage("the baseline length must be a number of at least 2"),
+        },
+        [path, flag, value] if flag == "--low-bits" => match value.parse() {
+            Ok(bits @ 1..=32) => (path, Sampling::LowBits(bits)),
+            _ => return usage("the low bits must be a number from 1 to 32"),
+        },
+        _ => return usage("invalid arguments"),
+    };
+
+    let timestamps = match File::open(path).and_then(|file| read_capture(BufReader::new(file))) {
+        Ok(timestamps) => timestamps,
+        Err(e) => {
+            eprintln!("Unable to read capture {path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let intervals = intervals(&timestamps);
+
+    let (samples, sample_bits) = match sampling {
+        Sampling::Bins(baseline_len) => {
+            let mut extractor = IntervalToBits::new().with_baseline_len(baseline_len);
+            let samples = bin_samples(&intervals, &mut extractor);
+            println!(
+                "Sorted {} intervals into {} bins ({} rebaselines)",
+                intervals.len(),
+                1_u64 << extractor.bits_per_interval(),
+                extractor.rebaselines()
+            );
+            (samples, extractor.bits_per_interval())
+        }
+        Sampling::LowBits(bits) => {
+            println!(
+                "Took the lowest {bits} bits of {} intervals",
+                intervals.len()
+            );
+            (low_bit_samples(&intervals, bits), bits)
+        }
+    };
+
+    if samples.len() < 2 {
+        eprintln!("Too few samples to estimate: {}", samples.len());
+        return ExitCode::FAILURE;
+    }
+    if samples.len() < MIN_SAMPLES {
+        eprintln!(
+            "Warning: SP 800-90B requires {MIN_SAMPLES} samples, the estimates of {} are unreliable",
+            samples.len()
+        );
+    }
+
+    let report = Report::new(&samples, sample_bits);
+    println!("\nSamples of {sample_bits} bits, per sample:");
+    print_estimates(&report.samples);
+    println!("\nBits of the samples, per bit:");
+    print_estimates(&report.bits);
+    println!(
+        "\nMin-entropy: {:.6} bits per sample of {sample_bits} bits",
+        report.min_entropy()
+    );
+
+    ExitCode::SUCCESS
+}
+
+/// Print every estimate that could be made.
+fn print_estimates(estimates: &Estimates) {
+    let all = [
+        ("Most common value", Some(estimates.most_common_value)),
+        ("Collision", estimates.collision),
+        ("Markov", estimates.markov),
+        ("Compression", estimates.compression),
+        ("t-tuple", estimates.t_tuple),
+        (
+            "Longest repeated substring",
+            estimates.longest_repeated_substring,
+        ),
+    ];
+
+    for (name, estimate) in all {
+        match estimate {
+            Some(estimate) => println!("  {name:<28}{estimate:.6}"),
+            None => println!("  {name:<28}n/a"),
+        }
+    }
+}
+
+/// Print the usage after the given error.
+fn usage(error: &str) -> ExitCode {
+    eprintln!("{error}\nUsage: tdtp-entropy <capture> [--bins <baseline_len> | --low-bits <bits>]");
+    ExitCode::FAILURE
+}
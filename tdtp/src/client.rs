@@ -1,109 +1,1184 @@
 //! Client-side data types and functions.
 //!
-//! A connection can be established with the [`data`] function in this crate.
+//! A connection can be established with the [`data`] function in this crate. To survive transient outages, see
+//! [`data_reconnecting`]. Timeouts and socket options are set with a [`ClientConfig`]. With the `async` feature, an asynchronous equivalent is available in [`asynchronous`].
 
 use std::{
-    io::{self, BufReader, Read, Write},
-    net::{IpAddr, TcpStream},
-    sync::mpsc::SendError,
+    fmt::Display,
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-use log::{error, info, trace};
+use log::{debug, error, info, trace, warn};
 
 use crate::{
-    client_mpsc, close,
-    consts::{ConnectionType, EMP, SIG_EXIT, SIG_PACKET},
+    SocketOptions, client_mpsc, close,
+    codec::{DecodeError, Decoder, Frame, FrameReader, Packet, Position, encode},
+    consts::{
+        CAP_CREDIT, CAP_EPOCH, CAP_RESUME, CAP_SYNC, CAPABILITIES, CTRL, ConnectionType,
+        HELLO_ACCEPT, HELLO_REJECT, MAGIC, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, RejectReason,
+        SIG_EXIT, SIG_SYNC,
+    },
+    no_addresses, random, unix_micros,
 };
 
+#[cfg(feature = "async")]
+pub mod asynchronous;
+
+/// How long to wait for a connection to a single address to be established, and for the server to reply to the hello.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a client whose channel is full checks whether it has drained, so that it can grant the server more credit.
+const DRAIN_POLL: Duration = Duration::from_millis(50);
+
 /// An incoming data packet, sent over a channel to be processed.
 /// This must represent the amount of microseconds elapsed since the unix epoch.
 pub type IncomingDataPacket = u128;
 
+/// An event of a data connection, as received over a channel created with
+/// [`client_event_channel`](client_mpsc::client_event_channel).
+///
+/// By default, a packet is received as its timestamp. To receive the detector metadata sent along with it, use a
+/// `ClientEvent<Packet>` instead, see [`client_mpsc::channel`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientEvent<P = IncomingDataPacket> {
+    /// A packet was received.
+    Packet(P),
+    /// Packets are missing between the packet received before and the one received after this event.
+    Gap(Gap),
+}
+
+impl<P> From<P> for ClientEvent<P> {
+    fn from(packet: P) -> Self {
+        Self::Packet(packet)
+    }
+}
+
+impl<P> ClientEvent<P> {
+    /// Convert the packet of this event, if it holds one.
+    pub fn map<Q>(self, f: impl FnOnce(P) -> Q) -> ClientEvent<Q> {
+        match self {
+            Self::Packet(packet) => ClientEvent::Packet(f(packet)),
+            Self::Gap(gap) => ClientEvent::Gap(gap),
+        }
+    }
+}
+
+/// Packets which are missing from the packets received by a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gap {
+    /// The packets with the sequence numbers `from..to` are missing, e.g. because the queue of the client on the
+    /// server was full, or because they were no longer available when the client resumed its connection.
+    Missing {
+        /// The sequence number of the first missing packet.
+        from: u64,
+        /// The sequence number of the packet following the gap.
+        to: u64,
+    },
+    /// An unknown amount of packets may be missing, because the server restarted, or because a lost connection could
+    /// not be resumed.
+    Unknown,
+}
+
+impl Display for Gap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing { from, to } => write!(f, "missed packets {from}..{to}"),
+            Self::Unknown => write!(f, "missed an unknown amount of packets"),
+        }
+    }
+}
+
+/// An item which a data connection sends over a [`ClientSender`](client_mpsc::ClientSender).
+///
+/// Besides packets, a connection reports gaps in the packets, which are only sent if the item can represent them, like
+/// [`ClientEvent`]. Otherwise, gaps are only logged. Likewise, the detector metadata of a packet is only kept if the
+/// item is or holds a [`Packet`].
+pub trait ChannelItem: Sized {
+    /// Wrap a received packet.
+    fn packet(packet: Packet) -> Self;
+
+    /// Wrap a gap, or return `None` if gaps are not sent.
+    fn gap(gap: Gap) -> Option<Self>;
+}
+
+impl ChannelItem for IncomingDataPacket {
+    fn packet(packet: Packet) -> Self {
+        packet.timestamp
+    }
+
+    fn gap(_: Gap) -> Option<Self> {
+        None
+    }
+}
+
+impl ChannelItem for Packet {
+    fn packet(packet: Packet) -> Self {
+        packet
+    }
+
+    fn gap(_: Gap) -> Option<Self> {
+        None
+    }
+}
+
+impl<P: From<Packet>> ChannelItem for ClientEvent<P> {
+    fn packet(packet: Packet) -> Self {
+        Self::Packet(packet.into())
+    }
+
+    fn gap(gap: Gap) -> Option<Self> {
+        Some(Self::Gap(gap))
+    }
+}
+
+/// A client error.
+#[derive(Debug)]
+pub enum ClientError {
+    /// An I/O error was encountered.
+    IoError(io::Error),
+    /// The server sent a signal which is not valid at this point of the protocol.
+    UnexpectedSignal(u8),
+    /// The packet receiver hung up.
+    ChannelTermination,
+    /// The hello exchange with the server failed.
+    Handshake(HandshakeError),
+    /// The server sent a frame which could not be decoded.
+    Protocol(DecodeError),
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => write!(f, "{e}"),
+            Self::UnexpectedSignal(sig) => write!(f, "Unexpected signal {sig:#04x}"),
+            Self::ChannelTermination => write!(f, "Channel disconnected"),
+            Self::Handshake(e) => write!(f, "Handshake failed: {e}"),
+            Self::Protocol(e) => write!(f, "Protocol error: {e}"),
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(value: io::Error) -> Self {
+        Self::IoError(value)
+    }
+}
+
+impl From<DecodeError> for ClientError {
+    fn from(value: DecodeError) -> Self {
+        Self::Protocol(value)
+    }
+}
+
+impl From<HandshakeError> for ClientError {
+    fn from(value: HandshakeError) -> Self {
+        Self::Handshake(value)
+    }
+}
+
+/// The reason for which the hello exchange with the server failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeError {
+    /// The server rejected the connection. Contains the raw reason code, see [`RejectReason`].
+    Rejected(u8),
+    /// The server negotiated a protocol version which is not supported by this crate.
+    UnsupportedVersion(u8),
+    /// The server replied with neither [`HELLO_ACCEPT`] nor [`HELLO_REJECT`].
+    InvalidReply(u8),
+}
+
+impl Display for HandshakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rejected(code) => match RejectReason::try_from(*code) {
+                Ok(reason) => write!(f, "server rejected connection: {reason}"),
+                Err(code) => write!(
+                    f,
+                    "server rejected connection with unknown reason {code:#04x}"
+                ),
+            },
+            Self::UnsupportedVersion(v) => write!(f, "unsupported protocol version {v}"),
+            Self::InvalidReply(reply) => write!(f, "invalid hello reply {reply:#04x}"),
+        }
+    }
+}
+
+/// The result of a clock synchronisation round with the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncInfo {
+    /// The local instant at which the server marked the shared epoch.
+    pub epoch: Instant,
+    /// The server's unix timestamp (in microseconds) at the shared epoch.
+    pub epoch_micros: u128,
+    /// The estimated difference between the server's and the local system clock, in microseconds.
+    /// Positive values mean the server clock is ahead.
+    pub offset: i128,
+    /// The measured round-trip time.
+    pub rtt: Duration,
+}
+
+impl SyncInfo {
+    /// Convert the given packet into a local, monotonic [`Instant`] relative to the shared epoch.
+    ///
+    /// Packets hold the unix timestamps of the server. If [`CAP_EPOCH`] was negotiated, they are sent as offsets from
+    /// the epoch, but converted back while decoding. This maps them onto the local clock as
+    /// `epoch + (packet - epoch_micros)`.
+    ///
+    /// Returns `None` if the result cannot be represented by an [`Instant`].
+    #[must_use]
+    pub fn to_instant(&self, packet: IncomingDataPacket) -> Option<Instant> {
+        if packet >= self.epoch_micros {
+            let since = u64::try_from(packet - self.epoch_micros).ok()?;
+            self.epoch.checked_add(Duration::from_micros(since))
+        } else {
+            let until = u64::try_from(self.epoch_micros - packet).ok()?;
+            self.epoch.checked_sub(Duration::from_micros(until))
+        }
+    }
+}
+
+/// A handle to the clock synchronisation state of a data connection.
+///
+/// Clones of this handle share the same state, so one may be passed to [`data_with_clock`] while another is kept by the caller.
+#[derive(Debug, Clone, Default)]
+pub struct ClockSync {
+    /// The result of the latest synchronisation round, if any.
+    info: Arc<Mutex<Option<SyncInfo>>>,
+    /// Whether the caller requested a new synchronisation round.
+    requested: Arc<AtomicBool>,
+}
+
+impl ClockSync {
+    /// Create a new, unsynchronised handle.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The result of the latest synchronisation round, or `None` if none has completed yet.
+    #[must_use]
+    pub fn info(&self) -> Option<SyncInfo> {
+        *self.info.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The estimated clock offset to the server in microseconds. See [`SyncInfo::offset`].
+    #[must_use]
+    pub fn offset(&self) -> Option<i128> {
+        self.info().map(|i| i.offset)
+    }
+
+    /// The measured round-trip time. See [`SyncInfo::rtt`].
+    #[must_use]
+    pub fn rtt(&self) -> Option<Duration> {
+        self.info().map(|i| i.rtt)
+    }
+
+    /// Request a new synchronisation round. The connection will send the request before reading the next frame.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+    }
+
+    /// Store the result of a synchronisation round.
+    fn update(&self, info: SyncInfo) {
+        *self.info.lock().unwrap_or_else(PoisonError::into_inner) = Some(info);
+    }
+}
+
+/// How a client reconnects after losing its connection. See [`data_reconnecting`].
+///
+/// The delay before the `n`th consecutive attempt is `initial_delay * 2^(n - 1)`, capped at `max_delay`. To keep
+/// several clients from reconnecting in lockstep, a random jitter of up to half the delay is subtracted.
+///
+/// An attempt only succeeds once the server sent the first frame over the new connection, so that a server which
+/// accepts connections, but drops them right away, is backed off from as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reconnect {
+    /// The delay before the first attempt.
+    pub initial_delay: Duration,
+    /// The upper bound of the delay.
+    pub max_delay: Duration,
+    /// How many consecutive attempts may fail before the client gives up, or `None` to retry forever.
+    pub max_attempts: Option<u32>,
+    /// How long the client waits for a frame before assuming the connection was lost, or `None` to wait forever.
+    ///
+    /// This should be a multiple of the heartbeat interval of the server.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_attempts: None,
+            idle_timeout: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+impl Reconnect {
+    /// The delay before the given attempt, starting at `1`, including jitter.
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(1 << (attempt - 1).min(31))
+            .min(self.max_delay);
+
+        let random = random();
+        let jitter = delay / 2;
+        let jitter_nanos = u64::try_from(jitter.as_nanos()).unwrap_or(u64::MAX);
+
+        delay.saturating_sub(Duration::from_nanos(
+            random % jitter_nanos.saturating_add(1),
+        ))
+    }
+}
+
+/// The state of a reconnecting client, as reported by [`data_reconnecting`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The client connected to the server at the given address and completed the handshake.
+    Connected(SocketAddr),
+    /// The connection was lost or could not be established. The client will try again after `delay`.
+    Reconnecting {
+        /// The number of consecutive failed attempts so far.
+        attempt: u32,
+        /// How long the client waits before the next attempt.
+        delay: Duration,
+    },
+    /// The client exceeded [`Reconnect::max_attempts`] and stopped.
+    GaveUp,
+}
+
+/// The configuration of a client, see [`data_with_config`].
+///
+/// Every setting which is not adjusted with the `with_` methods keeps the default used by [`data`].
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use tdtp::client::ClientConfig;
+///
+/// let config = ClientConfig::new()
+///     .with_connect_timeout(Duration::from_secs(1))
+///     .with_read_timeout(Some(Duration::from_secs(10)))
+///     .with_nodelay(true);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientConfig {
+    /// How long to wait for a connection to be established, and for the server to reply to the hello.
+    connect_timeout: Duration,
+    /// How long to wait for a frame before the connection fails, or `None` to wait indefinitely.
+    read_timeout: Option<Duration>,
+    /// The options of the stream.
+    socket: SocketOptions,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: CONNECT_TIMEOUT,
+            read_timeout: None,
+            socket: SocketOptions::default(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// The default configuration of a client.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how long to wait for a connection to a single address to be established, and for the server to reply to the
+    /// hello. Defaults to [`CONNECT_TIMEOUT`].
+    #[must_use]
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Set how long to wait for a frame before the connection fails, or `None` to wait indefinitely, which is the
+    /// default. Since the server sends heartbeats, this should be a multiple of its heartbeat interval.
+    #[must_use]
+    pub fn with_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Set how long a write to the server may block before the connection fails. By default, writes block
+    /// indefinitely.
+    #[must_use]
+    pub fn with_write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.socket.write_timeout = timeout;
+        self
+    }
+
+    /// Set whether Nagle's algorithm is disabled (`TCP_NODELAY`), so that control signals, e.g. the echo of a clock
+    /// synchronisation round, are sent right away. Defaults to `false`.
+    #[must_use]
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.socket.nodelay = nodelay;
+        self
+    }
+
+    /// Set after how long an idle connection is probed with TCP keepalive. By default, the connection is not probed.
+    #[must_use]
+    pub fn with_keepalive(mut self, keepalive: Option<Duration>) -> Self {
+        self.socket.keepalive = keepalive;
+        self
+    }
+
+    /// Set the size of the send buffer of the socket in bytes, or `None` to keep the system default.
+    #[must_use]
+    pub fn with_send_buffer(mut self, size: Option<usize>) -> Self {
+        self.socket.send_buffer = size;
+        self
+    }
+
+    /// Set the size of the receive buffer of the socket in bytes, or `None` to keep the system default.
+    #[must_use]
+    pub fn with_recv_buffer(mut self, size: Option<usize>) -> Self {
+        self.socket.recv_buffer = size;
+        self
+    }
+}
+
 /// Initiate a data connection to the given address.
 ///
+/// `addr` may resolve to several addresses, e.g. a hostname with both an IPv4 and an IPv6 address. Each is tried in
+/// turn until a connection is established within [`CONNECT_TIMEOUT`].
+///
 /// Once a packet is received, this function will send it to the other end of the supplied `supplier`.
 ///
-/// If the receiver has hung up, this function will attempt to terminate the connection and exit with `Ok(())`.
+/// If the server terminates the connection, this function exits with `Ok(())`.
+/// If the receiver has hung up, this function will attempt to terminate the connection and exit with
+/// `Err(ClientError::ChannelTermination)`.
+///
+/// If `sender` belongs to a channel created with [`client_event_channel`](client_mpsc::client_event_channel), gaps in
+/// the packets are reported as [`ClientEvent::Gap`]. For this, the server must support sequence numbers.
+///
+/// If the server supports it, it only sends as many packets as there is space in the channel of `sender`. Once the
+/// receiver does not keep up, the server holds packets back until the channel has drained, and sends them afterwards.
+/// Reported gaps take space in the channel, but are excluded from this, so the client may wait for the receiver after
+/// reporting one.
 ///
-/// The [`Sender`] requested by this function is not the [`std::sync::mpsc::Sender`]. It is a custom sender which allows this function to check
-/// if the other side has hung up.
+/// To configure timeouts and socket options, see [`data_with_config`].
+///
+/// `sender` is a [`ClientSender`](client_mpsc::ClientSender) rather than a [`std::sync::mpsc::Sender`], so that this
+/// function notices the receiver hanging up even while no packets arrive. Note that this is a breaking change: a
+/// receiver which hangs up used to end this function with `Ok(())`, whereas it now returns
+/// `Err(ClientError::ChannelTermination)`.
 ///
 /// # Errors
-/// May return an I/O error.
+/// Returns an I/O error, an error indicating that the receiver hung up, or a protocol error if the server
+/// rejected the connection, sent an unexpected signal or sent a frame which could not be decoded.
 ///
 /// # Example
 /// ```no_run
-/// use tdtp_impl::client::{ChannelDataPacket, data};
-/// use core::net::{IpAddr, Ipv4Addr};
-/// use std::{thread::spawn, sync::mpsc::channel};
+/// use tdtp::{client::data, client_mpsc::client_channel};
+/// use std::thread::spawn;
 ///
-/// let (tx, rx) = channel();
+/// let (tx, rx) = client_channel(8192);
 ///
 /// let consumer_thread = spawn(move || {
 ///     while let Ok(packet) = rx.recv() {
-///         if let ChannelDataPacket::Packet(packet) = packet {
-///             println!("Got a packet: {packet:?}");
+///         println!("Got a packet: {packet:?}");
+///     }
+/// });
+///
+/// data("localhost:8000", tx);
+/// ```
+pub fn data<T: ChannelItem>(
+    addr: impl ToSocketAddrs,
+    sender: client_mpsc::ClientSender<T>,
+) -> Result<(), ClientError> {
+    data_with_clock(addr, sender, &ClockSync::new())
+}
+
+/// Like [`data`], but publishes the results of clock synchronisation rounds to the given [`ClockSync`] handle.
+///
+/// The server synchronises clocks once on connect; further rounds can be requested with [`ClockSync::request`].
+///
+/// # Errors
+/// See [`data`].
+///
+/// # Example
+/// ```no_run
+/// use tdtp::{client::{ClockSync, data_with_clock}, client_mpsc::client_channel};
+///
+/// let (tx, rx) = client_channel(8192);
+/// let clock = ClockSync::new();
+/// let handle = clock.clone();
+///
+/// std::thread::spawn(move || {
+///     while let Ok(packet) = rx.recv() {
+///         println!("Packet {packet} arrived at offset {:?}", handle.offset());
+///     }
+/// });
+///
+/// data_with_clock("127.0.0.1:8000", tx, &clock);
+/// ```
+pub fn data_with_clock<T: ChannelItem>(
+    addr: impl ToSocketAddrs,
+    sender: client_mpsc::ClientSender<T>,
+    clock: &ClockSync,
+) -> Result<(), ClientError> {
+    data_with_config(addr, sender, clock, &ClientConfig::default())
+}
+
+/// Like [`data_with_clock`], but configured by `config`. See [`ClientConfig`].
+///
+/// # Errors
+/// See [`data`]. If the read timeout of `config` elapses without a frame from the server, an I/O error is returned.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use tdtp::{client::{ClientConfig, ClockSync, data_with_config}, client_mpsc::client_channel};
+///
+/// let (tx, rx) = client_channel(8192);
+/// let config = ClientConfig::new()
+///     .with_read_timeout(Some(Duration::from_secs(10)))
+///     .with_keepalive(Some(Duration::from_secs(30)));
+///
+/// std::thread::spawn(move || {
+///     while let Ok(packet) = rx.recv() {
+///         println!("Got a packet: {packet:?}");
+///     }
+/// });
+///
+/// data_with_config("localhost:8000", tx, &ClockSync::new(), &config);
+/// ```
+#[expect(clippy::needless_pass_by_value)]
+pub fn data_with_config<T: ChannelItem>(
+    addr: impl ToSocketAddrs,
+    sender: client_mpsc::ClientSender<T>,
+    clock: &ClockSync,
+    config: &ClientConfig,
+) -> Result<(), ClientError> {
+    let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+    let (stream, _, capabilities) = connect(&addrs, config)?;
+
+    receive(stream, capabilities, &sender, clock, &mut None, &mut false)
+}
+
+/// Like [`data_with_clock`], but reconnects to the server if the connection is lost.
+///
+/// If the connection fails with an I/O error, e.g. because the network dropped, the client waits with exponential
+/// backoff as configured by `reconnect` and then connects and performs the handshake again. Packets keep arriving on
+/// the same `sender`. If the server supports it, the new connection resumes where the lost one ended, and the server
+/// replays the packets sent in the meantime. Otherwise, an unknown [`Gap`] is reported. Every change of the connection
+/// state is reported to `on_state`.
+///
+/// The client stops once the server terminates the connection, the receiver hangs up, the server rejects the
+/// connection or sends an unexpected signal or a frame which cannot be decoded, or [`Reconnect::max_attempts`]
+/// consecutive attempts failed.
+///
+/// # Errors
+/// See [`data`]. If the client gave up reconnecting, the error of the last attempt is returned.
+///
+/// # Example
+/// ```no_run
+/// use tdtp::{
+///     client::{ClientEvent, ClockSync, ConnectionState, Reconnect, data_reconnecting},
+///     client_mpsc::client_event_channel,
+/// };
+///
+/// let (tx, rx) = client_event_channel(8192);
+///
+/// std::thread::spawn(move || {
+///     while let Ok(event) = rx.recv() {
+///         match event {
+///             ClientEvent::Packet(packet) => println!("Got a packet: {packet}"),
+///             ClientEvent::Gap(gap) => println!("Lost packets: {gap}"),
 ///         }
 ///     }
 /// });
 ///
-/// data(
-///     IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-///     8000,
-///     tx
-/// );
+/// data_reconnecting("detector.local:8000", tx, &ClockSync::new(), &Reconnect::default(), |state| {
+///     match state {
+///         ConnectionState::Connected(addr) => println!("Connected to {addr}"),
+///         ConnectionState::Reconnecting { attempt, delay } => {
+///             println!("Reconnecting in {delay:?} (attempt {attempt})");
+///         }
+///         ConnectionState::GaveUp => println!("Giving up"),
+///     }
+/// });
 /// ```
+pub fn data_reconnecting<T: ChannelItem>(
+    addr: impl ToSocketAddrs,
+    sender: client_mpsc::ClientSender<T>,
+    clock: &ClockSync,
+    reconnect: &Reconnect,
+    on_state: impl FnMut(ConnectionState),
+) -> Result<(), ClientError> {
+    data_reconnecting_with_config(
+        addr,
+        sender,
+        clock,
+        reconnect,
+        &ClientConfig::default(),
+        on_state,
+    )
+}
+
+/// Like [`data_reconnecting`], but configured by `config`. See [`ClientConfig`].
+///
+/// If [`Reconnect::idle_timeout`] is set, it takes precedence over the read timeout of `config`.
+///
+/// # Errors
+/// See [`data_reconnecting`].
 #[expect(clippy::needless_pass_by_value)]
-pub fn data(ip: IpAddr, port: u16, sender: client_mpsc::ClientSender) -> io::Result<()> {
-    info!("Connecting to {ip}:{port}");
-    let mut stream = TcpStream::connect((ip, port))?; // W
-    info!("Connected to {ip}:{port}");
-    let mut reader = BufReader::new(stream.try_clone()?); // R
-    trace!("Sending data signal");
-    stream.write_all(&[ConnectionType::Data as u8])?;
+pub fn data_reconnecting_with_config<T: ChannelItem>(
+    addr: impl ToSocketAddrs,
+    sender: client_mpsc::ClientSender<T>,
+    clock: &ClockSync,
+    reconnect: &Reconnect,
+    config: &ClientConfig,
+    mut on_state: impl FnMut(ConnectionState),
+) -> Result<(), ClientError> {
+    // the number of consecutive failed attempts
+    let mut attempt = 0;
+    // the position of the next packet, kept across connections to resume where the last one ended
+    let mut next_seq = None;
+    let mut reconnected = false;
+
+    loop {
+        if !sender.has_receiver() {
+            trace!("Client packet receiver hung up, exiting");
+            return Err(ClientError::ChannelTermination);
+        }
 
-    let mut sig = [0xCE]; // some unused signal
-    let mut data = [0; 16];
+        let connected = addr
+            .to_socket_addrs()
+            .map_err(ClientError::from)
+            .and_then(|addrs| connect(&addrs.collect::<Vec<_>>(), config));
+
+        let e = match connected {
+            Ok((stream, addr, capabilities)) => 'connection: {
+                on_state(ConnectionState::Connected(addr));
+
+                if let Some(timeout) = reconnect.idle_timeout
+                    && let Err(e) = stream.set_read_timeout(Some(timeout))
+                {
+                    break 'connection e;
+                }
+
+                if reconnected && (next_seq.is_none() || capabilities & CAP_RESUME == 0) {
+                    next_seq = None;
+                    report(&sender, Gap::Unknown)?;
+                }
+                reconnected = true;
+
+                let mut established = false;
+                let result = receive(
+                    stream,
+                    capabilities,
+                    &sender,
+                    clock,
+                    &mut next_seq,
+                    &mut established,
+                );
+                if established {
+                    attempt = 0;
+                }
+
+                match result {
+                    Err(ClientError::IoError(e)) => e,
+                    result => return result,
+                }
+            }
+            Err(ClientError::IoError(e)) => e,
+            Err(e) => return Err(e),
+        };
+
+        attempt += 1;
+        if reconnect.max_attempts.is_some_and(|max| attempt > max) {
+            error!("Giving up after {} failed attempts: {e}", attempt - 1);
+            on_state(ConnectionState::GaveUp);
+            return Err(e.into());
+        }
+
+        let delay = reconnect.delay(attempt);
+        warn!("Connection lost ({e}), reconnecting in {delay:?}");
+        on_state(ConnectionState::Reconnecting { attempt, delay });
+        thread::sleep(delay);
+    }
+}
+
+/// Receive packets over an established data connection and send them to `sender`. See [`data`].
+///
+/// If the server supports it, the connection resumes at the position `next_seq`, which is kept up to date with the
+/// packets received, and the server is granted credit for the space in the channel of `sender`, see [`FlowControl`].
+/// `established` is set once the first frame was received.
+fn receive<T: ChannelItem>(
+    mut stream: TcpStream,
+    capabilities: u16,
+    sender: &client_mpsc::ClientSender<T>,
+    clock: &ClockSync,
+    next_seq: &mut Option<Position>,
+    established: &mut bool,
+) -> Result<(), ClientError> {
+    if capabilities & CAP_RESUME != 0 {
+        encode(&Frame::Resume(*next_seq), &mut stream)?;
+    }
+
+    let mut reader = FrameReader::with_decoder(stream.try_clone()?, decoder(capabilities)); // R
+
+    // the packets decoded from the current frame
+    let mut packets = Vec::new();
+    // the last timestamp received, which is the base of the next delta
+    let mut last = None;
+    // the synchronisation round which is waiting for the reply of the server
+    let mut round = None;
+    let sync = capabilities & CAP_SYNC != 0;
+    // the server synchronises on connect, so we must not request a round until that one is done
+    let mut awaiting_sync = sync;
+    let mut flow = None;
+    if capabilities & CAP_CREDIT != 0 {
+        flow = FlowControl::new(sender.capacity());
+        if flow.is_none() {
+            encode(&Frame::Credit(u64::MAX), &mut stream)?;
+        }
+    }
 
     loop {
         if !sender.has_receiver() {
-            return Ok(());
-        }
-
-        trace!("Reading signal");
-        reader
-            .read_exact(&mut sig)
-            .inspect_err(|v| error!("Failed to read signal: {v}"))?;
-
-        match sig[0] {
-            EMP => (),
-            SIG_PACKET => {
-                trace!("Reading data");
-                reader.read_exact(&mut data)?;
-                if handle_packet(data, &sender).is_err() {
-                    trace!("Client packet receiver hung up, exiting");
-                    break close(stream);
+            trace!("Client packet receiver hung up, exiting");
+            close(stream, &Frame::Control(SIG_EXIT))?;
+            return Err(ClientError::ChannelTermination);
+        }
+
+        if sync && !awaiting_sync && clock.requested.swap(false, Ordering::Relaxed) {
+            debug!("Requesting clock synchronisation");
+            encode(&Frame::Control(SIG_SYNC), &mut stream)?;
+            awaiting_sync = true;
+        }
+
+        if let Some(flow) = &mut flow {
+            flow.update(sender.len(), &mut stream)?;
+        }
+
+        trace!("Reading frame");
+        let frame = match reader.next_frame::<ClientError>() {
+            Ok(frame) => {
+                *established = true;
+                frame
+            }
+            Err(ClientError::IoError(e)) if flow.as_ref().is_some_and(|flow| flow.polled(&e)) => {
+                continue;
+            }
+            Err(e) => {
+                error!("Failed to read frame: {e}");
+                return Err(e);
+            }
+        };
+
+        packets.clear();
+
+        match frame {
+            Frame::Sync => {
+                let mut start = SyncRound::start();
+                encode(&Frame::Control(SIG_SYNC), &mut stream)?;
+                start.echoed();
+                round = Some(start);
+            }
+            Frame::SyncReply {
+                elapsed,
+                epoch_micros,
+            } => {
+                if let Some(round) = round.take() {
+                    clock.update(round.finish(elapsed, epoch_micros));
                 }
+                awaiting_sync = false;
             }
-            SIG_EXIT => {
+            Frame::Sequence(position) => {
+                if let Some(gap) = sequence(next_seq, position)
+                    && report(sender, gap).is_err()
+                {
+                    close(stream, &Frame::Control(SIG_EXIT))?;
+                    return Err(ClientError::ChannelTermination);
+                }
+            }
+            Frame::Exit => {
                 info!("Server terminated connection, exiting");
                 break Ok(());
             }
-            _ => todo!(),
+            Frame::Control(_) => {
+                error!("Server sent a control signal, exiting");
+                break Err(ClientError::UnexpectedSignal(CTRL));
+            }
+            frame => frame
+                .events_into(&mut last, &mut packets)
+                .inspect_err(|v| error!("Failed to decode packets: {v}"))?,
+        }
+
+        if let Some(next) = next_seq {
+            next.seq += packets.len() as u64;
+        }
+        if let Some(flow) = &mut flow {
+            flow.received(packets.len());
+        }
+
+        for &packet in &packets {
+            if sender.send(T::packet(packet)).is_err() {
+                trace!("Client packet receiver hung up, exiting");
+                close(stream, &Frame::Control(SIG_EXIT))?;
+                return Err(ClientError::ChannelTermination);
+            }
+        }
+    }
+}
+
+/// Grants the server credit for the free space in the channel of the client ([`SIG_CREDIT`]), so that backpressure
+/// does not depend on the TCP buffers.
+///
+/// Space is free if it is neither filled nor covered by credit which is not used up yet, and it is granted once it
+/// amounts to a quarter of the channel. While the outstanding credit is below that, the stream is read with a short
+/// timeout, so that the client notices the channel draining even if the server holds back all packets.
+///
+/// Gaps are excluded from the credit: the server sends no packet for them, so they use up no credit, but they take a
+/// slot in the channel like packets do and are counted in its fill level once sent. A gap reported while the
+/// outstanding credit covers all free space thus leaves one slot too few, and the client waits for the receiver to
+/// take an item before it reads on.
+struct FlowControl {
+    /// The capacity of the channel.
+    capacity: u64,
+    /// The amount of free space which is granted at once.
+    threshold: u64,
+    /// The credit granted, but not used up by packets received yet.
+    outstanding: u64,
+    /// The state of the client while it waits for the channel to drain, if it does.
+    waiting: Option<Waiting>,
+}
+
+/// The state of a client which waits for its channel to drain.
+struct Waiting {
+    /// The read timeout of the stream before waiting, which is restored afterwards.
+    timeout: Option<Duration>,
+    /// When the last frame was received.
+    last_frame: Instant,
+}
+
+impl FlowControl {
+    /// Create the flow control for a channel of the given capacity, or `None` if the channel holds no items.
+    fn new(capacity: usize) -> Option<Self> {
+        let capacity = capacity as u64;
+        (capacity > 0).then(|| Self {
+            capacity,
+            threshold: (capacity / 4).max(1),
+            outstanding: 0,
+            waiting: None,
+        })
+    }
+
+    /// Grant the server credit according to the given fill level of the channel.
+    fn update(&mut self, len: usize, stream: &mut TcpStream) -> io::Result<()> {
+        let free = self
+            .capacity
+            .saturating_sub(len as u64)
+            .saturating_sub(self.outstanding);
+        if free >= self.threshold {
+            trace!("Granting the server credit for {free} packets");
+            encode(&Frame::Credit(free), stream)?;
+            self.outstanding += free;
+        }
+
+        let starved = self.outstanding < self.threshold;
+        match &self.waiting {
+            None if starved => {
+                trace!("Channel is full ({len} items), waiting for it to drain");
+                self.waiting = Some(Waiting {
+                    timeout: stream.read_timeout()?,
+                    last_frame: Instant::now(),
+                });
+                stream.set_read_timeout(Some(DRAIN_POLL))?;
+            }
+            Some(waiting) if !starved => {
+                trace!("Channel has drained ({len} items)");
+                stream.set_read_timeout(waiting.timeout)?;
+                self.waiting = None;
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Note that a frame carrying the given amount of packets was received.
+    fn received(&mut self, packets: usize) {
+        self.outstanding = self.outstanding.saturating_sub(packets as u64);
+        if let Some(waiting) = &mut self.waiting {
+            waiting.last_frame = Instant::now();
+        }
+    }
+
+    /// Whether the given error only means that reading timed out to check the channel, while the client waits for it
+    /// to drain and the read timeout of the stream has not elapsed yet.
+    fn polled(&self, e: &io::Error) -> bool {
+        self.waiting.as_ref().is_some_and(|waiting| {
+            matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+                && waiting
+                    .timeout
+                    .is_none_or(|timeout| waiting.last_frame.elapsed() < timeout)
+        })
+    }
+}
+
+/// Handle a [`Frame::Sequence`] announcing `position` as the position of the next packet, where `next_seq` is the one
+/// expected. Returns the gap between the two, if there is one.
+fn sequence(next_seq: &mut Option<Position>, position: Position) -> Option<Gap> {
+    let gap = match next_seq.replace(position) {
+        Some(next) if next.session != position.session => Gap::Unknown,
+        Some(next) if next.seq < position.seq => Gap::Missing {
+            from: next.seq,
+            to: position.seq,
+        },
+        _ => return None,
+    };
+
+    warn!("Server reported a gap: {gap}");
+    Some(gap)
+}
+
+/// Send the given gap to `sender`, if it can represent gaps.
+///
+/// # Errors
+/// Returns an error if the receiver hung up.
+fn report<T: ChannelItem>(
+    sender: &client_mpsc::ClientSender<T>,
+    gap: Gap,
+) -> Result<(), ClientError> {
+    match T::gap(gap) {
+        Some(item) => sender
+            .send(item)
+            .map_err(|_| ClientError::ChannelTermination),
+        None => Ok(()),
+    }
+}
+
+/// Connect to the given address and perform the hello exchange for a data connection, configuring the stream
+/// according to `config`.
+///
+/// Returns the stream, the address it is connected to and the capabilities negotiated with the server. If the server
+/// closes the connection without replying, it is assumed to predate version negotiation, and a legacy connection
+/// without any capabilities is made.
+fn connect(
+    addrs: &[SocketAddr],
+    config: &ClientConfig,
+) -> Result<(TcpStream, SocketAddr, u16), ClientError> {
+    let (mut stream, addr) = connect_any(addrs, config.connect_timeout)?;
+    config.socket.apply(&stream)?;
+    // a server which accepts but never replies must not stall the client forever
+    stream.set_read_timeout(Some(config.connect_timeout))?;
+
+    trace!("Sending hello");
+    stream.write_all(&hello())?;
+
+    let mut reply = [0; 1];
+    match stream.read_exact(&mut reply) {
+        Ok(()) => (),
+        Err(e) if is_legacy_hangup(&e) => {
+            info!("Server does not support version negotiation, falling back to legacy protocol");
+            let mut stream = TcpStream::connect_timeout(&addr, config.connect_timeout)?;
+            config.socket.apply(&stream)?;
+            stream.set_read_timeout(config.read_timeout)?;
+            stream.write_all(&[ConnectionType::Data as u8])?;
+            return Ok((stream, addr, 0));
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    match reply[0] {
+        HELLO_ACCEPT => {
+            let mut accept = [0; 3];
+            stream.read_exact(&mut accept)?;
+            stream.set_read_timeout(config.read_timeout)?;
+            Ok((stream, addr, accepted(accept)?))
+        }
+        HELLO_REJECT => {
+            let mut reason = [0; 1];
+            stream.read_exact(&mut reason)?;
+            let e = HandshakeError::Rejected(reason[0]);
+
+            error!("{e}");
+            Err(e.into())
+        }
+        other => Err(HandshakeError::InvalidReply(other).into()),
+    }
+}
+
+/// Try to connect to each of the given addresses in turn, waiting up to `timeout` for each. Returns the first stream
+/// established and its address.
+///
+/// If no address could be connected to, the error of the last attempt is returned.
+fn connect_any(addrs: &[SocketAddr], timeout: Duration) -> io::Result<(TcpStream, SocketAddr)> {
+    let mut last_error = None;
+
+    for &addr in addrs {
+        info!("Connecting to {addr}");
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                info!("Connected to {addr}");
+                return Ok((stream, addr));
+            }
+            Err(e) => {
+                warn!("Failed to connect to {addr}: {e}");
+                last_error = Some(e);
+            }
         }
     }
+
+    Err(last_error.unwrap_or_else(no_addresses))
 }
 
-/// Convert the given bytes into an [`IncomingDataPacket`] and send them via the sender.
-fn handle_packet(
-    data: [u8; 16],
-    sender: &client_mpsc::ClientSender,
-) -> Result<(), SendError<IncomingDataPacket>> {
-    sender.send(u128::from_le_bytes(data))
+/// The decoder for the frames of a data connection with the given negotiated capabilities.
+fn decoder(capabilities: u16) -> Decoder {
+    let decoder = Decoder::new();
+    if capabilities & CAP_EPOCH != 0 {
+        decoder.with_epoch_offsets()
+    } else {
+        decoder
+    }
+}
+
+/// The hello message opening a data connection.
+fn hello() -> [u8; 8] {
+    let mut hello = [0; 8];
+    hello[..4].copy_from_slice(&MAGIC);
+    hello[4] = PROTOCOL_VERSION;
+    hello[5..7].copy_from_slice(&CAPABILITIES.to_le_bytes());
+    hello[7] = ConnectionType::Data as u8;
+    hello
+}
+
+/// Whether the given error, encountered while reading the hello reply, means that the server predates version
+/// negotiation and hung up on the hello.
+fn is_legacy_hangup(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset
+    )
+}
+
+/// Parse the protocol version and capabilities following [`HELLO_ACCEPT`], returning the capabilities.
+fn accepted(accept: [u8; 3]) -> Result<u16, HandshakeError> {
+    let version = accept[0];
+    let capabilities = u16::from_le_bytes([accept[1], accept[2]]);
+
+    if version < MIN_PROTOCOL_VERSION {
+        return Err(HandshakeError::UnsupportedVersion(version));
+    }
+
+    debug!("Negotiated protocol version {version} and capabilities {capabilities:#06x}");
+    Ok(capabilities)
+}
+
+// synchronisation:
+// what is our problem?
+// SystemTime may be too inaccurate, so we want Instant instead, which, however,
+// is relative. both sides must agree on a sort of "ground zero", the shared epoch.
+
+// how do we do this?
+// 1. the server sends SIG_SYNC and marks the epoch (A_S, along with its unix time).
+// once the client reads it, it marks A_C.
+// 2. the client echoes [CTRL, SIG_SYNC] and measures how long that took (D_C).
+// once the server reads the echo, it measures the time elapsed since A_S (D_S).
+// 3. the server sends D_S and its unix time at A_S.
+// the client corrects A_C by half the round trip, A_C -= (D_S - D_C) / 2, which is
+// SyncInfo::epoch, and the difference of the unix times is SyncInfo::offset.
+
+// diagram?
+//    C        S
+//    |SIG_SYNC|
+// A_C|---<----|A_S
+//    |echo    |
+//    |--->----|D_S
+//    |D_S, A_S|
+//    |---<----|
+
+// what goes over the wire?
+// if both sides set CAP_EPOCH, the server sends every timestamp as its offset
+// from the unix time of A_S in the first round (packet - epoch_micros, wrapping
+// for packets older than the epoch), so the connect round precedes any packet,
+// including replayed ones. later rounds do not move that epoch. deltas are the
+// same either way. the Decoder adds epoch_micros back, so ClientEvents still
+// hold unix microseconds, as do legacy clients, which keep receiving plain u128s.
+// SyncInfo::to_instant then maps them onto the monotonic clock as
+// epoch + (packet - epoch_micros).
+
+/// A synchronisation round started by the server, answered by echoing its [`SIG_SYNC`] signal.
+///
+/// The local instant at which the signal was read (`A_C`) is corrected by half the round-trip time, which is
+/// the server-measured `D_S` minus the time `D_C` we took to echo the signal.
+struct SyncRound {
+    /// The local instant at which the signal was read.
+    received: Instant,
+    /// The local unix timestamp at which the signal was read.
+    received_micros: u128,
+    /// The time it took to echo the signal, `D_C`.
+    echo_delay: Duration,
+}
+
+impl SyncRound {
+    /// Start a round, right after the [`SIG_SYNC`] signal has been read.
+    fn start() -> Self {
+        Self {
+            received: Instant::now(),
+            received_micros: unix_micros(),
+            echo_delay: Duration::ZERO,
+        }
+    }
+
+    /// Mark the signal as echoed.
+    fn echoed(&mut self) {
+        self.echo_delay = self.received.elapsed();
+    }
+
+    /// Conclude the round with the reply of the server.
+    fn finish(self, server_elapsed: u128, epoch_micros: u128) -> SyncInfo {
+        let rtt = Duration::from_micros(
+            u64::try_from(server_elapsed.saturating_sub(self.echo_delay.as_micros()))
+                .unwrap_or(u64::MAX),
+        );
+        let latency = rtt / 2;
+        let local_epoch_micros = self.received_micros.saturating_sub(latency.as_micros());
+
+        let info = SyncInfo {
+            epoch: self.received.checked_sub(latency).unwrap_or(self.received),
+            epoch_micros,
+            #[expect(clippy::cast_possible_wrap)]
+            offset: epoch_micros as i128 - local_epoch_micros as i128,
+            rtt,
+        };
+        debug!(
+            "Synchronised clock: offset {}µs, rtt {:?}",
+            info.offset, info.rtt
+        );
+
+        info
+    }
 }
 
 /// A C-compatible wrapper for [`data`].
 ///
+/// Returns `0` if the server terminated the connection. Otherwise:
+///
+/// + A positive value is the OS error code of an I/O error.
+/// + `-1`: the I/O error was not constructed via `last_os_error` or `from_raw_os_error`.
+/// + `-2`: the server sent an unexpected signal or a frame which could not be decoded.
+/// + `-3`: the hello exchange with the server failed.
+/// + `-5`: the receiver hung up.
+///
 /// # Safety
 /// `sender` must be a valid pointer.
 #[cfg(feature = "interop")]
@@ -121,45 +1196,178 @@ pub unsafe extern "C" fn c_data(
     use crate::client_mpsc::ClientSender;
     use std::net::Ipv4Addr;
 
-    match data(
-        IpAddr::V4(Ipv4Addr::new(ip_a, ip_b, ip_c, ip_d)),
-        port,
+    c_data_result(data(
+        (Ipv4Addr::new(ip_a, ip_b, ip_c, ip_d), port),
         unsafe { *Box::from_raw(sender.cast::<ClientSender>()) },
-    ) {
+    ))
+}
+
+/// A C-compatible wrapper for [`data`], taking the address as a string.
+///
+/// `host` may be an IPv4 address (`127.0.0.1`), an IPv6 address (`::1`) or a hostname, each of whose addresses is
+/// tried in turn. Returns `0` if the server terminated the connection. Otherwise:
+///
+/// + A positive value is the OS error code of an I/O error.
+/// + `-1`: the I/O error was not constructed via `last_os_error` or `from_raw_os_error`.
+/// + `-2`: the server sent an unexpected signal or a frame which could not be decoded.
+/// + `-3`: the hello exchange with the server failed.
+/// + `-4`: `host` could not be resolved.
+/// + `-5`: the receiver hung up.
+///
+/// # Safety
+/// `host` must be a valid pointer to a nul-terminated string, and `sender` must be a valid pointer.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_data_host(
+    host: *const std::ffi::c_char,
+    port: u16,
+    sender: *mut (),
+) -> i32 {
+    use crate::client_mpsc::ClientSender;
+
+    let sender = unsafe { *Box::from_raw(sender.cast::<ClientSender>()) };
+    let Some(addrs) = (unsafe { crate::c_resolve(host, port) }) else {
+        return -4;
+    };
+
+    c_data_result(data(addrs.as_slice(), sender))
+}
+
+/// Convert the result of [`data`] into the return value of [`c_data`].
+#[cfg(feature = "interop")]
+fn c_data_result(result: Result<(), ClientError>) -> i32 {
+    match result {
         Ok(()) => 0,
-        Err(e) => e.raw_os_error().unwrap_or(-1),
+        Err(ClientError::IoError(e)) => e.raw_os_error().unwrap_or(-1),
+        Err(ClientError::UnexpectedSignal(_) | ClientError::Protocol(_)) => -2,
+        Err(ClientError::Handshake(_)) => -3,
+        Err(ClientError::ChannelTermination) => -5,
     }
 }
 
-// synchronisation:
-// what is our problem?
-// SystemTime may be too inaccurate, so we Instant instead, which, however,
-// is relative.
-// the packets sent will have a timestamp relative to one that both
-// server and client must agree on, a sort of "ground zero".
-// what do i want to do?
-// synchronise this "ground zero" timestamp between client and server
+#[cfg(test)]
+mod tests {
+    //! Tests of the data connection of the client.
 
-// how do we do this?
-// 1. the server will send some sort of signal (0xAA as an ex) (S1).
-// once the client receives it, it should create an Instant at now (Instant A_C)
-// once the server has sent it, it should also create an Instant at now (Instant A_S)
+    use super::{Gap, sequence};
+    use crate::codec::Position;
 
-// 2. the client will send back a signal (0xAA) (S2)
-// once the client has sent it, it should create a duration measuring how long it
-// has been since A_C (D_C)
-// once the server has received it, it should create a duration measuring how long it
-// has been since A_S (D_S)
+    /// A sequence announcement is compared with the position of the packet expected next.
+    #[test]
+    fn sequences_report_gaps() {
+        let position = |session, seq| Position { session, seq };
+        let mut next = None;
 
-// 3. server should send D_S to client
-// client should calc D_S - D_C and apply it to A_C, so:
-// A_C += D_S - D_C
+        assert_eq!(sequence(&mut next, position(1, 5)), None);
+        assert_eq!(sequence(&mut next, position(1, 5)), None);
+        assert_eq!(
+            sequence(&mut next, position(1, 8)),
+            Some(Gap::Missing { from: 5, to: 8 })
+        );
+        // packets sent twice after resuming are no gap
+        assert_eq!(sequence(&mut next, position(1, 2)), None);
+        assert_eq!(sequence(&mut next, position(2, 2)), Some(Gap::Unknown));
+        assert_eq!(next, Some(position(2, 2)));
+    }
 
-// diagram?
-//    C        S
-//    |Signal1 |
-// A_C|--->----|A_S
-//    |Signal2 |
-// B_C|---<----|B_S
-//    | D      |
-//    |---<----|
+    /// Packets arrive in order over a connection to a server, which synchronises the clocks on connect.
+    #[cfg(feature = "server")]
+    #[test]
+    fn packets_arrive_in_order() {
+        use std::sync::mpsc::channel;
+
+        use super::{ClockSync, data_with_clock};
+        use crate::{
+            client_mpsc::client_channel,
+            server::{OutgoingDataPacket, ServerConfig, spawn_server_with_config},
+        };
+
+        // the packets sent before the client connects are kept for it
+        let (supplier, packets) = channel::<OutgoingDataPacket>();
+        let server =
+            spawn_server_with_config(ServerConfig::new("127.0.0.1:0").unwrap(), packets).unwrap();
+        let addr = server.local_addrs()[0];
+
+        let clock = ClockSync::new();
+        let handle = clock.clone();
+        let (tx, rx) = client_channel(8192);
+        let client = std::thread::spawn(move || data_with_clock(addr, tx, &handle));
+
+        (0..5000).for_each(|packet| supplier.send(packet).unwrap());
+        assert!((0..5000).eq(rx.iter().take(5000)));
+        assert!(clock.info().is_some());
+
+        server.shutdown();
+        assert!(client.join().unwrap().is_ok());
+        assert!(server.join().is_ok());
+    }
+
+    /// A server which accepts connections, but drops them before sending a frame, counts as failing, so that the
+    /// client gives up on it.
+    #[test]
+    fn dropped_connections_fail() {
+        use std::{net::TcpListener, time::Duration};
+
+        use super::{ClientError, ClockSync, ConnectionState, Reconnect, data_reconnecting};
+        use crate::client_mpsc::client_channel;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || listener.incoming().for_each(drop));
+
+        let reconnect = Reconnect {
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+            max_attempts: Some(2),
+            idle_timeout: None,
+        };
+        let (tx, _rx) = client_channel(8);
+        let mut states = Vec::new();
+        let result = data_reconnecting(addr, tx, &ClockSync::new(), &reconnect, |state| {
+            states.push(state);
+        });
+
+        assert!(matches!(result, Err(ClientError::IoError(_))));
+        assert_eq!(states.last(), Some(&ConnectionState::GaveUp));
+    }
+
+    /// A frame which cannot be decoded is a protocol error, upon which the client does not reconnect.
+    #[test]
+    fn invalid_frames_are_fatal() {
+        use std::{
+            io::{Read, Write},
+            net::TcpListener,
+        };
+
+        use super::{ClientError, ClockSync, Reconnect, data_reconnecting};
+        use crate::{
+            client_mpsc::client_channel,
+            codec::DecodeError,
+            consts::{HELLO_ACCEPT, PROTOCOL_VERSION, SIG_DELTA},
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                stream.read_exact(&mut [0; 8]).unwrap();
+                stream
+                    .write_all(&[HELLO_ACCEPT, PROTOCOL_VERSION, 0, 0])
+                    .unwrap();
+                // a delta whose varint never ends
+                stream.write_all(&[SIG_DELTA]).unwrap();
+                stream.write_all(&[0xFF; 19]).unwrap();
+            }
+        });
+
+        let (tx, _rx) = client_channel(8);
+        let result = data_reconnecting(addr, tx, &ClockSync::new(), &Reconnect::default(), |_| {});
+        assert!(matches!(
+            result,
+            Err(ClientError::Protocol(DecodeError::VarintTooLong))
+        ));
+    }
+}
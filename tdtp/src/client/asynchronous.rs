@@ -0,0 +1,406 @@
+//! Asynchronous client, built on [`tokio`].
+//!
+//! Instead of sending packets over a channel, a data connection yields them as a [`PacketStream`], or as an
+//! [`EventStream`] which also reports gaps in the packets and may carry detector metadata.
+
+use std::{
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    pin::Pin,
+    sync::atomic::Ordering,
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, StreamExt, stream};
+use log::{debug, error, info, trace, warn};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, ToSocketAddrs, lookup_host},
+    time,
+};
+
+use super::{
+    CONNECT_TIMEOUT, ClientError, ClientEvent, ClockSync, HandshakeError, IncomingDataPacket,
+    SyncRound, accepted, decoder, hello, is_legacy_hangup, sequence,
+};
+use crate::{
+    codec::{Frame, FrameBuffer, Packet, Position, encode},
+    consts::{
+        CAP_CREDIT, CAP_RESUME, CAP_SYNC, CTRL, ConnectionType, HELLO_ACCEPT, HELLO_REJECT,
+        SIG_SYNC,
+    },
+    no_addresses,
+};
+
+/// How many bytes are read from the stream at once.
+const READ_SIZE: usize = 4096;
+
+/// How many packets the server may send ahead of those a data stream yielded, if it supports credit-based flow control
+/// ([`SIG_CREDIT`](crate::consts::SIG_CREDIT)). Credit is granted once half of it is used up.
+pub const CREDIT_WINDOW: u64 = 8192;
+
+/// The packets received over a data connection.
+///
+/// The stream ends once the server terminates the connection. If an error is encountered, it is yielded as the last
+/// item. Dropping the stream closes the connection.
+pub struct PacketStream(
+    Pin<Box<dyn Stream<Item = Result<IncomingDataPacket, ClientError>> + Send>>,
+);
+
+impl Stream for PacketStream {
+    type Item = Result<IncomingDataPacket, ClientError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.as_mut().poll_next(cx)
+    }
+}
+
+/// The events of a data connection, which are the packets received and the gaps between them.
+///
+/// Like a [`PacketStream`], the stream ends once the server terminates the connection, and an error is yielded as the
+/// last item. Packets are yielded as timestamps, or as [`Packet`]s by a stream from [`events_with_metadata`].
+pub struct EventStream<P = IncomingDataPacket>(
+    Pin<Box<dyn Stream<Item = Result<ClientEvent<P>, ClientError>> + Send>>,
+);
+
+impl<P> Stream for EventStream<P> {
+    type Item = Result<ClientEvent<P>, ClientError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.as_mut().poll_next(cx)
+    }
+}
+
+/// Initiate a data connection to the given address. This is the asynchronous equivalent of [`super::data`].
+///
+/// If the server supports it, it sends at most [`CREDIT_WINDOW`] packets ahead of those the stream yielded, and holds
+/// back the others while the stream is not polled.
+///
+/// # Errors
+/// Returns an I/O error or a protocol error if the connection could not be established. Errors encountered once
+/// connected are yielded by the returned stream.
+///
+/// # Example
+/// ```no_run
+/// use tdtp::client::asynchronous::data;
+/// use futures_util::StreamExt;
+///
+/// # async fn example() -> Result<(), tdtp::client::ClientError> {
+/// let mut packets = data("localhost:8000").await?;
+///
+/// while let Some(packet) = packets.next().await {
+///     println!("Got a packet: {:?}", packet?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn data(addr: impl ToSocketAddrs) -> Result<PacketStream, ClientError> {
+    data_with_clock(addr, ClockSync::new()).await
+}
+
+/// Like [`data`], but publishes the results of clock synchronisation rounds to the given [`ClockSync`] handle.
+///
+/// The server synchronises clocks once on connect; further rounds can be requested with [`ClockSync::request`].
+///
+/// # Errors
+/// See [`data`].
+pub async fn data_with_clock(
+    addr: impl ToSocketAddrs,
+    clock: ClockSync,
+) -> Result<PacketStream, ClientError> {
+    let events = events_with_metadata(addr, clock).await?;
+
+    Ok(PacketStream(Box::pin(events.0.filter_map(
+        |event| async move {
+            match event {
+                Ok(ClientEvent::Packet(packet)) => Some(Ok(packet.timestamp)),
+                Ok(ClientEvent::Gap(_)) => None,
+                Err(e) => Some(Err(e)),
+            }
+        },
+    ))))
+}
+
+/// Like [`data`], but yields gaps in the packets as [`ClientEvent::Gap`], if the server supports sequence numbers.
+///
+/// # Errors
+/// See [`data`].
+///
+/// # Example
+/// ```no_run
+/// use tdtp::client::{ClientEvent, asynchronous::events};
+/// use futures_util::StreamExt;
+///
+/// # async fn example() -> Result<(), tdtp::client::ClientError> {
+/// let mut events = events("localhost:8000").await?;
+///
+/// while let Some(event) = events.next().await {
+///     match event? {
+///         ClientEvent::Packet(packet) => println!("Got a packet: {packet}"),
+///         ClientEvent::Gap(gap) => println!("Lost packets: {gap}"),
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn events(addr: impl ToSocketAddrs) -> Result<EventStream, ClientError> {
+    events_with_clock(addr, ClockSync::new()).await
+}
+
+/// Like [`events`], but publishes the results of clock synchronisation rounds to the given [`ClockSync`] handle. See
+/// [`data_with_clock`].
+///
+/// # Errors
+/// See [`data`].
+pub async fn events_with_clock(
+    addr: impl ToSocketAddrs,
+    clock: ClockSync,
+) -> Result<EventStream, ClientError> {
+    let events = events_with_metadata(addr, clock).await?;
+
+    Ok(EventStream(Box::pin(
+        events
+            .0
+            .map(|event| event.map(|event| event.map(u128::from))),
+    )))
+}
+
+/// Like [`events_with_clock`], but yields the packets along with the detector metadata the server sent.
+///
+/// # Errors
+/// See [`data`].
+pub async fn events_with_metadata(
+    addr: impl ToSocketAddrs,
+    clock: ClockSync,
+) -> Result<EventStream<Packet>, ClientError> {
+    let addrs: Vec<SocketAddr> = lookup_host(addr).await?.collect();
+    let (stream, capabilities) = connect(&addrs).await?;
+    let sync = capabilities & CAP_SYNC != 0;
+
+    let mut connection = Connection {
+        stream,
+        clock,
+        frames: FrameBuffer::with_decoder(decoder(capabilities)),
+        packets: Vec::new(),
+        last: None,
+        next: 0,
+        round: None,
+        sync,
+        awaiting_sync: sync,
+        next_seq: None,
+        credit: (capabilities & CAP_CREDIT != 0).then_some(0),
+    };
+
+    if capabilities & CAP_RESUME != 0 {
+        connection.send(&Frame::Resume(None)).await?;
+    }
+
+    Ok(EventStream(Box::pin(stream::unfold(
+        Some(connection),
+        |connection| async move {
+            let mut connection = connection?;
+
+            match connection.next().await {
+                Ok(Some(event)) => Some((Ok(event), Some(connection))),
+                Ok(None) => None,
+                Err(e) => Some((Err(e), None)),
+            }
+        },
+    ))))
+}
+
+/// The state of an established data connection.
+struct Connection {
+    /// The stream to the server.
+    stream: TcpStream,
+    /// The handle to publish synchronisation results to.
+    clock: ClockSync,
+    /// The bytes read, but not decoded yet.
+    frames: FrameBuffer,
+    /// The packets decoded from the current frame.
+    packets: Vec<Packet>,
+    /// The last timestamp received, which is the base of the next delta.
+    last: Option<IncomingDataPacket>,
+    /// The index of the next packet in `packets` to yield.
+    next: usize,
+    /// The synchronisation round which is waiting for the reply of the server.
+    round: Option<SyncRound>,
+    /// Whether clock synchronisation was negotiated.
+    sync: bool,
+    /// Whether a synchronisation round is in progress, so that no further round may be requested.
+    awaiting_sync: bool,
+    /// The position of the next packet, if the server sent one.
+    next_seq: Option<Position>,
+    /// The credit granted to the server, but not used up yet, if credit-based flow control was negotiated.
+    credit: Option<u64>,
+}
+
+impl Connection {
+    /// Wait for the next event, or `None` if the server terminated the connection.
+    async fn next(&mut self) -> Result<Option<ClientEvent<Packet>>, ClientError> {
+        loop {
+            if let Some(&packet) = self.packets.get(self.next) {
+                self.next += 1;
+                return Ok(Some(ClientEvent::Packet(packet)));
+            }
+
+            self.packets.clear();
+            self.next = 0;
+
+            if self.sync
+                && !self.awaiting_sync
+                && self.clock.requested.swap(false, Ordering::Relaxed)
+            {
+                debug!("Requesting clock synchronisation");
+                self.send(&Frame::Control(SIG_SYNC)).await?;
+                self.awaiting_sync = true;
+            }
+
+            if let Some(outstanding) = self.credit
+                && outstanding <= CREDIT_WINDOW / 2
+            {
+                self.send(&Frame::Credit(CREDIT_WINDOW - outstanding))
+                    .await?;
+                self.credit = Some(CREDIT_WINDOW);
+            }
+
+            let frame = self
+                .next_frame()
+                .await
+                .inspect_err(|v| error!("Failed to read frame: {v}"))?;
+
+            match frame {
+                Frame::Sync => {
+                    let mut start = SyncRound::start();
+                    self.send(&Frame::Control(SIG_SYNC)).await?;
+                    start.echoed();
+                    self.round = Some(start);
+                }
+                Frame::SyncReply {
+                    elapsed,
+                    epoch_micros,
+                } => {
+                    if let Some(round) = self.round.take() {
+                        self.clock.update(round.finish(elapsed, epoch_micros));
+                    }
+                    self.awaiting_sync = false;
+                }
+                Frame::Sequence(position) => {
+                    if let Some(gap) = sequence(&mut self.next_seq, position) {
+                        return Ok(Some(ClientEvent::Gap(gap)));
+                    }
+                }
+                Frame::Exit => {
+                    info!("Server terminated connection, exiting");
+                    return Ok(None);
+                }
+                Frame::Control(_) => {
+                    error!("Server sent a control signal, exiting");
+                    return Err(ClientError::UnexpectedSignal(CTRL));
+                }
+                frame => frame
+                    .events_into(&mut self.last, &mut self.packets)
+                    .inspect_err(|v| error!("Failed to decode packets: {v}"))?,
+            }
+
+            if let Some(next) = &mut self.next_seq {
+                next.seq += self.packets.len() as u64;
+            }
+            if let Some(outstanding) = &mut self.credit {
+                *outstanding = outstanding.saturating_sub(self.packets.len() as u64);
+            }
+        }
+    }
+
+    /// Encode the given frame and write it to the stream.
+    async fn send(&mut self, frame: &Frame) -> io::Result<()> {
+        let mut data = Vec::new();
+        encode(frame, &mut data)?;
+        self.stream.write_all(&data).await
+    }
+
+    /// Read from the stream until a complete frame is buffered, then decode and remove that frame.
+    async fn next_frame(&mut self) -> Result<Frame, ClientError> {
+        loop {
+            if let Some(frame) = self.frames.next_frame()? {
+                return Ok(frame);
+            }
+
+            trace!("Reading frame");
+            let buf = self.frames.buffer_mut();
+            buf.reserve(READ_SIZE);
+            if self.stream.read_buf(buf).await? == 0 {
+                return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+            }
+        }
+    }
+}
+
+/// Connect to the given address and perform the hello exchange for a data connection. See [`super::connect`].
+async fn connect(addrs: &[SocketAddr]) -> Result<(TcpStream, u16), ClientError> {
+    let (mut stream, addr) = connect_any(addrs).await?;
+
+    trace!("Sending hello");
+    stream.write_all(&hello()).await?;
+
+    // a server which accepts but never replies must not stall the client forever
+    let reply = match in_time(stream.read_u8()).await {
+        Ok(reply) => reply,
+        Err(e) if is_legacy_hangup(&e) => {
+            info!("Server does not support version negotiation, falling back to legacy protocol");
+            let mut stream = connect_timeout(addr).await?;
+            stream.write_all(&[ConnectionType::Data as u8]).await?;
+            return Ok((stream, 0));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    match reply {
+        HELLO_ACCEPT => {
+            let mut accept = [0; 3];
+            in_time(stream.read_exact(&mut accept)).await?;
+            Ok((stream, accepted(accept)?))
+        }
+        HELLO_REJECT => {
+            let e = HandshakeError::Rejected(in_time(stream.read_u8()).await?);
+
+            error!("{e}");
+            Err(e.into())
+        }
+        other => Err(HandshakeError::InvalidReply(other).into()),
+    }
+}
+
+/// Try to connect to each of the given addresses in turn. See [`super::connect_any`].
+async fn connect_any(addrs: &[SocketAddr]) -> io::Result<(TcpStream, SocketAddr)> {
+    let mut last_error = None;
+
+    for &addr in addrs {
+        info!("Connecting to {addr}");
+        match connect_timeout(addr).await {
+            Ok(stream) => {
+                info!("Connected to {addr}");
+                return Ok((stream, addr));
+            }
+            Err(e) => {
+                warn!("Failed to connect to {addr}: {e}");
+                last_error = Some(e);
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(no_addresses))
+}
+
+/// Connect to the given address, failing if no connection is established within [`CONNECT_TIMEOUT`].
+async fn connect_timeout(addr: SocketAddr) -> io::Result<TcpStream> {
+    in_time(TcpStream::connect(addr)).await
+}
+
+/// Wait for the given I/O operation of establishing a connection, failing if it does not complete within
+/// [`CONNECT_TIMEOUT`].
+async fn in_time<T>(operation: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    time::timeout(CONNECT_TIMEOUT, operation)
+        .await
+        .unwrap_or_else(|_| Err(io::Error::from(ErrorKind::TimedOut)))
+}
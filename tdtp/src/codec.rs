@@ -0,0 +1,1254 @@
+//! Encoding and decoding of protocol frames, independent of any socket.
+//!
+//! A [`Frame`] is written with [`encode`], and read back with a [`Decoder`], which works on byte slices and can thus
+//! be fed from any transport. The server decodes the frames of clients with a [`ControlDecoder`], which rejects any
+//! other frame. A [`FrameBuffer`] keeps the bytes of incomplete frames for transports which deliver bytes in arbitrary
+//! chunks, and [`FrameReader`] reads into one from a blocking reader.
+//!
+//! # Example
+//! ```
+//! use tdtp::codec::{Decoder, Frame, encode};
+//!
+//! let mut buf = Vec::new();
+//! encode(&Frame::Packet(42), &mut buf).unwrap();
+//! encode(&Frame::Empty, &mut buf).unwrap();
+//!
+//! let mut decoder = Decoder::new();
+//! // a frame is only decoded once it is complete
+//! assert_eq!(decoder.decode(&buf[..5]).unwrap(), None);
+//!
+//! let (frame, len) = decoder.decode(&buf).unwrap().unwrap();
+//! assert_eq!(frame, Frame::Packet(42));
+//! assert_eq!(decoder.decode(&buf[len..]).unwrap(), Some((Frame::Empty, 1)));
+//! ```
+
+use std::{
+    fmt::Display,
+    io::{self, ErrorKind, Read, Write},
+};
+
+use crate::consts::{
+    CTRL, EMP, EVENT_VERSION, FIELD_CHANNEL, FIELD_PULSE_WIDTH, FIELD_TICK, SIG_BATCH, SIG_CREDIT,
+    SIG_DELTA, SIG_DELTA_BATCH, SIG_EVENT, SIG_EXIT, SIG_PACKET, SIG_RESUME, SIG_SEQUENCE,
+    SIG_SYNC,
+};
+
+/// How many bytes a [`FrameReader`] reads at once.
+const READ_SIZE: usize = 4096;
+
+/// The maximum length of the body of a [`SIG_EVENT`] frame, so that a corrupt length is not waited for forever.
+const MAX_EVENT_LEN: usize = 255;
+
+/// A single protocol frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// An [`EMP`] heartbeat.
+    Empty,
+    /// A single timestamp ([`SIG_PACKET`]).
+    Packet(u128),
+    /// Multiple timestamps ([`SIG_BATCH`]).
+    Batch(Vec<u128>),
+    /// A single delta to the previous timestamp ([`SIG_DELTA`]).
+    Delta(u128),
+    /// Multiple deltas, each to the timestamp preceding it ([`SIG_DELTA_BATCH`]).
+    DeltaBatch(Vec<u128>),
+    /// The start of a clock synchronisation round ([`SIG_SYNC`]).
+    Sync,
+    /// The reply concluding a clock synchronisation round.
+    SyncReply {
+        /// The time `D_S` the server measured until the client echoed the round, in microseconds.
+        elapsed: u128,
+        /// The server's unix timestamp at the start of the round, in microseconds.
+        epoch_micros: u128,
+    },
+    /// The position of the next packet in the numbering of the server ([`SIG_SEQUENCE`]).
+    Sequence(Position),
+    /// The termination of the connection ([`SIG_EXIT`]).
+    Exit,
+    /// A control signal sent by the client, prefixed with [`CTRL`].
+    Control(u8),
+    /// The resume request of a client ([`SIG_RESUME`]), holding the position of the next packet it expects, or `None`
+    /// for a new connection.
+    Resume(Option<Position>),
+    /// A timestamp along with detector metadata ([`SIG_EVENT`]).
+    Event(Packet),
+    /// The amount of further packets a client accepts ([`SIG_CREDIT`]).
+    Credit(u64),
+}
+
+/// The position of a packet in the numbering of a server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    /// The randomly chosen ID of the server session which numbered the packet. It changes when the server restarts.
+    pub session: u64,
+    /// The sequence number of the packet, which is one higher than that of the packet preceding it.
+    pub seq: u64,
+}
+
+/// A detector event, i.e., the timestamp of a packet along with the metadata the server knows about it.
+///
+/// Fields may be added in the future, so a packet is created with [`Packet::new`] or converted from a timestamp, and
+/// its fields are set with the `with_*` methods. Converting a packet into a `u128` yields its timestamp.
+///
+/// # Example
+/// ```
+/// use tdtp::codec::Packet;
+///
+/// let packet = Packet::new(1_700_000_000_000_000).with_channel(2).with_tick(4242);
+/// assert_eq!(packet.channel, Some(2));
+/// assert_eq!(packet.pulse_width, None);
+/// assert_eq!(u128::from(packet), 1_700_000_000_000_000);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Packet {
+    /// The amount of microseconds elapsed since the unix epoch.
+    pub timestamp: u128,
+    /// The ID of the channel (detector) which registered the event ([`FIELD_CHANNEL`]).
+    pub channel: Option<u16>,
+    /// The width of the detector pulse in microseconds ([`FIELD_PULSE_WIDTH`]).
+    pub pulse_width: Option<u32>,
+    /// The GPIO tick of the event ([`FIELD_TICK`]).
+    pub tick: Option<u32>,
+}
+
+impl Packet {
+    /// Create a packet holding only the given timestamp.
+    #[must_use]
+    pub const fn new(timestamp: u128) -> Self {
+        Self {
+            timestamp,
+            channel: None,
+            pulse_width: None,
+            tick: None,
+        }
+    }
+
+    /// Set the channel (detector) ID of this packet.
+    #[must_use]
+    pub const fn with_channel(mut self, channel: u16) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    /// Set the pulse width of this packet, in microseconds.
+    #[must_use]
+    pub const fn with_pulse_width(mut self, pulse_width: u32) -> Self {
+        self.pulse_width = Some(pulse_width);
+        self
+    }
+
+    /// Set the GPIO tick of this packet.
+    #[must_use]
+    pub const fn with_tick(mut self, tick: u32) -> Self {
+        self.tick = Some(tick);
+        self
+    }
+
+    /// The field flags of this packet, which tell the fields set besides the timestamp.
+    #[must_use]
+    pub const fn fields(&self) -> u8 {
+        let mut fields = 0;
+        if self.channel.is_some() {
+            fields |= FIELD_CHANNEL;
+        }
+        if self.pulse_width.is_some() {
+            fields |= FIELD_PULSE_WIDTH;
+        }
+        if self.tick.is_some() {
+            fields |= FIELD_TICK;
+        }
+        fields
+    }
+}
+
+impl From<u128> for Packet {
+    fn from(timestamp: u128) -> Self {
+        Self::new(timestamp)
+    }
+}
+
+impl From<Packet> for u128 {
+    fn from(packet: Packet) -> Self {
+        packet.timestamp
+    }
+}
+
+impl Frame {
+    /// Append the timestamps carried by this frame to `packets`.
+    ///
+    /// Deltas are resolved against `last`, which is updated to the last timestamp of the frame. Frames which carry no
+    /// timestamps leave both untouched.
+    ///
+    /// # Errors
+    /// Returns an error if this frame holds deltas, but there is no previous timestamp, or if a delta overflows.
+    ///
+    /// # Example
+    /// ```
+    /// use tdtp::codec::Frame;
+    ///
+    /// let mut last = None;
+    /// let mut packets = Vec::new();
+    ///
+    /// Frame::Packet(100).packets_into(&mut last, &mut packets).unwrap();
+    /// Frame::DeltaBatch(vec![5, 0, 20]).packets_into(&mut last, &mut packets).unwrap();
+    ///
+    /// assert_eq!(packets, [100, 105, 105, 125]);
+    /// assert_eq!(last, Some(125));
+    /// ```
+    pub fn packets_into(
+        &self,
+        last: &mut Option<u128>,
+        packets: &mut Vec<u128>,
+    ) -> Result<(), DecodeError> {
+        self.collect_into(last, packets)
+    }
+
+    /// Like [`Frame::packets_into`], but appends the packets along with their metadata, which only [`Frame::Event`]
+    /// carries.
+    ///
+    /// # Errors
+    /// See [`Frame::packets_into`].
+    ///
+    /// # Example
+    /// ```
+    /// use tdtp::codec::{Decoder, Frame, Packet, encode};
+    ///
+    /// let mut buf = Vec::new();
+    /// encode(&Frame::Event(Packet::new(100).with_channel(3)), &mut buf).unwrap();
+    /// encode(&Frame::Delta(5), &mut buf).unwrap();
+    ///
+    /// let mut decoder = Decoder::new();
+    /// let (event, len) = decoder.decode(&buf).unwrap().unwrap();
+    /// let (delta, _) = decoder.decode(&buf[len..]).unwrap().unwrap();
+    ///
+    /// let mut last = None;
+    /// let mut packets = Vec::new();
+    /// event.events_into(&mut last, &mut packets).unwrap();
+    /// delta.events_into(&mut last, &mut packets).unwrap();
+    ///
+    /// assert_eq!(packets, [Packet::new(100).with_channel(3), Packet::new(105)]);
+    /// ```
+    pub fn events_into(
+        &self,
+        last: &mut Option<u128>,
+        packets: &mut Vec<Packet>,
+    ) -> Result<(), DecodeError> {
+        self.collect_into(last, packets)
+    }
+
+    /// Append the packets carried by this frame to `out`, converted to the desired type. See [`Frame::packets_into`].
+    fn collect_into<P: From<Packet>>(
+        &self,
+        last: &mut Option<u128>,
+        out: &mut Vec<P>,
+    ) -> Result<(), DecodeError> {
+        // the timestamp of the last packet appended, if any
+        let timestamp = match self {
+            Self::Packet(packet) => {
+                out.push(Packet::new(*packet).into());
+                Some(*packet)
+            }
+            Self::Batch(batch) => {
+                out.extend(batch.iter().map(|&packet| Packet::new(packet).into()));
+                batch.last().copied()
+            }
+            Self::Delta(delta) => {
+                let timestamp = resolve(*last, *delta, SIG_DELTA)?;
+                out.push(Packet::new(timestamp).into());
+                Some(timestamp)
+            }
+            Self::DeltaBatch(deltas) => {
+                let start = out.len();
+                out.reserve(deltas.len());
+
+                let mut base = *last;
+                for &delta in deltas {
+                    // a frame is appended either completely or not at all
+                    let timestamp = resolve(base, delta, SIG_DELTA_BATCH)
+                        .inspect_err(|_| out.truncate(start))?;
+                    out.push(Packet::new(timestamp).into());
+                    base = Some(timestamp);
+                }
+                base
+            }
+            Self::Event(packet) => {
+                out.push((*packet).into());
+                Some(packet.timestamp)
+            }
+            _ => return Ok(()),
+        };
+
+        *last = timestamp.or(*last);
+        Ok(())
+    }
+}
+
+/// Add `delta` to `base`, the base of a frame with the given signal.
+fn resolve(base: Option<u128>, delta: u128, sig: u8) -> Result<u128, DecodeError> {
+    base.ok_or(DecodeError::MissingBase(sig))?
+        .checked_add(delta)
+        .ok_or(DecodeError::DeltaOverflow)
+}
+
+/// An error encountered while decoding frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The signal does not start any known frame.
+    UnexpectedSignal(u8),
+    /// A varint does not fit into a `u128`.
+    VarintTooLong,
+    /// The frame with the given signal holds deltas, but no timestamp preceded it.
+    MissingBase(u8),
+    /// Adding a delta to the previous timestamp overflowed.
+    DeltaOverflow,
+    /// An event body has a version this crate does not understand.
+    UnsupportedEventVersion(u8),
+    /// An event body is too long, or ends before its fields do.
+    MalformedEvent,
+    /// The frame with the given signal holds an offset from the epoch, but no synchronisation reply preceded it.
+    MissingEpoch(u8),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedSignal(sig) => write!(f, "Unexpected signal {sig:#04x}"),
+            Self::VarintTooLong => write!(f, "varint is too long"),
+            Self::MissingBase(sig) => write!(f, "delta frame {sig:#04x} without a base timestamp"),
+            Self::DeltaOverflow => write!(f, "timestamp delta overflowed"),
+            Self::UnsupportedEventVersion(version) => {
+                write!(f, "unsupported event version {version}")
+            }
+            Self::MalformedEvent => write!(f, "malformed event"),
+            Self::MissingEpoch(sig) => write!(f, "frame {sig:#04x} without an epoch"),
+        }
+    }
+}
+
+impl From<DecodeError> for io::Error {
+    fn from(value: DecodeError) -> Self {
+        io::Error::new(ErrorKind::InvalidData, value.to_string())
+    }
+}
+
+/// Write the given frame into this sink.
+///
+/// # Errors
+/// Returns an I/O error if writing fails, or if a batch holds more than `u16::MAX` entries.
+pub fn encode(frame: &Frame, sink: &mut impl Write) -> io::Result<()> {
+    let mut data = Vec::new();
+
+    match frame {
+        Frame::Empty => data.push(EMP),
+        Frame::Packet(packet) => {
+            data.push(SIG_PACKET);
+            data.extend_from_slice(&packet.to_le_bytes());
+        }
+        Frame::Batch(packets) => {
+            data.reserve(3 + packets.len() * 16);
+            data.push(SIG_BATCH);
+            data.extend_from_slice(&batch_len(packets)?.to_le_bytes());
+            for packet in packets {
+                data.extend_from_slice(&packet.to_le_bytes());
+            }
+        }
+        Frame::Delta(delta) => {
+            data.push(SIG_DELTA);
+            write_varint(*delta, &mut data);
+        }
+        Frame::DeltaBatch(deltas) => {
+            data.push(SIG_DELTA_BATCH);
+            data.extend_from_slice(&batch_len(deltas)?.to_le_bytes());
+            for &delta in deltas {
+                write_varint(delta, &mut data);
+            }
+        }
+        Frame::Sync => data.push(SIG_SYNC),
+        Frame::SyncReply {
+            elapsed,
+            epoch_micros,
+        } => {
+            data.extend_from_slice(&elapsed.to_le_bytes());
+            data.extend_from_slice(&epoch_micros.to_le_bytes());
+        }
+        Frame::Sequence(position) => {
+            data.push(SIG_SEQUENCE);
+            data.extend_from_slice(&position.session.to_le_bytes());
+            write_varint(u128::from(position.seq), &mut data);
+        }
+        Frame::Exit => data.push(SIG_EXIT),
+        Frame::Control(sig) => data.extend_from_slice(&[CTRL, *sig]),
+        Frame::Resume(None) => data.extend_from_slice(&[CTRL, SIG_RESUME, 0]),
+        Frame::Resume(Some(position)) => {
+            data.extend_from_slice(&[CTRL, SIG_RESUME]);
+            write_varint(u128::from(position.seq) + 1, &mut data);
+            data.extend_from_slice(&position.session.to_le_bytes());
+        }
+        Frame::Credit(amount) => {
+            data.extend_from_slice(&[CTRL, SIG_CREDIT]);
+            write_varint(u128::from(*amount), &mut data);
+        }
+        Frame::Event(packet) => {
+            let mut body = vec![EVENT_VERSION, packet.fields()];
+            body.extend_from_slice(&packet.timestamp.to_le_bytes());
+            if let Some(channel) = packet.channel {
+                body.extend_from_slice(&channel.to_le_bytes());
+            }
+            if let Some(pulse_width) = packet.pulse_width {
+                write_varint(u128::from(pulse_width), &mut body);
+            }
+            if let Some(tick) = packet.tick {
+                body.extend_from_slice(&tick.to_le_bytes());
+            }
+
+            data.push(SIG_EVENT);
+            write_varint(body.len() as u128, &mut data);
+            data.extend_from_slice(&body);
+        }
+    }
+
+    sink.write_all(&data)
+}
+
+/// The length of the given batch as written in a frame.
+fn batch_len(batch: &[u128]) -> io::Result<u16> {
+    u16::try_from(batch.len())
+        .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "batch is too long"))
+}
+
+/// Append the given value to `buf` as an unsigned LEB128 varint.
+fn write_varint(mut value: u128, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        #[expect(clippy::cast_possible_truncation)]
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    #[expect(clippy::cast_possible_truncation)]
+    buf.push(value as u8);
+}
+
+/// An incremental decoder for frames in either direction.
+///
+/// The decoder does no I/O: it is handed the bytes received so far and decodes the frame at their start once it is
+/// complete. It keeps track of whether a [`Frame::Sync`] was decoded, since its reply has no signal and can only be
+/// recognised by following it, and of the epoch which timestamps are relative to if [`CAP_EPOCH`](crate::consts::CAP_EPOCH) was negotiated.
+#[derive(Debug, Clone, Default)]
+pub struct Decoder {
+    /// Whether a [`SIG_SYNC`] was decoded, so that the next frame is its reply.
+    sync_reply: bool,
+    /// Whether timestamps are offsets from the epoch.
+    offsets: bool,
+    /// The unix timestamp of the epoch, once the first synchronisation reply was decoded.
+    epoch: Option<u128>,
+}
+
+impl Decoder {
+    /// Create a new decoder.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode the timestamps of [`Frame::Packet`], [`Frame::Batch`] and [`Frame::Event`] as offsets from the epoch of
+    /// the first [`Frame::SyncReply`], as sent if [`CAP_EPOCH`](crate::consts::CAP_EPOCH) was negotiated. They are converted back into unix
+    /// timestamps, so that the decoded frames are the same as without offsets.
+    ///
+    /// # Example
+    /// ```
+    /// use tdtp::codec::{Decoder, Frame, encode};
+    ///
+    /// let mut buf = Vec::new();
+    /// encode(&Frame::Sync, &mut buf).unwrap();
+    /// encode(&Frame::SyncReply { elapsed: 10, epoch_micros: 1_000 }, &mut buf).unwrap();
+    /// encode(&Frame::Packet(25), &mut buf).unwrap();
+    ///
+    /// let mut decoder = Decoder::new().with_epoch_offsets();
+    /// let mut pos = 0;
+    /// let mut frames = Vec::new();
+    /// while let Some((frame, len)) = decoder.decode(&buf[pos..]).unwrap() {
+    ///     frames.push(frame);
+    ///     pos += len;
+    /// }
+    /// assert_eq!(frames[2], Frame::Packet(1_025));
+    /// ```
+    #[must_use]
+    pub fn with_epoch_offsets(mut self) -> Self {
+        self.offsets = true;
+        self
+    }
+
+    /// Decode the frame at the start of `buf`.
+    ///
+    /// Returns the frame along with its length in bytes, or `None` if `buf` does not hold a complete frame yet, in
+    /// which case it should be called again once more bytes are available.
+    ///
+    /// # Errors
+    /// Returns an error if `buf` does not start with a valid frame.
+    pub fn decode(&mut self, buf: &[u8]) -> Result<Option<(Frame, usize)>, DecodeError> {
+        if self.sync_reply {
+            let Some(reply) = buf.get(..32) else {
+                return Ok(None);
+            };
+
+            self.sync_reply = false;
+            let epoch_micros = read_u128(&reply[16..]);
+            if self.offsets {
+                self.epoch.get_or_insert(epoch_micros);
+            }
+
+            let frame = Frame::SyncReply {
+                elapsed: read_u128(&reply[..16]),
+                epoch_micros,
+            };
+            return Ok(Some((frame, 32)));
+        }
+
+        let Some((&sig, rest)) = buf.split_first() else {
+            return Ok(None);
+        };
+
+        let frame = match sig {
+            EMP => (Frame::Empty, 1),
+            SIG_PACKET => match rest.get(..16) {
+                Some(data) => (Frame::Packet(read_u128(data)), 17),
+                None => return Ok(None),
+            },
+            SIG_BATCH => {
+                let Some(count) = read_batch_len(rest) else {
+                    return Ok(None);
+                };
+                let Some(data) = rest.get(2..2 + count * 16) else {
+                    return Ok(None);
+                };
+                let batch = data.chunks_exact(16).map(read_u128).collect();
+                (Frame::Batch(batch), 3 + data.len())
+            }
+            SIG_DELTA => match read_varint(rest)? {
+                Some((delta, len)) => (Frame::Delta(delta), 1 + len),
+                None => return Ok(None),
+            },
+            SIG_DELTA_BATCH => {
+                let Some(count) = read_batch_len(rest) else {
+                    return Ok(None);
+                };
+                let mut deltas = Vec::with_capacity(count);
+                let mut len = 3;
+
+                for _ in 0..count {
+                    let Some((delta, varint_len)) = read_varint(&buf[len..])? else {
+                        return Ok(None);
+                    };
+                    deltas.push(delta);
+                    len += varint_len;
+                }
+
+                (Frame::DeltaBatch(deltas), len)
+            }
+            SIG_SYNC => {
+                self.sync_reply = true;
+                (Frame::Sync, 1)
+            }
+            SIG_SEQUENCE => {
+                let Some(session) = rest.get(..8) else {
+                    return Ok(None);
+                };
+                let Some((seq, len)) = read_sequence(&rest[8..])? else {
+                    return Ok(None);
+                };
+
+                let position = Position {
+                    session: read_u64(session),
+                    seq,
+                };
+                (Frame::Sequence(position), 9 + len)
+            }
+            SIG_EVENT => match read_event(rest)? {
+                Some((packet, len)) => (Frame::Event(packet), 1 + len),
+                None => return Ok(None),
+            },
+            SIG_EXIT => (Frame::Exit, 1),
+            CTRL => return read_control(rest),
+            other => return Err(DecodeError::UnexpectedSignal(other)),
+        };
+
+        let (frame, len) = frame;
+        Ok(Some((self.restore(frame)?, len)))
+    }
+
+    /// Convert the offsets held by the given frame back into unix timestamps, if offsets were negotiated.
+    fn restore(&self, frame: Frame) -> Result<Frame, DecodeError> {
+        if !self.offsets {
+            return Ok(frame);
+        }
+
+        let epoch = |sig| self.epoch.ok_or(DecodeError::MissingEpoch(sig));
+        Ok(match frame {
+            Frame::Packet(offset) => Frame::Packet(epoch(SIG_PACKET)?.wrapping_add(offset)),
+            Frame::Batch(offsets) => {
+                let epoch = epoch(SIG_BATCH)?;
+                Frame::Batch(
+                    offsets
+                        .into_iter()
+                        .map(|offset| epoch.wrapping_add(offset))
+                        .collect(),
+                )
+            }
+            Frame::Event(packet) => Frame::Event(Packet {
+                timestamp: epoch(SIG_EVENT)?.wrapping_add(packet.timestamp),
+                ..packet
+            }),
+            frame => frame,
+        })
+    }
+}
+
+/// An incremental decoder for the frames a client sends to the server.
+///
+/// Unlike [`Decoder`], it only accepts the few frames a client sends: a [`CTRL`] followed by [`SIG_EXIT`],
+/// [`SIG_SYNC`], [`SIG_CREDIT`] or [`SIG_RESUME`], and a bare [`SIG_EXIT`] as sent by older clients. Any other byte is
+/// rejected on its own, so that stray bytes cannot make the decoder wait for the body of a frame the client never
+/// sends, and swallow the frames following them.
+///
+/// # Example
+/// ```
+/// use tdtp::{codec::{ControlDecoder, DecodeError, Frame}, consts::{CTRL, SIG_EXIT, SIG_SYNC}};
+///
+/// let mut decoder = ControlDecoder::new();
+/// assert_eq!(decoder.decode(&[CTRL, SIG_EXIT]).unwrap(), Some((Frame::Control(SIG_EXIT), 2)));
+/// // a sync signal is only ever sent after a CTRL by the client, so it has no reply following it
+/// assert_eq!(decoder.decode(&[SIG_SYNC, CTRL]), Err(DecodeError::UnexpectedSignal(SIG_SYNC)));
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ControlDecoder;
+
+impl ControlDecoder {
+    /// Create a new decoder.
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Decode the frame at the start of `buf`. See [`Decoder::decode`].
+    ///
+    /// # Errors
+    /// Returns an error if `buf` does not start with a frame sent by clients.
+    pub fn decode(&mut self, buf: &[u8]) -> Result<Option<(Frame, usize)>, DecodeError> {
+        let Some((&sig, rest)) = buf.split_first() else {
+            return Ok(None);
+        };
+
+        match sig {
+            SIG_EXIT => Ok(Some((Frame::Exit, 1))),
+            CTRL => match read_control(rest)? {
+                Some((Frame::Control(sig), _)) if sig != SIG_EXIT && sig != SIG_SYNC => {
+                    Err(DecodeError::UnexpectedSignal(sig))
+                }
+                frame => Ok(frame),
+            },
+            other => Err(DecodeError::UnexpectedSignal(other)),
+        }
+    }
+}
+
+/// Decodes frames from the bytes received so far, which allows a [`FrameBuffer`] to be used with either direction.
+pub trait Decode {
+    /// Decode the frame at the start of `buf`. See [`Decoder::decode`].
+    ///
+    /// # Errors
+    /// Returns an error if `buf` does not start with a valid frame.
+    fn decode(&mut self, buf: &[u8]) -> Result<Option<(Frame, usize)>, DecodeError>;
+}
+
+impl Decode for Decoder {
+    fn decode(&mut self, buf: &[u8]) -> Result<Option<(Frame, usize)>, DecodeError> {
+        Decoder::decode(self, buf)
+    }
+}
+
+impl Decode for ControlDecoder {
+    fn decode(&mut self, buf: &[u8]) -> Result<Option<(Frame, usize)>, DecodeError> {
+        ControlDecoder::decode(self, buf)
+    }
+}
+
+/// Read the frame following a [`CTRL`], where `rest` holds the bytes after it.
+///
+/// Returns the frame along with its length including the [`CTRL`], or `None` if `rest` ends before the frame does.
+fn read_control(rest: &[u8]) -> Result<Option<(Frame, usize)>, DecodeError> {
+    let frame = match rest.first() {
+        Some(&SIG_RESUME) => {
+            let Some((value, len)) = read_varint(&rest[1..])? else {
+                return Ok(None);
+            };
+
+            match value.checked_sub(1) {
+                None => (Frame::Resume(None), 2 + len),
+                Some(seq) => {
+                    let Some(session) = rest.get(1 + len..9 + len) else {
+                        return Ok(None);
+                    };
+
+                    let position = Position {
+                        session: read_u64(session),
+                        seq: u64::try_from(seq).map_err(|_| DecodeError::VarintTooLong)?,
+                    };
+                    (Frame::Resume(Some(position)), 10 + len)
+                }
+            }
+        }
+        Some(&SIG_CREDIT) => match read_sequence(&rest[1..])? {
+            Some((amount, len)) => (Frame::Credit(amount), 2 + len),
+            None => return Ok(None),
+        },
+        Some(&sig) => (Frame::Control(sig), 2),
+        None => return Ok(None),
+    };
+
+    Ok(Some(frame))
+}
+
+/// Read a little-endian `u128` from the given 16 bytes.
+fn read_u128(data: &[u8]) -> u128 {
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(data);
+    u128::from_le_bytes(bytes)
+}
+
+/// Read a little-endian `u64` from the given 8 bytes.
+fn read_u64(data: &[u8]) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(data);
+    u64::from_le_bytes(bytes)
+}
+
+/// Read the length-prefixed body of a [`SIG_EVENT`] frame from the start of `buf`.
+///
+/// Returns the packet along with the length of the body and its prefix, or `None` if `buf` ends before the body does.
+/// Fields following the known ones are skipped, since they were added by a later version of the format.
+fn read_event(buf: &[u8]) -> Result<Option<(Packet, usize)>, DecodeError> {
+    let Some((len, prefix_len)) = read_varint(buf)? else {
+        return Ok(None);
+    };
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|&len| len <= MAX_EVENT_LEN)
+        .ok_or(DecodeError::MalformedEvent)?;
+    let Some(body) = buf.get(prefix_len..prefix_len + len) else {
+        return Ok(None);
+    };
+
+    let [version, fields, rest @ ..] = body else {
+        return Err(DecodeError::MalformedEvent);
+    };
+    if *version != EVENT_VERSION {
+        return Err(DecodeError::UnsupportedEventVersion(*version));
+    }
+
+    let (timestamp, mut rest) = rest
+        .split_at_checked(16)
+        .ok_or(DecodeError::MalformedEvent)?;
+    let mut packet = Packet::new(read_u128(timestamp));
+
+    if fields & FIELD_CHANNEL != 0 {
+        let (channel, tail) = rest
+            .split_at_checked(2)
+            .ok_or(DecodeError::MalformedEvent)?;
+        packet.channel = Some(u16::from_le_bytes([channel[0], channel[1]]));
+        rest = tail;
+    }
+    if fields & FIELD_PULSE_WIDTH != 0 {
+        let (pulse_width, len) = read_varint(rest)?.ok_or(DecodeError::MalformedEvent)?;
+        packet.pulse_width =
+            Some(u32::try_from(pulse_width).map_err(|_| DecodeError::VarintTooLong)?);
+        rest = &rest[len..];
+    }
+    if fields & FIELD_TICK != 0 {
+        let tick = rest.get(..4).ok_or(DecodeError::MalformedEvent)?;
+        packet.tick = Some(u32::from_le_bytes([tick[0], tick[1], tick[2], tick[3]]));
+    }
+
+    Ok(Some((packet, prefix_len + len)))
+}
+
+/// Read the little-endian `u16` count at the start of a batch frame's body.
+fn read_batch_len(body: &[u8]) -> Option<usize> {
+    let count = body.get(..2)?;
+    Some(usize::from(u16::from_le_bytes([count[0], count[1]])))
+}
+
+/// Read an unsigned LEB128 varint from the start of `buf`.
+///
+/// Returns the value along with its length in bytes, or `None` if `buf` ends before the varint does.
+fn read_varint(buf: &[u8]) -> Result<Option<(u128, usize)>, DecodeError> {
+    let mut value = 0;
+
+    for (i, shift) in (0..u128::BITS).step_by(7).enumerate() {
+        let Some(&byte) = buf.get(i) else {
+            return Ok(None);
+        };
+        value |= u128::from(byte & 0x7F) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(Some((value, i + 1)));
+        }
+    }
+
+    Err(DecodeError::VarintTooLong)
+}
+
+/// Read a varint holding a sequence number from the start of `buf`. See [`read_varint`].
+fn read_sequence(buf: &[u8]) -> Result<Option<(u64, usize)>, DecodeError> {
+    match read_varint(buf)? {
+        Some((value, len)) => Ok(Some((
+            u64::try_from(value).map_err(|_| DecodeError::VarintTooLong)?,
+            len,
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Buffers the bytes received from any transport, and decodes the frames they hold.
+///
+/// Received bytes are appended to [`FrameBuffer::buffer_mut`], and frames are taken with [`FrameBuffer::next_frame`].
+/// Bytes are only discarded once, before more are appended, so that taking a frame does not shift the buffer.
+///
+/// # Example
+/// ```
+/// use tdtp::codec::{Frame, FrameBuffer, encode};
+///
+/// let mut data = Vec::new();
+/// encode(&Frame::Packet(42), &mut data).unwrap();
+/// encode(&Frame::Empty, &mut data).unwrap();
+///
+/// let mut frames = FrameBuffer::new();
+/// frames.buffer_mut().extend_from_slice(&data[..10]);
+/// assert_eq!(frames.next_frame().unwrap(), None);
+///
+/// frames.buffer_mut().extend_from_slice(&data[10..]);
+/// assert_eq!(frames.next_frame().unwrap(), Some(Frame::Packet(42)));
+/// assert_eq!(frames.next_frame().unwrap(), Some(Frame::Empty));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FrameBuffer<D = Decoder> {
+    /// The bytes received. Those before `pos` are decoded already, and are discarded before more are appended.
+    buf: Vec<u8>,
+    /// The position of the first byte which is not decoded yet.
+    pos: usize,
+    /// The decoder.
+    decoder: D,
+}
+
+impl FrameBuffer {
+    /// Create an empty buffer for frames in either direction.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<D: Decode> FrameBuffer<D> {
+    /// Create an empty buffer decoding frames with the given decoder, e.g., a [`ControlDecoder`] on the server.
+    #[must_use]
+    pub fn with_decoder(decoder: D) -> Self {
+        Self {
+            buf: Vec::new(),
+            pos: 0,
+            decoder,
+        }
+    }
+
+    /// Decode the next frame from the bytes received, or return `None` if they do not hold a complete frame yet.
+    ///
+    /// # Errors
+    /// Returns an error if the bytes do not start with a valid frame. The first byte of the invalid frame is
+    /// discarded, so that decoding can continue with the bytes following it.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, DecodeError> {
+        match self.decoder.decode(&self.buf[self.pos..]) {
+            Ok(Some((frame, len))) => {
+                self.pos += len;
+                Ok(Some(frame))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                self.pos += 1;
+                Err(e)
+            }
+        }
+    }
+
+    /// Discard the bytes decoded so far, and return the buffer to append the bytes received next to.
+    pub fn buffer_mut(&mut self) -> &mut Vec<u8> {
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        &mut self.buf
+    }
+}
+
+/// Reads frames from a blocking reader, buffering the bytes of incomplete frames in a [`FrameBuffer`].
+///
+/// # Example
+/// ```
+/// use tdtp::codec::{Frame, FrameReader, encode};
+///
+/// let mut data = Vec::new();
+/// encode(&Frame::Batch(vec![1, 2, 3]), &mut data).unwrap();
+///
+/// let mut reader = FrameReader::new(data.as_slice());
+/// let frame = reader.next_frame::<std::io::Error>().unwrap();
+/// assert_eq!(frame, Frame::Batch(vec![1, 2, 3]));
+/// ```
+#[derive(Debug)]
+pub struct FrameReader<R, D = Decoder> {
+    /// The underlying reader.
+    reader: R,
+    /// The bytes read, but not decoded yet.
+    frames: FrameBuffer<D>,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Create a new frame reader for frames in either direction.
+    pub fn new(reader: R) -> Self {
+        Self::with_decoder(reader, Decoder::new())
+    }
+}
+
+impl<R: Read, D: Decode> FrameReader<R, D> {
+    /// Create a new frame reader decoding frames with the given decoder, e.g., a [`ControlDecoder`] on the server.
+    pub fn with_decoder(reader: R, decoder: D) -> Self {
+        Self {
+            reader,
+            frames: FrameBuffer::with_decoder(decoder),
+        }
+    }
+
+    /// Read until the next frame is complete, and return it.
+    ///
+    /// The error type can be chosen by the caller; [`io::Error`] will do if decode errors need not be told apart.
+    ///
+    /// # Errors
+    /// Returns an I/O error, which is of kind [`ErrorKind::UnexpectedEof`] if the reader ends before the frame does,
+    /// or a [`DecodeError`] if the frame is not valid. In the latter case, the first byte of the invalid frame is
+    /// discarded, so that reading can continue with the bytes following it.
+    pub fn next_frame<E: From<io::Error> + From<DecodeError>>(&mut self) -> Result<Frame, E> {
+        loop {
+            if let Some(frame) = self.frames.next_frame()? {
+                return Ok(frame);
+            }
+
+            let buf = self.frames.buffer_mut();
+            let len = buf.len();
+            buf.resize(len + READ_SIZE, 0);
+            match self.reader.read(&mut buf[len..]) {
+                Ok(0) => {
+                    buf.truncate(len);
+                    return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+                }
+                Ok(n) => buf.truncate(len + n),
+                Err(e) if e.kind() == ErrorKind::Interrupted => buf.truncate(len),
+                Err(e) => {
+                    buf.truncate(len);
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests of the encoding and decoding of frames.
+
+    use std::io::{self, ErrorKind};
+
+    use super::{
+        ControlDecoder, Decode, DecodeError, Decoder, Frame, FrameBuffer, FrameReader, Packet,
+        Position, encode,
+    };
+    use crate::consts::{
+        CTRL, EMP, EVENT_VERSION, SIG_BATCH, SIG_CREDIT, SIG_DELTA, SIG_DELTA_BATCH, SIG_EVENT,
+        SIG_EXIT, SIG_PACKET, SIG_SEQUENCE, SIG_SYNC,
+    };
+
+    /// A frame of every kind the server sends, in an order it may send them.
+    fn server_frames() -> Vec<Frame> {
+        vec![
+            Frame::Sequence(Position {
+                session: u64::MAX,
+                seq: 1 << 40,
+            }),
+            Frame::Packet(u128::MAX),
+            Frame::Empty,
+            Frame::Batch(vec![1, 2, 3]),
+            Frame::Batch(Vec::new()),
+            Frame::Delta(0),
+            Frame::DeltaBatch(vec![127, 128, 1 << 60]),
+            Frame::Sync,
+            Frame::SyncReply {
+                elapsed: 1234,
+                epoch_micros: 1_700_000_000_000_000,
+            },
+            Frame::Event(Packet::new(5)),
+            Frame::Event(
+                Packet::new(6)
+                    .with_channel(7)
+                    .with_pulse_width(u32::MAX)
+                    .with_tick(9),
+            ),
+            Frame::Exit,
+        ]
+    }
+
+    /// Encode the given frames back to back.
+    fn encode_all(frames: &[Frame]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for frame in frames {
+            encode(frame, &mut buf).unwrap();
+        }
+        buf
+    }
+
+    /// Feed `data` into `frames` in chunks of `chunk` bytes, and return the frames decoded along with the amount of
+    /// errors.
+    fn decode_chunked<D: Decode>(
+        frames: &mut FrameBuffer<D>,
+        data: &[u8],
+        chunk: usize,
+    ) -> (Vec<Frame>, usize) {
+        let mut decoded = Vec::new();
+        let mut errors = 0;
+
+        for chunk in data.chunks(chunk) {
+            frames.buffer_mut().extend_from_slice(chunk);
+            loop {
+                match frames.next_frame() {
+                    Ok(Some(frame)) => decoded.push(frame),
+                    Ok(None) => break,
+                    Err(_) => errors += 1,
+                }
+            }
+        }
+
+        (decoded, errors)
+    }
+
+    /// The frames a client sends are decoded, and a bare exit of older clients is understood.
+    #[test]
+    fn control_decoder_accepts_client_frames() {
+        let position = Position {
+            session: 0xDEAD_BEEF,
+            seq: 41,
+        };
+        let sent = [
+            Frame::Resume(None),
+            Frame::Resume(Some(position)),
+            Frame::Credit(u64::MAX),
+            Frame::Control(SIG_SYNC),
+            Frame::Control(SIG_EXIT),
+            Frame::Exit,
+        ];
+        let data = encode_all(&sent);
+
+        for chunk in [1, 2, 7, data.len()] {
+            let mut frames = FrameBuffer::with_decoder(ControlDecoder::new());
+            assert_eq!(
+                decode_chunked(&mut frames, &data, chunk),
+                (sent.to_vec(), 0)
+            );
+        }
+    }
+
+    /// Stray bytes are rejected one at a time, without swallowing the exit and credit following them.
+    #[test]
+    fn control_decoder_skips_garbage() {
+        let sent = [Frame::Control(SIG_EXIT), Frame::Credit(300)];
+        let garbage: [&[u8]; 8] = [
+            &[SIG_SYNC],
+            &[SIG_PACKET],
+            &[SIG_BATCH, 0xFF, 0xFF],
+            &[SIG_EVENT, 0x7F],
+            &[SIG_SEQUENCE],
+            &[EMP, 0x42],
+            &[CTRL, 0x42],
+            &[CTRL, SIG_PACKET, CTRL, SIG_BATCH],
+        ];
+
+        for garbage in garbage {
+            let mut data = garbage.to_vec();
+            data.extend(encode_all(&sent));
+
+            for chunk in [1, data.len()] {
+                let mut frames = FrameBuffer::with_decoder(ControlDecoder::new());
+                let (decoded, errors) = decode_chunked(&mut frames, &data, chunk);
+                assert_eq!(decoded, sent, "after {garbage:02x?}");
+                assert_eq!(errors, garbage.len(), "after {garbage:02x?}");
+            }
+        }
+    }
+
+    /// Unknown signals after a [`CTRL`] are rejected, while incomplete frames are waited for.
+    #[test]
+    fn control_decoder_rejects_unknown_signals() {
+        let mut decoder = ControlDecoder::new();
+
+        assert_eq!(
+            decoder.decode(&[CTRL, 0x42]),
+            Err(DecodeError::UnexpectedSignal(0x42))
+        );
+        assert_eq!(
+            decoder.decode(&[CTRL, EMP]),
+            Err(DecodeError::UnexpectedSignal(EMP))
+        );
+        assert_eq!(decoder.decode(&[]), Ok(None));
+        assert_eq!(decoder.decode(&[CTRL]), Ok(None));
+        assert_eq!(decoder.decode(&[CTRL, SIG_CREDIT, 0x80]), Ok(None));
+    }
+
+    /// Frames of every kind are decoded as they were encoded, however the bytes are split when received.
+    #[test]
+    fn frames_survive_any_split() {
+        let sent = server_frames();
+        let data = encode_all(&sent);
+
+        for chunk in [1, 2, 3, 16, 17, 100, data.len()] {
+            let mut frames = FrameBuffer::new();
+            assert_eq!(decode_chunked(&mut frames, &data, chunk), (sent.clone(), 0));
+        }
+    }
+
+    /// A truncated frame is waited for rather than decoded, and no prefix of a frame is mistaken for a frame.
+    #[test]
+    fn truncated_frames_are_incomplete() {
+        for frame in server_frames() {
+            let data = encode_all(std::slice::from_ref(&frame));
+            let mut decoder = Decoder::new();
+            if let Frame::SyncReply { .. } = frame {
+                decoder.decode(&[SIG_SYNC]).unwrap();
+            }
+
+            for len in 0..data.len() {
+                assert_eq!(
+                    decoder.clone().decode(&data[..len]),
+                    Ok(None),
+                    "{frame:?} cut to {len} bytes"
+                );
+            }
+            assert_eq!(decoder.decode(&data), Ok(Some((frame, data.len()))));
+        }
+    }
+
+    /// A [`FrameReader`] reports a stream ending within a frame as such, and skips an invalid byte.
+    #[test]
+    fn frame_reader_errors() {
+        let mut data = vec![0x42];
+        data.extend(encode_all(&[Frame::Packet(7)]));
+        data.pop();
+
+        let mut reader = FrameReader::new(data.as_slice());
+        let error = reader.next_frame::<io::Error>().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let error = reader.next_frame::<io::Error>().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    /// The general decoder passes on control signals it does not know, so that the caller can decide about them.
+    #[test]
+    fn decoder_passes_unknown_control_signals() {
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            decoder.decode(&[CTRL, 0x42, EMP]),
+            Ok(Some((Frame::Control(0x42), 2)))
+        );
+        assert_eq!(
+            decoder.decode(&[0x42]),
+            Err(DecodeError::UnexpectedSignal(0x42))
+        );
+    }
+
+    /// Varints longer than their type are rejected.
+    #[test]
+    fn long_varints_are_rejected() {
+        let mut decoder = Decoder::new();
+
+        let mut delta = vec![SIG_DELTA];
+        delta.extend([0xFF; 19]);
+        assert_eq!(decoder.decode(&delta), Err(DecodeError::VarintTooLong));
+
+        let mut credit = vec![CTRL, SIG_CREDIT];
+        credit.extend([0xFF; 9]);
+        credit.push(0x7F);
+        assert_eq!(decoder.decode(&credit), Err(DecodeError::VarintTooLong));
+        assert_eq!(
+            ControlDecoder::new().decode(&credit),
+            Err(DecodeError::VarintTooLong)
+        );
+    }
+
+    /// Event bodies of another version, or which are too long or too short for their fields, are rejected.
+    #[test]
+    fn malformed_events_are_rejected() {
+        let mut decoder = Decoder::new();
+
+        assert_eq!(
+            decoder.decode(&[SIG_EVENT, 2, EVENT_VERSION + 1, 0]),
+            Err(DecodeError::UnsupportedEventVersion(EVENT_VERSION + 1))
+        );
+        assert_eq!(
+            decoder.decode(&[SIG_EVENT, 0x80, 0x02]),
+            Err(DecodeError::MalformedEvent)
+        );
+        assert_eq!(
+            decoder.decode(&[SIG_EVENT, 1, EVENT_VERSION]),
+            Err(DecodeError::MalformedEvent)
+        );
+
+        // the channel flag is set, but the body ends after the timestamp
+        let mut event = vec![SIG_EVENT, 18, EVENT_VERSION, 1];
+        event.extend([0; 16]);
+        assert_eq!(decoder.decode(&event), Err(DecodeError::MalformedEvent));
+
+        // unknown trailing fields are skipped
+        let mut event = vec![SIG_EVENT, 19, EVENT_VERSION, 0];
+        event.extend([0; 17]);
+        assert_eq!(
+            decoder.decode(&event),
+            Ok(Some((Frame::Event(Packet::new(0)), 21)))
+        );
+    }
+
+    /// Deltas without a base or overflowing it are rejected, and a rejected frame appends no packets.
+    #[test]
+    fn deltas_need_a_valid_base() {
+        let mut packets = Vec::new();
+
+        let mut last = None;
+        assert_eq!(
+            Frame::Delta(1).packets_into(&mut last, &mut packets),
+            Err(DecodeError::MissingBase(SIG_DELTA))
+        );
+        assert_eq!(
+            Frame::DeltaBatch(vec![1]).packets_into(&mut last, &mut packets),
+            Err(DecodeError::MissingBase(SIG_DELTA_BATCH))
+        );
+
+        let mut last = Some(u128::MAX - 2);
+        assert_eq!(
+            Frame::DeltaBatch(vec![1, 1, 1]).packets_into(&mut last, &mut packets),
+            Err(DecodeError::DeltaOverflow)
+        );
+        assert_eq!(last, Some(u128::MAX - 2));
+        assert!(packets.is_empty());
+
+        Frame::Empty.packets_into(&mut last, &mut packets).unwrap();
+        assert_eq!(last, Some(u128::MAX - 2));
+    }
+
+    /// A batch longer than its length prefix allows is not encoded.
+    #[test]
+    fn long_batches_are_not_encoded() {
+        let batch = Frame::Batch(vec![0; usize::from(u16::MAX) + 1]);
+        let error = encode(&batch, &mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,459 @@
+//! Cryptographic conditioning of extracted bits, as allowed by NIST SP 800-90B, section 3.1.5.
+//!
+//! The bits of an [`Extractor`] are never perfectly unbiased and independent. [`Conditioned`] collects them until they
+//! carry enough min-entropy, and compresses them with a [`Conditioner`] into blocks of [`BLOCK_LEN`] bits with full
+//! entropy. [`RandomSource`] draws random numbers from the bits of an extractor, and implements [`TryRngCore`] of
+//! `rand_core`, as well as [`RngCore`](rand_core::RngCore) through [`TryRngCore::unwrap_err`].
+//!
+//! # Example
+//! ```no_run
+//! use rand_core::{RngCore, TryRngCore};
+//! use tdtp::{
+//!     client::data,
+//!     client_mpsc::client_channel,
+//!     condition::{Conditioned, RandomSource},
+//!     extract::{Comparison, VonNeumann},
+//!     health::HealthChecked,
+//! };
+//!
+//! let (tx, rx) = client_channel(8192);
+//! std::thread::spawn(move || data("localhost:8000", tx));
+//!
+//! // claim the entropy per bit estimated by `tdtp-entropy`
+//! let extractor =
+//!     Conditioned::new(HealthChecked::new(VonNeumann::new(Comparison::new()))).with_entropy(0.9);
+//! let mut source = RandomSource::new(rx.iter(), extractor);
+//!
+//! match source.try_next_u64() {
+//!     Ok(number) => println!("Random number: {number}"),
+//!     Err(e) => eprintln!("No random number: {e}"),
+//! }
+//!
+//! // panics once the connection closes or a health test fails
+//! let mut rng = source.unwrap_err();
+//! println!("Random number: {}", rng.next_u32());
+//! ```
+
+use std::{collections::VecDeque, fmt::Display};
+
+use hmac::{Hmac, Mac};
+use log::debug;
+use rand_core::{TryCryptoRng, TryRngCore};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    client::{ClientEvent, IncomingDataPacket},
+    extract::Extractor,
+    health::HealthChecked,
+};
+
+/// The length of a conditioned block, in bits, which is the output length of SHA-256.
+pub const BLOCK_LEN: usize = 256;
+
+/// The default min-entropy claimed per raw bit, which is deliberately conservative, and should be replaced by an
+/// estimate, see [`entropy`](crate::entropy).
+pub const DEFAULT_CLAIMED_ENTROPY: f64 = 0.5;
+
+/// The default min-entropy collected per conditioned block, which is its length plus the 64 bits SP 800-90C requires
+/// for its output to have full entropy.
+pub const DEFAULT_INPUT_ENTROPY: f64 = 320.0;
+
+/// A vetted conditioning function of SP 800-90B, which compresses raw bits into a block of [`BLOCK_LEN`] bits.
+#[derive(Debug, Clone)]
+pub struct Conditioner {
+    /// The HMAC keyed for every block, or `None` to hash the raw bits with SHA-256.
+    mac: Option<Hmac<Sha256>>,
+}
+
+impl Default for Conditioner {
+    fn default() -> Self {
+        Self::sha256()
+    }
+}
+
+impl Conditioner {
+    /// Condition with SHA-256.
+    #[must_use]
+    pub fn sha256() -> Self {
+        Self { mac: None }
+    }
+
+    /// Condition with HMAC-SHA-256 keyed with `key`, which need not be secret.
+    ///
+    /// # Panics
+    /// Never panics, as HMAC takes keys of any length.
+    #[must_use]
+    pub fn hmac_sha256(key: &[u8]) -> Self {
+        let mac = Hmac::new_from_slice(key).expect("HMAC takes keys of any length");
+
+        Self { mac: Some(mac) }
+    }
+
+    /// Compress the given raw bytes into a block.
+    fn condition(&self, raw: &[u8]) -> [u8; BLOCK_LEN / 8] {
+        match &self.mac {
+            Some(mac) => mac.clone().chain_update(raw).finalize().into_bytes().into(),
+            None => Sha256::digest(raw).into(),
+        }
+    }
+}
+
+/// Conditions the bits of another extractor: it collects raw bits until they carry [`DEFAULT_INPUT_ENTROPY`], and
+/// yields the [`BLOCK_LEN`] bits of their conditioned block.
+///
+/// How many raw bits are collected per block follows from the min-entropy claimed per raw bit, which defaults to
+/// [`DEFAULT_CLAIMED_ENTROPY`].
+///
+/// # Example
+/// ```
+/// use tdtp::{
+///     condition::{BLOCK_LEN, Conditioned, Conditioner},
+///     extract::{Comparison, Extractor},
+/// };
+///
+/// let mut extractor = Conditioned::new(Comparison::new())
+///     .with_conditioner(Conditioner::hmac_sha256(b"tdtp"))
+///     .with_entropy(0.8);
+/// assert_eq!(extractor.raw_len(), 400);
+///
+/// // every pair of intervals which differ yields a raw bit, so that these yield one block
+/// let timestamps = (0..1000).map(|i: u128| i * 10_000 + i * i % 1009);
+/// assert_eq!(extractor.extract(timestamps).len(), BLOCK_LEN);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Conditioned<E> {
+    /// The extractor whose bits are conditioned.
+    inner: E,
+    /// The conditioning function.
+    conditioner: Conditioner,
+    /// The min-entropy claimed per raw bit.
+    entropy: f64,
+    /// The min-entropy collected per block.
+    input_entropy: f64,
+    /// The raw bits collected for the current block.
+    raw: Vec<bool>,
+}
+
+impl<E: Extractor> Conditioned<E> {
+    /// Condition the bits of `inner` with SHA-256.
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            conditioner: Conditioner::sha256(),
+            entropy: DEFAULT_CLAIMED_ENTROPY,
+            input_entropy: DEFAULT_INPUT_ENTROPY,
+            raw: Vec::new(),
+        }
+    }
+
+    /// Set the conditioning function. Defaults to [`Conditioner::sha256`].
+    #[must_use]
+    pub fn with_conditioner(mut self, conditioner: Conditioner) -> Self {
+        self.conditioner = conditioner;
+        self
+    }
+
+    /// Set the min-entropy claimed per raw bit. Defaults to [`DEFAULT_CLAIMED_ENTROPY`].
+    ///
+    /// # Panics
+    /// Panics if `entropy` is not within `(0, 1]`.
+    #[must_use]
+    pub fn with_entropy(mut self, entropy: f64) -> Self {
+        assert!(
+            entropy > 0.0 && entropy <= 1.0,
+            "a bit carries between 0 and 1 bits of entropy"
+        );
+        self.entropy = entropy;
+        self
+    }
+
+    /// Set the min-entropy collected per block. Defaults to [`DEFAULT_INPUT_ENTROPY`].
+    ///
+    /// # Panics
+    /// Panics if `input_entropy` is less than [`BLOCK_LEN`], as conditioning cannot create entropy.
+    #[must_use]
+    #[expect(clippy::cast_precision_loss)]
+    pub fn with_input_entropy(mut self, input_entropy: f64) -> Self {
+        assert!(
+            input_entropy >= BLOCK_LEN as f64,
+            "a block needs at least as much entropy as it has bits"
+        );
+        self.input_entropy = input_entropy;
+        self
+    }
+
+    /// How many raw bits are collected per block.
+    #[must_use]
+    #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn raw_len(&self) -> usize {
+        (self.input_entropy / self.entropy).ceil() as usize
+    }
+
+    /// The extractor whose bits are conditioned, e.g. to read the status of its health tests.
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// Mutable access to the extractor whose bits are conditioned, e.g. to reset its health tests after a failure.
+    pub fn inner_mut(&mut self) -> &mut E {
+        &mut self.inner
+    }
+}
+
+impl<E: Extractor> Extractor for Conditioned<E> {
+    fn push(&mut self, timestamp: IncomingDataPacket, bits: &mut Vec<bool>) {
+        let raw_len = self.raw_len();
+        self.inner.push(timestamp, &mut self.raw);
+
+        let complete = self.raw.len() / raw_len * raw_len;
+        for raw in self.raw[..complete].chunks_exact(raw_len) {
+            let bytes = raw
+                .chunks(8)
+                .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+                .collect::<Vec<_>>();
+            let block = self.conditioner.condition(&bytes);
+
+            debug!("Conditioned {raw_len} raw bits into a block");
+            bits.extend(
+                block
+                    .iter()
+                    .flat_map(|byte| (0..8).rev().map(move |bit| byte >> bit & 1 == 1)),
+            );
+        }
+        self.raw.drain(..complete);
+    }
+
+    /// Forward the gap to the inner extractor. The raw bits collected so far are kept, since each of them was
+    /// extracted before the gap, and their order does not matter to the conditioning function.
+    fn gap(&mut self) {
+        self.inner.gap();
+    }
+
+    fn is_halted(&self) -> bool {
+        self.inner.is_halted()
+    }
+}
+
+/// Why a [`RandomSource`] could not produce random numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceError {
+    /// The timestamps ended, e.g. because the connection to the server closed.
+    Exhausted,
+    /// The extractor halted, e.g. because a health test failed.
+    Halted,
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exhausted => write!(f, "the timestamps ended"),
+            Self::Halted => write!(f, "the extractor halted"),
+        }
+    }
+}
+
+/// A source of random numbers, which extracts bits from the given timestamps as they are needed.
+///
+/// It implements [`TryRngCore`], and [`TryCryptoRng`] if its extractor is [`Conditioned`] and health-tested by a
+/// [`HealthChecked`], since conditioning alone does not notice the detector failing. Since it may fail, it
+/// implements [`RngCore`](rand_core::RngCore) only through [`TryRngCore::unwrap_err`] and
+/// [`TryRngCore::unwrap_mut`], which panic instead.
+///
+/// Drawing a number blocks until enough timestamps arrived, unless the extractor halted, which fails instead. The
+/// timestamps may also be given as [`ClientEvent`]s, whose gaps are forwarded to [`Extractor::gap`].
+#[derive(Debug, Clone)]
+pub struct RandomSource<I, E> {
+    /// The timestamps of the events.
+    timestamps: I,
+    /// The extractor the bits are extracted with.
+    extractor: E,
+    /// The bits extracted, but not yet used.
+    bits: VecDeque<bool>,
+    /// The bits extracted from the latest timestamp, which are moved to `bits` right away, and only kept to reuse
+    /// their allocation.
+    extracted: Vec<bool>,
+}
+
+impl<I: Iterator<Item: Into<ClientEvent>>, E: Extractor> RandomSource<I, E> {
+    /// Draw random numbers from the bits `extractor` extracts from `timestamps`, such as
+    /// [`ClientReceiver::iter`](crate::client_mpsc::ClientReceiver::iter) of a channel of timestamps or of
+    /// [`ClientEvent`]s.
+    pub fn new(timestamps: impl IntoIterator<IntoIter = I>, extractor: E) -> Self {
+        Self {
+            timestamps: timestamps.into_iter(),
+            extractor,
+            bits: VecDeque::new(),
+            extracted: Vec::new(),
+        }
+    }
+
+    /// The extractor the bits are extracted with.
+    pub fn extractor(&self) -> &E {
+        &self.extractor
+    }
+
+    /// The mutable extractor the bits are extracted with, e.g. to reset its health tests after a failure.
+    pub fn extractor_mut(&mut self) -> &mut E {
+        &mut self.extractor
+    }
+
+    /// The next byte, made of the next 8 bits.
+    fn next_byte(&mut self) -> Result<u8, SourceError> {
+        while self.bits.len() < 8 {
+            if self.extractor.is_halted() {
+                return Err(SourceError::Halted);
+            }
+            match self.timestamps.next().ok_or(SourceError::Exhausted)?.into() {
+                ClientEvent::Packet(timestamp) => {
+                    self.extracted.clear();
+                    self.extractor.push(timestamp, &mut self.extracted);
+                    self.bits.extend(&self.extracted);
+                }
+                ClientEvent::Gap(_) => self.extractor.gap(),
+            }
+        }
+
+        Ok(self
+            .bits
+            .drain(..8)
+            .fold(0, |acc, bit| acc << 1 | u8::from(bit)))
+    }
+}
+
+impl<I: Iterator<Item: Into<ClientEvent>>, E: Extractor> TryRngCore for RandomSource<I, E> {
+    type Error = SourceError;
+
+    fn try_next_u32(&mut self) -> Result<u32, Self::Error> {
+        let mut bytes = [0; 4];
+        self.try_fill_bytes(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn try_next_u64(&mut self) -> Result<u64, Self::Error> {
+        let mut bytes = [0; 8];
+        self.try_fill_bytes(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn try_fill_bytes(&mut self, dst: &mut [u8]) -> Result<(), Self::Error> {
+        for byte in dst {
+            *byte = self.next_byte()?;
+        }
+        Ok(())
+    }
+}
+
+impl<I: Iterator<Item: Into<ClientEvent>>, E: Extractor> TryCryptoRng
+    for RandomSource<I, Conditioned<HealthChecked<E>>>
+{
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests of the conditioning functions and of the random source.
+
+    use rand_core::TryRngCore;
+    use sha2::{Digest, Sha256};
+
+    use super::{BLOCK_LEN, Conditioned, Conditioner, RandomSource, SourceError};
+    use crate::{
+        client::{ClientEvent, Gap, IncomingDataPacket},
+        extract::Extractor,
+        health::HealthChecked,
+    };
+
+    /// Yields the lowest byte of every timestamp, most significant bit first, until it is halted.
+    #[derive(Default)]
+    struct LowByte {
+        /// Whether it halted.
+        halted: bool,
+        /// How many gaps it was told about.
+        gaps: u32,
+    }
+
+    impl Extractor for LowByte {
+        fn push(&mut self, timestamp: IncomingDataPacket, bits: &mut Vec<bool>) {
+            bits.extend((0..8).rev().map(|bit| timestamp >> bit & 1 == 1));
+        }
+
+        fn gap(&mut self) {
+            self.gaps += 1;
+        }
+
+        fn is_halted(&self) -> bool {
+            self.halted
+        }
+    }
+
+    /// Decode the given hex string.
+    fn hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// The conditioning functions match the test vectors of FIPS 180-4 and RFC 4231.
+    #[test]
+    fn conditioners_match_test_vectors() {
+        assert_eq!(
+            Conditioner::sha256().condition(b"abc").to_vec(),
+            hex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert_eq!(
+            Conditioner::hmac_sha256(b"Jefe")
+                .condition(b"what do ya want for nothing?")
+                .to_vec(),
+            hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+    }
+
+    /// Raw bits are collected until they carry the input entropy, and conditioned as bytes, most significant bit first.
+    #[test]
+    fn conditioned_blocks_of_raw_bits() {
+        assert_eq!(Conditioned::new(LowByte::default()).raw_len(), 640);
+
+        let mut extractor = Conditioned::new(LowByte::default())
+            .with_entropy(1.0)
+            .with_input_entropy(256.0);
+        assert_eq!(extractor.raw_len(), 256);
+
+        assert!(extractor.extract(0..31).is_empty());
+        let block = extractor.extract(31..40);
+        assert_eq!(block.len(), BLOCK_LEN);
+
+        let bytes = block
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+            .collect::<Vec<_>>();
+        assert_eq!(bytes, Sha256::digest((0..32).collect::<Vec<u8>>()).to_vec());
+    }
+
+    /// A source draws bytes from the bits as they are extracted, and fails once the timestamps end or the extractor
+    /// halts.
+    #[test]
+    fn source_fails_when_exhausted_or_halted() {
+        let mut source = RandomSource::new([1, 2, 3, 4, 5], LowByte::default());
+        assert_eq!(source.try_next_u32(), Ok(0x0403_0201));
+        assert_eq!(source.try_next_u32(), Err(SourceError::Exhausted));
+
+        let mut source = RandomSource::new(0.., LowByte::default());
+        source.extractor_mut().halted = true;
+        assert_eq!(source.try_next_u64(), Err(SourceError::Halted));
+    }
+
+    /// The gaps among the events a source draws from are forwarded through every extractor.
+    #[test]
+    fn source_forwards_gaps() {
+        let events = [
+            ClientEvent::Packet(1),
+            ClientEvent::Gap(Gap::Unknown),
+            ClientEvent::Packet(2),
+        ];
+        let extractor = Conditioned::new(HealthChecked::new(LowByte::default()));
+        let mut source = RandomSource::new(events, extractor);
+
+        assert_eq!(source.try_next_u32(), Err(SourceError::Exhausted));
+        assert_eq!(source.extractor().inner().inner().gaps, 1);
+    }
+}
@@ -8,6 +8,91 @@ pub const EMP: u8 = 0x00;
 pub const SIG_PACKET: u8 = !EMP;
 /// The exit signal.
 pub const SIG_EXIT: u8 = 0x19;
+/// The clock synchronisation signal. Sent by the server to start a synchronisation round and echoed by the client,
+/// prefixed with [`CTRL`]. Outside of a round, the client may send `[CTRL, SIG_SYNC]` to request one.
+pub const SIG_SYNC: u8 = 0xAA;
+/// The batch signal, indicating that a little-endian `u16` count follows, followed by that many timestamps.
+pub const SIG_BATCH: u8 = 0xBB;
+/// The maximum amount of timestamps in a single [`SIG_BATCH`] or [`SIG_DELTA_BATCH`] frame.
+pub const MAX_BATCH: u16 = 1024;
+/// The delta signal, indicating that a LEB128 varint follows, which is to be added to the previously received timestamp.
+pub const SIG_DELTA: u8 = 0xDE;
+/// The delta batch signal, indicating that a little-endian `u16` count follows, followed by that many LEB128 varints.
+/// Each of them is a delta to the timestamp preceding it, like in [`SIG_DELTA`].
+pub const SIG_DELTA_BATCH: u8 = 0xDB;
+/// The sequence signal, indicating that the session ID of the server follows as a little-endian `u64`, followed by a
+/// LEB128 varint, which is the sequence number of the next packet. Every packet after it is numbered one higher than
+/// the one preceding it. The server sends it before the first packet of a connection and whenever the numbering skips
+/// ahead, i.e., when packets are missing. The session ID is chosen randomly whenever the server starts.
+pub const SIG_SEQUENCE: u8 = 0x5E;
+/// The resume signal, sent by the client prefixed with [`CTRL`] as its first frame if [`CAP_RESUME`] was negotiated.
+/// It is followed by a LEB128 varint, which is `0` for a new connection. Otherwise, it is one more than the sequence
+/// number of the next packet the client expects, followed by the session ID of that number as a little-endian `u64`,
+/// and the server replays the packets the client missed, as far as they are still available.
+pub const SIG_RESUME: u8 = 0x4E;
+/// The credit signal, sent by the client prefixed with [`CTRL`] if [`CAP_CREDIT`] was negotiated. It is followed by a
+/// LEB128 varint, which is the amount of further packets the client accepts. The server only sends packets while it
+/// has credit, and holds back the others until the client grants more, as far as they are still kept for replay. The
+/// client grants its first credit right after its resume request; `u64::MAX` grants unlimited credit.
+pub const SIG_CREDIT: u8 = 0xC7;
+/// The event signal, indicating that a LEB128 varint follows, which is the length of the event body following it.
+/// The body starts with the version of its format ([`EVENT_VERSION`]) and a byte of field flags, followed by the
+/// timestamp as a little-endian `u128` and the fields whose flags are set, in the order of their flags. Since fields
+/// are only ever appended, a receiver skips the fields it does not know. The timestamp is the base for following
+/// deltas, like that of a [`SIG_PACKET`] frame.
+pub const SIG_EVENT: u8 = 0xEE;
+/// The version of the event body format (see [`SIG_EVENT`]) implemented by this crate. It only changes if the format
+/// changes in a way which is incompatible with receivers of the previous version; new fields do not change it.
+pub const EVENT_VERSION: u8 = 1;
+
+/// The event field flag for the ID of the channel (detector) which registered the event, a little-endian `u16`.
+pub const FIELD_CHANNEL: u8 = 1 << 0;
+/// The event field flag for the width of the detector pulse in microseconds, a LEB128 varint.
+pub const FIELD_PULSE_WIDTH: u8 = 1 << 1;
+/// The event field flag for the GPIO tick of the event, i.e., the microseconds since the boot of the GPIO library as a
+/// little-endian `u32`, which wraps around.
+pub const FIELD_TICK: u8 = 1 << 2;
+
+/// The magic bytes opening the hello message of a client.
+pub const MAGIC: [u8; 4] = *b"TDTP";
+/// The protocol version implemented by this crate. Clients which do not send a hello message are considered to speak version `0`.
+pub const PROTOCOL_VERSION: u8 = 1;
+/// The oldest protocol version which still supports version negotiation.
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+
+/// The capability flag for clock synchronisation (see [`SIG_SYNC`]).
+pub const CAP_SYNC: u16 = 1 << 0;
+/// The capability flag for batched packet frames (see [`SIG_BATCH`]).
+pub const CAP_BATCH: u16 = 1 << 1;
+/// The capability flag for delta-encoded timestamps (see [`SIG_DELTA`]).
+pub const CAP_DELTA: u16 = 1 << 2;
+/// The capability flag for sequence numbers and resuming connections (see [`SIG_SEQUENCE`] and [`SIG_RESUME`]).
+pub const CAP_RESUME: u16 = 1 << 3;
+/// The capability flag for events carrying detector metadata (see [`SIG_EVENT`]).
+pub const CAP_EVENT: u16 = 1 << 4;
+/// The capability flag for credit-based flow control (see [`SIG_CREDIT`]).
+pub const CAP_CREDIT: u16 = 1 << 5;
+/// The capability flag for timestamps sent as offsets from the epoch of the first clock synchronisation, rather than
+/// as unix timestamps (see [`SIG_SYNC`]). It is only negotiated along with [`CAP_SYNC`].
+pub const CAP_EPOCH: u16 = 1 << 6;
+/// All capabilities supported by this crate.
+pub const CAPABILITIES: u16 =
+    CAP_SYNC | CAP_BATCH | CAP_DELTA | CAP_RESUME | CAP_EVENT | CAP_CREDIT | CAP_EPOCH;
+
+/// The server's reply to a hello message, indicating that the connection was accepted.
+/// It is followed by the negotiated protocol version and the negotiated capabilities as a little-endian `u16`.
+pub const HELLO_ACCEPT: u8 = 0x06;
+/// The server's reply to a hello message, indicating that the connection was rejected. It is followed by a [`RejectReason`].
+pub const HELLO_REJECT: u8 = 0x15;
+
+/// The reject reason for a hello message not starting with [`MAGIC`].
+pub const REJECT_BAD_MAGIC: u8 = 0x01;
+/// The reject reason for an unsupported protocol version.
+pub const REJECT_UNSUPPORTED_VERSION: u8 = 0x02;
+/// The reject reason for an unsupported connection type.
+pub const REJECT_UNSUPPORTED_CONNECTION: u8 = 0x03;
+/// The reject reason for a server which already serves as many connections as it allows.
+pub const REJECT_SERVER_FULL: u8 = 0x04;
 
 /// The connection data flag.
 pub const CONN_DATA: u8 = 0x01;
@@ -15,7 +100,47 @@ pub const CONN_DATA: u8 = 0x01;
 /// Represents the different types of connections which are available.
 // enum because we may add diff conn types in the future
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConnectionType {
     /// The data connection, for transmitting radioactivity data.
     Data = CONN_DATA,
 }
+
+/// Represents the reasons for which a server may reject a connection.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The hello message did not start with [`MAGIC`].
+    BadMagic = REJECT_BAD_MAGIC,
+    /// The client's protocol version is not supported by the server.
+    UnsupportedVersion = REJECT_UNSUPPORTED_VERSION,
+    /// The requested connection type is not supported by the server.
+    UnsupportedConnection = REJECT_UNSUPPORTED_CONNECTION,
+    /// The server already serves as many connections as it allows.
+    ServerFull = REJECT_SERVER_FULL,
+}
+
+impl TryFrom<u8> for RejectReason {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            REJECT_BAD_MAGIC => Ok(Self::BadMagic),
+            REJECT_UNSUPPORTED_VERSION => Ok(Self::UnsupportedVersion),
+            REJECT_UNSUPPORTED_CONNECTION => Ok(Self::UnsupportedConnection),
+            REJECT_SERVER_FULL => Ok(Self::ServerFull),
+            v => Err(v),
+        }
+    }
+}
+
+impl core::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "bad magic bytes"),
+            Self::UnsupportedVersion => write!(f, "unsupported protocol version"),
+            Self::UnsupportedConnection => write!(f, "unsupported connection type"),
+            Self::ServerFull => write!(f, "server is full"),
+        }
+    }
+}
@@ -0,0 +1,717 @@
+//! Estimation of the min-entropy of recorded detector events, following the non-IID track of NIST SP 800-90B,
+//! section 6.3.
+//!
+//! A capture is a sequence of timestamps, stored as little-endian [`IncomingDataPacket`]s like on the wire (see
+//! [`read_capture`] and [`write_capture`]). The intervals between them are turned into samples, either by sorting them
+//! into the bins of an [`IntervalToBits`] extractor ([`bin_samples`]), or by taking their lowest bits
+//! ([`low_bit_samples`]), and [`Report::new`] runs the estimators on these samples:
+//!
+//! + the most common value, t-tuple and longest repeated substring estimates on the samples themselves, and
+//! + all of the above plus the collision, Markov and compression estimates on their bits.
+//!
+//! The min-entropy per sample is the least of all estimates, where those of the bits are scaled by the bits per
+//! sample. The `tdtp-entropy` binary runs them on a capture file.
+//!
+//! SP 800-90B requires at least a million samples for its estimates to be meaningful.
+//!
+//! # Example
+//! ```no_run
+//! use std::fs::File;
+//!
+//! use tdtp::{
+//!     entropy::{Report, bin_samples, intervals, read_capture},
+//!     extract::IntervalToBits,
+//! };
+//!
+//! let timestamps = read_capture(File::open("capture.bin")?)?;
+//! let mut extractor = IntervalToBits::new();
+//! let samples = bin_samples(&intervals(&timestamps), &mut extractor);
+//!
+//! let report = Report::new(&samples, extractor.bits_per_interval());
+//! println!("{:.3} bits of min-entropy per interval", report.min_entropy());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+};
+
+use crate::{
+    client::IncomingDataPacket,
+    extract::{IntervalToBits, interval},
+};
+
+/// The size of a block of the compression estimate, in bits.
+const COMPRESSION_BLOCK_LEN: u32 = 6;
+
+/// The amount of blocks the dictionary of the compression estimate is initialized with.
+const COMPRESSION_DICTIONARY_LEN: usize = 1000;
+
+/// The standard score of the upper bound of the 99% confidence interval every estimate is based on.
+const Z_ALPHA: f64 = 2.576;
+
+/// How often the most common tuple must occur for the t-tuple estimate to consider tuples of its length.
+const TUPLE_CUTOFF: u64 = 35;
+
+/// Read a capture of timestamps, each stored as a little-endian [`IncomingDataPacket`].
+///
+/// # Errors
+/// Returns an error if reading fails, or [`io::ErrorKind::InvalidData`] if the capture ends within a timestamp.
+pub fn read_capture(mut reader: impl Read) -> io::Result<Vec<IncomingDataPacket>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let timestamps = bytes.chunks_exact(size_of::<IncomingDataPacket>());
+    if !timestamps.remainder().is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "capture ends within a timestamp",
+        ));
+    }
+
+    Ok(timestamps
+        .map(|timestamp| {
+            let mut bytes = [0; size_of::<IncomingDataPacket>()];
+            bytes.copy_from_slice(timestamp);
+            IncomingDataPacket::from_le_bytes(bytes)
+        })
+        .collect())
+}
+
+/// Write a capture of timestamps, which can be read with [`read_capture`].
+///
+/// # Errors
+/// Returns an error if writing fails.
+pub fn write_capture(mut writer: impl Write, timestamps: &[IncomingDataPacket]) -> io::Result<()> {
+    for timestamp in timestamps {
+        writer.write_all(&timestamp.to_le_bytes())?;
+    }
+
+    writer.flush()
+}
+
+/// The intervals between successive timestamps. Timestamps preceding their predecessor are skipped.
+#[must_use]
+pub fn intervals(timestamps: &[IncomingDataPacket]) -> Vec<u128> {
+    let mut last = None;
+    timestamps
+        .iter()
+        .filter_map(|&timestamp| interval(&mut last, timestamp))
+        .collect()
+}
+
+/// Sort the intervals into the bins of `extractor`, which yields one sample of
+/// [`IntervalToBits::bits_per_interval`] bits per interval. Intervals taken for a baseline yield no sample.
+#[must_use]
+pub fn bin_samples(intervals: &[u128], extractor: &mut IntervalToBits) -> Vec<u32> {
+    #[expect(clippy::cast_precision_loss)]
+    intervals
+        .iter()
+        .filter_map(|&interval| extractor.take_interval(interval as f64))
+        .collect()
+}
+
+/// Take the lowest `bits` bits of every interval as a sample.
+///
+/// # Panics
+/// Panics if `bits` is not within `1..=32`.
+#[must_use]
+pub fn low_bit_samples(intervals: &[u128], bits: u32) -> Vec<u32> {
+    assert!((1..=u32::BITS).contains(&bits), "a sample has 1 to 32 bits");
+
+    #[expect(clippy::cast_possible_truncation)]
+    intervals
+        .iter()
+        .map(|&interval| (interval & ((1 << bits) - 1)) as u32)
+        .collect()
+}
+
+/// The min-entropy estimates of a sequence of samples, in bits per sample. Estimates which cannot be made, because
+/// they only apply to bits or because there are too few samples, are `None`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Estimates {
+    /// The most common value estimate (6.3.1).
+    pub most_common_value: f64,
+    /// The collision estimate (6.3.2), for bits only.
+    pub collision: Option<f64>,
+    /// The Markov estimate (6.3.3), for bits only.
+    pub markov: Option<f64>,
+    /// The compression estimate (6.3.4), for bits only.
+    pub compression: Option<f64>,
+    /// The t-tuple estimate (6.3.5).
+    pub t_tuple: Option<f64>,
+    /// The longest repeated substring estimate (6.3.6).
+    pub longest_repeated_substring: Option<f64>,
+}
+
+impl Estimates {
+    /// Run the estimators which apply to samples of any width.
+    ///
+    /// # Panics
+    /// Panics if there are less than two samples.
+    #[must_use]
+    pub fn of_samples(samples: &[u32]) -> Self {
+        assert!(
+            samples.len() >= 2,
+            "estimating requires at least two samples"
+        );
+
+        let tuples = Tuples::count(samples);
+        Self {
+            most_common_value: most_common_value(samples),
+            collision: None,
+            markov: None,
+            compression: None,
+            t_tuple: tuples.t_tuple(samples.len()),
+            longest_repeated_substring: tuples.longest_repeated_substring(samples.len()),
+        }
+    }
+
+    /// Run all estimators on bits.
+    ///
+    /// # Panics
+    /// Panics if there are less than two bits.
+    #[must_use]
+    pub fn of_bits(bits: &[bool]) -> Self {
+        let samples = bits.iter().map(|&bit| u32::from(bit)).collect::<Vec<_>>();
+
+        Self {
+            collision: collision(bits),
+            markov: Some(markov(bits)),
+            compression: compression(bits),
+            ..Self::of_samples(&samples)
+        }
+    }
+
+    /// The least of all estimates.
+    #[must_use]
+    pub fn min_entropy(&self) -> f64 {
+        [
+            self.collision,
+            self.markov,
+            self.compression,
+            self.t_tuple,
+            self.longest_repeated_substring,
+        ]
+        .into_iter()
+        .flatten()
+        .fold(self.most_common_value, f64::min)
+    }
+}
+
+/// The estimates of samples of a given width and of their bits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    /// The bits per sample.
+    pub sample_bits: u32,
+    /// The estimates of the samples, in bits per sample.
+    pub samples: Estimates,
+    /// The estimates of the bits of the samples, most significant bit first, in bits per bit.
+    pub bits: Estimates,
+}
+
+impl Report {
+    /// Run the estimators on samples of `sample_bits` bits each and on their bits.
+    ///
+    /// # Panics
+    /// Panics if there are less than two samples, or if `sample_bits` is not within `1..=32`.
+    #[must_use]
+    pub fn new(samples: &[u32], sample_bits: u32) -> Self {
+        assert!(
+            (1..=u32::BITS).contains(&sample_bits),
+            "a sample has 1 to 32 bits"
+        );
+
+        let bits = samples
+            .iter()
+            .flat_map(|sample| {
+                (0..sample_bits)
+                    .rev()
+                    .map(move |bit| sample >> bit & 1 == 1)
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            sample_bits,
+            samples: Estimates::of_samples(samples),
+            bits: Estimates::of_bits(&bits),
+        }
+    }
+
+    /// The min-entropy per sample, which is the least of the estimates of the samples and those of their bits, scaled
+    /// by the bits per sample.
+    #[must_use]
+    pub fn min_entropy(&self) -> f64 {
+        self.samples
+            .min_entropy()
+            .min(f64::from(self.sample_bits) * self.bits.min_entropy())
+    }
+}
+
+/// The min-entropy of an outcome of probability `p`, which is `0` rather than `-0` if it is certain.
+fn entropy(p: f64) -> f64 {
+    p.recip().log2()
+}
+
+/// The upper bound of the confidence interval of the probability `p`, estimated from `len` samples.
+#[expect(clippy::cast_precision_loss)]
+fn upper_bound(p: f64, len: usize) -> f64 {
+    (p + Z_ALPHA * (p * (1.0 - p) / (len - 1) as f64).sqrt()).min(1.0)
+}
+
+/// The most common value estimate.
+#[expect(clippy::cast_precision_loss)]
+fn most_common_value(samples: &[u32]) -> f64 {
+    let mut counts = HashMap::<u32, u64>::new();
+    for &sample in samples {
+        *counts.entry(sample).or_default() += 1;
+    }
+
+    let most_common = counts.into_values().max().unwrap_or_default();
+    entropy(upper_bound(
+        most_common as f64 / samples.len() as f64,
+        samples.len(),
+    ))
+}
+
+/// The collision estimate, or `None` if there are less than two collisions.
+#[expect(clippy::cast_precision_loss)]
+fn collision(bits: &[bool]) -> Option<f64> {
+    // among two bits which differ, the third always collides with one of them
+    let mut times = Vec::new();
+    let mut i = 0;
+    while i + 1 < bits.len() {
+        let time = if bits[i] == bits[i + 1] { 2 } else { 3 };
+        if i + time > bits.len() {
+            break;
+        }
+        times.push(time as f64);
+        i += time;
+    }
+
+    if times.len() < 2 {
+        return None;
+    }
+
+    let v = times.len() as f64;
+    let mean = times.iter().sum::<f64>() / v;
+    let deviation = (times.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / (v - 1.0)).sqrt();
+    let mean = mean - Z_ALPHA * deviation / v.sqrt();
+
+    // for bits, the expected time is 2 + 2p(1 - p), which is solved for the larger p
+    let p = (0.5 + (1.25 - 0.5 * mean).max(0.0).sqrt()).min(1.0);
+    Some(entropy(p))
+}
+
+/// The Markov estimate, from the probability of the most likely sequence of 128 bits.
+#[expect(clippy::cast_precision_loss)]
+fn markov(bits: &[bool]) -> f64 {
+    let ones = bits.iter().filter(|&&bit| bit).count() as f64;
+    let p1 = ones / bits.len() as f64;
+    let p0 = 1.0 - p1;
+
+    // the transitions from a bit to the next, indexed by the bits as `[from][to]`
+    let mut transitions = [[0_u64; 2]; 2];
+    for pair in bits.windows(2) {
+        transitions[usize::from(pair[0])][usize::from(pair[1])] += 1;
+    }
+    let [[p00, p01], [p10, p11]] = transitions.map(|[to0, to1]| {
+        let total = (to0 + to1).max(1) as f64;
+        [to0 as f64 / total, to1 as f64 / total]
+    });
+
+    let p_max = [
+        p0 * p00.powi(127),
+        p0 * p01.powi(64) * p10.powi(63),
+        p0 * p01 * p11.powi(126),
+        p1 * p10 * p00.powi(126),
+        p1 * p10.powi(64) * p01.powi(63),
+        p1 * p11.powi(127),
+    ]
+    .into_iter()
+    .fold(0.0, f64::max);
+
+    (entropy(p_max) / 128.0).min(1.0)
+}
+
+/// The compression estimate, or `None` if there are too few bits to initialize the dictionary.
+#[expect(clippy::cast_precision_loss)]
+fn compression(bits: &[bool]) -> Option<f64> {
+    let blocks = bits
+        .chunks_exact(COMPRESSION_BLOCK_LEN as usize)
+        .map(|block| {
+            block
+                .iter()
+                .fold(0, |acc, &bit| acc << 1 | usize::from(bit))
+        })
+        .collect::<Vec<_>>();
+    let n = blocks.len();
+    if n < COMPRESSION_DICTIONARY_LEN + 2 {
+        return None;
+    }
+
+    // the index of the last occurrence of every block, counted from 1
+    let mut dictionary = [0; 1 << COMPRESSION_BLOCK_LEN];
+    for (i, &block) in blocks[..COMPRESSION_DICTIONARY_LEN].iter().enumerate() {
+        dictionary[block] = i + 1;
+    }
+
+    let mut distances = Vec::with_capacity(n - COMPRESSION_DICTIONARY_LEN);
+    for (i, &block) in blocks.iter().enumerate().skip(COMPRESSION_DICTIONARY_LEN) {
+        let previous = std::mem::replace(&mut dictionary[block], i + 1);
+        distances.push(((i + 1 - previous) as f64).log2());
+    }
+
+    let v = distances.len() as f64;
+    let mean = distances.iter().sum::<f64>() / v;
+    let deviation = 0.5907
+        * (distances.iter().map(|d| d * d).sum::<f64>() / (v - 1.0) - mean * mean)
+            .max(0.0)
+            .sqrt();
+    let mean = mean - Z_ALPHA * deviation / v.sqrt();
+
+    // the expected mean decreases with p, so that it is solved by bisection
+    let min_p = 2_f64.powi(-COMPRESSION_BLOCK_LEN.cast_signed());
+    let logs = (1..=n).map(|u| (u as f64).log2()).collect::<Vec<_>>();
+    let p = if mean >= compression_expectation(min_p, &logs) {
+        min_p
+    } else {
+        let (mut low, mut high) = (min_p, 1.0);
+        for _ in 0..64 {
+            let p = f64::midpoint(low, high);
+            if compression_expectation(p, &logs) > mean {
+                low = p;
+            } else {
+                high = p;
+            }
+        }
+        low
+    };
+
+    Some(entropy(p) / f64::from(COMPRESSION_BLOCK_LEN))
+}
+
+/// The expected mean of the logarithmic distances of as many blocks as there are `logs`, the binary logarithms of all
+/// distances from `1`, if the most likely block has the probability `p` and all others share the rest equally.
+fn compression_expectation(p: f64, logs: &[f64]) -> f64 {
+    let others = f64::from((1 << COMPRESSION_BLOCK_LEN) - 1);
+    compression_g(p, logs) + others * compression_g((1.0 - p) / others, logs)
+}
+
+/// The function `G` of the compression estimate, summed in linear time.
+#[expect(clippy::cast_precision_loss)]
+fn compression_g(z: f64, logs: &[f64]) -> f64 {
+    let (n, d) = (logs.len(), COMPRESSION_DICTIONARY_LEN);
+    let mut sum = 0.0;
+    // (1 - z)^(u - 1)
+    let mut power = 1.0;
+
+    for (u, &log) in (1..=n).zip(logs) {
+        // the remaining terms are negligible, and computing them with subnormal numbers slow
+        if power < f64::MIN_POSITIVE {
+            break;
+        }
+        // the distance u to every later block t, which is not the first occurrence since the dictionary
+        if u < n {
+            sum += z * z * power * log * (n - d.max(u)) as f64;
+        }
+        // the first occurrence of a block at t = u
+        if u > d {
+            sum += z * power * log;
+        }
+        power *= 1.0 - z;
+    }
+
+    sum / (n - d) as f64
+}
+
+/// The occurrences of the tuples of every length of a sequence of samples, indexed by the length.
+struct Tuples {
+    /// How often the most common tuple occurs.
+    most_common: Vec<u64>,
+    /// The amount of pairs of equal tuples.
+    pairs: Vec<u64>,
+}
+
+impl Tuples {
+    /// Count the tuples of every length which occurs more than once, using the intervals of the longest common prefixes
+    /// of the sorted suffixes of `samples`. Each such interval contains the suffixes starting with the same tuples of
+    /// the lengths between the longest common prefix of its parent and its own.
+    fn count(samples: &[u32]) -> Self {
+        let suffixes = suffix_array(samples);
+        let lcp = longest_common_prefixes(samples, &suffixes);
+        let longest = lcp.iter().copied().max().unwrap_or_default();
+
+        let mut tuples = Self {
+            most_common: vec![1; longest + 1],
+            pairs: vec![0; longest + 1],
+        };
+        // the longest common prefix and the left bound of every open interval
+        let mut stack = vec![(0, 0)];
+
+        for i in 1..=samples.len() {
+            let prefix = lcp.get(i).copied().unwrap_or_default();
+            let mut left = i - 1;
+
+            while let Some(&(top, top_left)) = stack.last()
+                && prefix < top
+            {
+                stack.pop();
+                let parent = stack.last().map_or(0, |&(parent, _)| parent).max(prefix);
+                let size = (i - top_left) as u64;
+                for len in parent + 1..=top {
+                    tuples.most_common[len] = tuples.most_common[len].max(size);
+                    tuples.pairs[len] += size * (size - 1) / 2;
+                }
+                left = top_left;
+            }
+
+            if stack.last().is_none_or(|&(top, _)| prefix > top) {
+                stack.push((prefix, left));
+            }
+        }
+
+        tuples
+    }
+
+    /// The largest length whose most common tuple occurs at least [`TUPLE_CUTOFF`] times, or `0`.
+    fn cutoff_len(&self) -> usize {
+        self.most_common
+            .iter()
+            .rposition(|&count| count >= TUPLE_CUTOFF)
+            .unwrap_or_default()
+    }
+
+    /// The t-tuple estimate of `len` samples, or `None` if no sample occurs [`TUPLE_CUTOFF`] times.
+    #[expect(clippy::cast_precision_loss)]
+    fn t_tuple(&self, len: usize) -> Option<f64> {
+        let p = (1..=self.cutoff_len())
+            .map(|t| (self.most_common[t] as f64 / (len - t + 1) as f64).powf(1.0 / t as f64))
+            .reduce(f64::max)?;
+
+        Some(entropy(upper_bound(p, len)))
+    }
+
+    /// The longest repeated substring estimate of `len` samples, or `None` if no tuple longer than those of the
+    /// t-tuple estimate occurs twice.
+    #[expect(clippy::cast_precision_loss)]
+    fn longest_repeated_substring(&self, len: usize) -> Option<f64> {
+        let p = (self.cutoff_len() + 1..self.pairs.len())
+            .map(|w| {
+                let tuples = (len - w + 1) as f64;
+                (self.pairs[w] as f64 / (tuples * (tuples - 1.0) / 2.0)).powf(1.0 / w as f64)
+            })
+            .reduce(f64::max)?;
+
+        Some(entropy(upper_bound(p, len)))
+    }
+}
+
+/// The starts of the suffixes of `samples` in lexicographic order, sorted by prefix doubling with a radix sort.
+fn suffix_array(samples: &[u32]) -> Vec<usize> {
+    let n = samples.len();
+    let mut values = samples.to_vec();
+    values.sort_unstable();
+    values.dedup();
+
+    // the rank of every suffix among the distinct prefixes of length `len`
+    let mut rank = samples
+        .iter()
+        .map(|&sample| values.partition_point(|&value| value < sample))
+        .collect::<Vec<_>>();
+    let mut classes = values.len();
+    let mut suffixes = (0..n).collect::<Vec<_>>();
+    suffixes.sort_unstable_by_key(|&i| rank[i]);
+
+    let mut by_second = Vec::with_capacity(n);
+    let mut next_rank = vec![0; n];
+    let mut starts = Vec::new();
+    let mut len = 1;
+
+    while classes < n {
+        // sorted by the second half of the prefix of length 2 * len, where the suffixes without one come first
+        by_second.clear();
+        by_second.extend(n - len..n);
+        by_second.extend(suffixes.iter().filter(|&&i| i >= len).map(|&i| i - len));
+
+        // stably sorted by the first half
+        starts.clear();
+        starts.resize(classes, 0);
+        for &i in &by_second {
+            starts[rank[i]] += 1;
+        }
+        let mut start = 0;
+        for count in &mut starts {
+            start += std::mem::replace(count, start);
+        }
+        for &i in &by_second {
+            suffixes[starts[rank[i]]] = i;
+            starts[rank[i]] += 1;
+        }
+
+        let key = |i: usize| (rank[i], rank.get(i + len));
+        next_rank[suffixes[0]] = 0;
+        classes = 1;
+        for pair in suffixes.windows(2) {
+            classes += usize::from(key(pair[0]) != key(pair[1]));
+            next_rank[pair[1]] = classes - 1;
+        }
+        std::mem::swap(&mut rank, &mut next_rank);
+        len *= 2;
+    }
+
+    suffixes
+}
+
+/// The longest common prefix of every suffix in `suffixes` and the one preceding it, or `0` for the first one
+/// (Kasai's algorithm).
+fn longest_common_prefixes(samples: &[u32], suffixes: &[usize]) -> Vec<usize> {
+    let n = samples.len();
+    let mut rank = vec![0; n];
+    for (i, &suffix) in suffixes.iter().enumerate() {
+        rank[suffix] = i;
+    }
+
+    let mut lcp = vec![0; n];
+    let mut prefix = 0;
+    for i in 0..n {
+        if rank[i] == 0 {
+            prefix = 0;
+            continue;
+        }
+
+        let j = suffixes[rank[i] - 1];
+        while i + prefix < n && j + prefix < n && samples[i + prefix] == samples[j + prefix] {
+            prefix += 1;
+        }
+        lcp[rank[i]] = prefix;
+        prefix = prefix.saturating_sub(1);
+    }
+
+    lcp
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests of the captures and of the estimators.
+
+    use std::io::ErrorKind;
+
+    use super::{
+        Estimates, Report, Tuples, collision, compression, intervals, longest_common_prefixes,
+        low_bit_samples, markov, read_capture, suffix_array, write_capture,
+    };
+
+    /// `len` pseudo-random samples of `bits` bits each, from a xorshift generator.
+    fn random_samples(len: usize, bits: u32) -> Vec<u32> {
+        let mut state = 0x9E37_79B9_7F4A_7C15_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let sample = (state >> 32) as u32;
+                sample >> (u32::BITS - bits)
+            })
+            .collect()
+    }
+
+    /// A capture is read as it was written, and one ending within a timestamp is rejected.
+    #[test]
+    fn captures_round_trip() {
+        let timestamps = [0, 1, u128::MAX, 1_700_000_000_000_000];
+        let mut capture = Vec::new();
+        write_capture(&mut capture, &timestamps).unwrap();
+        assert_eq!(read_capture(capture.as_slice()).unwrap(), timestamps);
+
+        capture.pop();
+        let error = read_capture(capture.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    /// Intervals skip timestamps preceding their predecessor, and samples take the lowest bits of the intervals.
+    #[test]
+    fn samples_of_intervals() {
+        let intervals = intervals(&[10, 15, 12, 30, 30]);
+        assert_eq!(intervals, [5, 15, 0]);
+        assert_eq!(low_bit_samples(&intervals, 2), [1, 3, 0]);
+        assert_eq!(low_bit_samples(&[u128::MAX], 32), [u32::MAX]);
+    }
+
+    /// The suffix array, longest common prefixes and tuple counts match those found naively.
+    #[test]
+    fn tuples_match_naive_counts() {
+        let samples = random_samples(300, 2);
+        let n = samples.len();
+
+        let mut naive = (0..n).collect::<Vec<_>>();
+        naive.sort_by_key(|&i| &samples[i..]);
+        let suffixes = suffix_array(&samples);
+        assert_eq!(suffixes, naive);
+
+        let lcp = longest_common_prefixes(&samples, &suffixes);
+        for (i, pair) in suffixes.windows(2).enumerate() {
+            let prefix = samples[pair[0]..]
+                .iter()
+                .zip(&samples[pair[1]..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            assert_eq!(lcp[i + 1], prefix);
+        }
+
+        let tuples = Tuples::count(&samples);
+        for len in 1..tuples.most_common.len() {
+            let mut counts = std::collections::HashMap::<&[u32], u64>::new();
+            for tuple in samples.windows(len) {
+                *counts.entry(tuple).or_default() += 1;
+            }
+            let most_common = counts.values().copied().max().unwrap();
+            let pairs = counts.values().map(|c| c * (c - 1) / 2).sum::<u64>();
+            assert_eq!(
+                (tuples.most_common[len], tuples.pairs[len]),
+                (most_common, pairs),
+                "tuples of {len} samples"
+            );
+        }
+    }
+
+    /// Constant bits carry no entropy.
+    #[test]
+    fn constant_bits_have_no_entropy() {
+        let bits = [true; 10_000];
+
+        let estimates = Estimates::of_bits(&bits);
+        assert!(estimates.most_common_value.abs() < 1e-9);
+        assert!(estimates.min_entropy().abs() < 1e-3);
+        assert!(collision(&bits).unwrap().abs() < 1e-9);
+        assert!(markov(&bits).abs() < 1e-9);
+        assert!(compression(&bits).unwrap() < 1e-3);
+    }
+
+    /// Alternating bits are balanced, but entirely predictable, which only the Markov estimate notices among those on
+    /// single bits.
+    #[test]
+    fn alternating_bits_are_predictable() {
+        let bits = (0..10_000).map(|i| i % 2 == 0).collect::<Vec<_>>();
+
+        let estimates = Estimates::of_bits(&bits);
+        assert!(estimates.most_common_value > 0.95);
+        assert!((markov(&bits) - 1.0 / 128.0).abs() < 1e-9);
+        assert!(estimates.min_entropy() < 0.01);
+    }
+
+    /// Pseudo-random samples are estimated to carry most of their bits as entropy, but not more. The estimates are
+    /// lower bounds, of which those of the collision and compression estimates are the most conservative.
+    #[test]
+    fn random_samples_have_high_entropy() {
+        let samples = random_samples(100_000, 4);
+        let report = Report::new(&samples, 4);
+
+        assert!(report.samples.t_tuple.is_some());
+        assert!(report.bits.compression.is_some());
+        assert!(report.samples.most_common_value > 3.9);
+        assert!((3.0..=4.0).contains(&report.min_entropy()), "{report:?}");
+    }
+}
@@ -0,0 +1,806 @@
+//! Extraction of random bits from the intervals between detector events.
+//!
+//! Radioactive decays are independent of each other, so the intervals between successive events are exponentially
+//! distributed. Every extractor implements [`Extractor`], which takes the timestamps of the events one by one:
+//!
+//! + [`IntervalToBits`] sorts each interval into one of several equiprobable bins of that distribution, whose rate is
+//!   estimated from a baseline of intervals.
+//! + [`Comparison`] compares the intervals of successive pairs, which yields one bit per pair.
+//!
+//! The output of any extractor can be debiased further with [`VonNeumann`] or [`Peres`], and its yield per event
+//! measured with [`Metered`]. Lost events are reported to an extractor with [`Extractor::gap`], so that it does not
+//! take the interval spanning them.
+//!
+//! # Example
+//! ```no_run
+//! use tdtp::{
+//!     client::data,
+//!     client_mpsc::client_channel,
+//!     extract::{Comparison, Extractor, Metered, VonNeumann},
+//! };
+//!
+//! let (tx, rx) = client_channel(8192);
+//! std::thread::spawn(move || data("localhost:8000", tx));
+//!
+//! let mut extractor = Metered::new(VonNeumann::new(Comparison::new()));
+//! let mut bits = Vec::new();
+//! while let Ok(timestamp) = rx.recv() {
+//!     extractor.push(timestamp, &mut bits);
+//! }
+//!
+//! println!("Extracted {:.3} bits per event", extractor.bits_per_event());
+//! ```
+
+use log::{debug, info, warn};
+
+use crate::client::IncomingDataPacket;
+
+/// The default amount of intervals the rate of the intervals is estimated from.
+pub const DEFAULT_BASELINE_LEN: usize = 10_000;
+
+/// The default amount of intervals after which the rate of the intervals is tested for drift.
+pub const DEFAULT_TEST_INTERVAL: usize = 10_000;
+
+/// The default critical value of the drift test, which corresponds to a confidence of about 99%.
+pub const DEFAULT_T_CRIT: f64 = 2.58;
+
+/// The default amount of bits [`Peres`] debiases at once.
+pub const DEFAULT_PERES_BLOCK_LEN: usize = 1024;
+
+/// An extractor of random bits from the timestamps of detector events.
+///
+/// The timestamps are those received from the server, i.e. [`IncomingDataPacket`]s, in the order they were received.
+pub trait Extractor {
+    /// Take the timestamp of the next event, and append the bits extracted to `bits`, if any.
+    fn push(&mut self, timestamp: IncomingDataPacket, bits: &mut Vec<bool>);
+
+    /// Forget the previous events, since the events following them were lost, e.g. on a
+    /// [`ClientEvent::Gap`](crate::client::ClientEvent::Gap).
+    ///
+    /// The interval spanning lost events is longer than any interval between successive events, so an extractor
+    /// neither takes it nor pairs anything from before the gap with anything after it. Defaults to doing nothing, which
+    /// suits extractors without state.
+    fn gap(&mut self) {}
+
+    /// Whether no more bits will be extracted, e.g. because a health test failed. Defaults to `false`.
+    fn is_halted(&self) -> bool {
+        false
+    }
+
+    /// Take the timestamps of the given events, and return the bits extracted.
+    fn extract(&mut self, timestamps: impl IntoIterator<Item = IncomingDataPacket>) -> Vec<bool>
+    where
+        Self: Sized,
+    {
+        let mut bits = Vec::new();
+        for timestamp in timestamps {
+            self.push(timestamp, &mut bits);
+        }
+        bits
+    }
+}
+
+impl<E: Extractor + ?Sized> Extractor for Box<E> {
+    fn push(&mut self, timestamp: IncomingDataPacket, bits: &mut Vec<bool>) {
+        (**self).push(timestamp, bits);
+    }
+
+    fn gap(&mut self) {
+        (**self).gap();
+    }
+
+    fn is_halted(&self) -> bool {
+        (**self).is_halted()
+    }
+}
+
+/// Extracts bits from the intervals between detector events by sorting them into equiprobable quantile bins.
+///
+/// The first [`DEFAULT_BASELINE_LEN`] intervals form the baseline, from which the rate `λ` of the exponential
+/// distribution of the intervals is estimated as the inverse of their mean. The distribution is split into `2^n` bins
+/// of equal probability, bounded by the quantiles `-ln(1 - k / 2^n) / λ`, where `2^n` is the largest power of two not
+/// exceeding `round(sqrt(baseline_len))`. Every further interval yields the `n` bits of the index of its bin, most
+/// significant bit first. No bits are yielded while the baseline is collected.
+///
+/// The rate of the source may drift, e.g. as the detector warms up, upon which the bins are no longer equiprobable. To
+/// notice this, every window of [`DEFAULT_TEST_INTERVAL`] intervals is compared to the baseline with Welch's t-test.
+/// If `t` exceeds [`DEFAULT_T_CRIT`], the interval completing the window is discarded and a new baseline is collected.
+///
+/// # Example
+/// ```
+/// use tdtp::extract::{Extractor, IntervalToBits};
+///
+/// let mut extractor = IntervalToBits::new().with_baseline_len(100);
+/// let mut bits = Vec::new();
+///
+/// let mut timestamp = 1_700_000_000_000_000;
+/// for i in 0..200 {
+///     timestamp += 1 + (i * 7919) % 1000;
+///     extractor.push(timestamp, &mut bits);
+/// }
+///
+/// // round(sqrt(100)) = 10, so the intervals are sorted into 8 bins of 3 bits each
+/// assert_eq!(extractor.bits_per_interval(), 3);
+/// // 200 timestamps make 199 intervals, of which the first 100 form the baseline
+/// assert_eq!(bits.len(), 3 * 99);
+/// ```
+#[derive(Debug, Clone)]
+pub struct IntervalToBits {
+    /// How many intervals form a baseline.
+    baseline_len: usize,
+    /// After how many intervals the rate is tested for drift.
+    test_interval: usize,
+    /// The critical value of the drift test.
+    t_crit: f64,
+    /// The bits yielded per interval.
+    bits: u32,
+    /// The previous timestamp, which the next interval starts at.
+    last: Option<IncomingDataPacket>,
+    /// The intervals of the baseline.
+    baseline: Vec<f64>,
+    /// The upper bounds of all bins but the last, or none while the baseline is collected.
+    quantiles: Vec<f64>,
+    /// The intervals since the last drift test.
+    window: Vec<f64>,
+    /// How often a new baseline was collected because the rate drifted.
+    rebaselines: u64,
+}
+
+impl Default for IntervalToBits {
+    fn default() -> Self {
+        Self {
+            baseline_len: DEFAULT_BASELINE_LEN,
+            test_interval: DEFAULT_TEST_INTERVAL,
+            t_crit: DEFAULT_T_CRIT,
+            bits: bits_for(DEFAULT_BASELINE_LEN),
+            last: None,
+            baseline: Vec::with_capacity(DEFAULT_BASELINE_LEN),
+            quantiles: Vec::new(),
+            window: Vec::with_capacity(DEFAULT_TEST_INTERVAL),
+            rebaselines: 0,
+        }
+    }
+}
+
+impl IntervalToBits {
+    /// Create an extractor with the default settings.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how many intervals form a baseline, which also determines the bits yielded per interval. Defaults to
+    /// [`DEFAULT_BASELINE_LEN`].
+    ///
+    /// # Panics
+    /// Panics if `baseline_len` is less than `2`, since the variance of the baseline is undefined then.
+    #[must_use]
+    pub fn with_baseline_len(mut self, baseline_len: usize) -> Self {
+        assert!(baseline_len >= 2, "a baseline needs at least two intervals");
+        self.baseline_len = baseline_len;
+        self.bits = bits_for(baseline_len);
+        self
+    }
+
+    /// Set after how many intervals the rate is tested for drift. Defaults to [`DEFAULT_TEST_INTERVAL`].
+    ///
+    /// # Panics
+    /// Panics if `test_interval` is less than `2`, since the variance of the intervals tested is undefined then.
+    #[must_use]
+    pub fn with_test_interval(mut self, test_interval: usize) -> Self {
+        assert!(
+            test_interval >= 2,
+            "a drift test needs at least two intervals"
+        );
+        self.test_interval = test_interval;
+        self
+    }
+
+    /// Set the critical value of the drift test. Defaults to [`DEFAULT_T_CRIT`].
+    #[must_use]
+    pub fn with_t_crit(mut self, t_crit: f64) -> Self {
+        self.t_crit = t_crit;
+        self
+    }
+
+    /// Take the next interval, and return the index of its bin, or `None` while the baseline is collected, or if the
+    /// rate drifted and a new baseline is collected.
+    pub fn take_interval(&mut self, interval: f64) -> Option<u32> {
+        if self.quantiles.is_empty() {
+            self.baseline.push(interval);
+            if self.baseline.len() == self.baseline_len {
+                self.create_bins();
+            }
+            return None;
+        }
+
+        let bin = self.bin(interval);
+        self.window.push(interval);
+        if self.window.len() == self.test_interval {
+            if self.drifted() {
+                self.rebaseline();
+                return None;
+            }
+            self.window.clear();
+        }
+
+        bin
+    }
+
+    /// The index of the bin the given interval falls into, or `None` while the baseline is collected.
+    #[must_use]
+    pub fn bin(&self, interval: f64) -> Option<u32> {
+        if self.quantiles.is_empty() {
+            return None;
+        }
+
+        #[expect(clippy::cast_possible_truncation)]
+        Some(self.quantiles.partition_point(|&q| q <= interval) as u32)
+    }
+
+    /// The bits yielded per interval.
+    #[must_use]
+    pub fn bits_per_interval(&self) -> u32 {
+        self.bits
+    }
+
+    /// The upper bounds of all bins but the last, which is unbounded, or an empty slice while the baseline is collected.
+    #[must_use]
+    pub fn quantiles(&self) -> &[f64] {
+        &self.quantiles
+    }
+
+    /// How often a new baseline was collected because the rate drifted.
+    #[must_use]
+    pub fn rebaselines(&self) -> u64 {
+        self.rebaselines
+    }
+
+    /// Estimate the rate from the baseline, and split its distribution into equiprobable bins.
+    fn create_bins(&mut self) {
+        let (mean, _) = mean_variance(&self.baseline);
+        let lambda = mean.recip();
+        let bins = 1_u32 << self.bits;
+
+        self.quantiles = (1..bins)
+            .map(|k| -(-f64::from(k) / f64::from(bins)).ln_1p() / lambda)
+            .collect();
+        info!("Created {bins} bins from a baseline with rate {lambda} per microsecond");
+    }
+
+    /// Whether the rate of the intervals since the last test drifted from that of the baseline, according to Welch's
+    /// t-test.
+    #[expect(clippy::cast_precision_loss)]
+    fn drifted(&self) -> bool {
+        let (mean_baseline, var_baseline) = mean_variance(&self.baseline);
+        let (mean_window, var_window) = mean_variance(&self.window);
+
+        let t = (mean_baseline - mean_window).abs()
+            / (var_baseline / self.baseline.len() as f64 + var_window / self.window.len() as f64)
+                .sqrt();
+        debug!("Tested the rate for drift, t = {t}");
+
+        t > self.t_crit
+    }
+
+    /// Discard the baseline and the bins, so that a new baseline is collected.
+    fn rebaseline(&mut self) {
+        warn!("The rate of the intervals drifted, collecting a new baseline");
+        self.baseline.clear();
+        self.quantiles.clear();
+        self.window.clear();
+        self.rebaselines += 1;
+    }
+}
+
+impl Extractor for IntervalToBits {
+    /// Take the timestamp of the next event, and append the bits extracted from the interval since the previous event
+    /// to `bits`, if any.
+    ///
+    /// A timestamp which precedes the previous one is skipped.
+    fn push(&mut self, timestamp: IncomingDataPacket, bits: &mut Vec<bool>) {
+        #[expect(clippy::cast_precision_loss)]
+        let Some(interval) = interval(&mut self.last, timestamp).map(|interval| interval as f64)
+        else {
+            return;
+        };
+
+        if let Some(bin) = self.take_interval(interval) {
+            bits.extend((0..self.bits).rev().map(|bit| bin >> bit & 1 == 1));
+        }
+    }
+
+    /// Forget the previous timestamp, so that the next interval starts at the event following the gap. The baseline
+    /// and the window of the drift test are kept, since the rate does not change with lost events.
+    fn gap(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Extracts bits by comparing the intervals of successive pairs of intervals.
+///
+/// The intervals are split into disjoint pairs `(t1, t2)`, each of which yields `0` if `t1 < t2` and `1` if `t1 > t2`.
+/// Since both intervals follow the same distribution, either is equally likely, regardless of the rate of the source.
+/// A pair of equal intervals, which the resolution of the timestamps makes possible, is discarded.
+///
+/// # Example
+/// ```
+/// use tdtp::extract::{Comparison, Extractor};
+///
+/// let mut extractor = Comparison::new();
+/// // the intervals are 5, 3, then 2, 7, then 4, 4
+/// let bits = extractor.extract([0, 5, 8, 10, 17, 21, 25]);
+/// assert_eq!(bits, [true, false]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Comparison {
+    /// The previous timestamp, which the next interval starts at.
+    last: Option<IncomingDataPacket>,
+    /// The first interval of the current pair, if it was taken already.
+    first: Option<IncomingDataPacket>,
+}
+
+impl Comparison {
+    /// Create a comparison extractor.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Extractor for Comparison {
+    /// Take the timestamp of the next event. Every second interval completes a pair, whose bit is appended to `bits`
+    /// unless both intervals are equal.
+    ///
+    /// A timestamp which precedes the previous one is skipped.
+    fn push(&mut self, timestamp: IncomingDataPacket, bits: &mut Vec<bool>) {
+        let Some(interval) = interval(&mut self.last, timestamp) else {
+            return;
+        };
+
+        match self.first.take() {
+            None => self.first = Some(interval),
+            Some(first) if first != interval => bits.push(first > interval),
+            Some(_) => (),
+        }
+    }
+
+    /// Forget the previous timestamp and the first interval of the current pair, so that the next pair starts after
+    /// the gap.
+    fn gap(&mut self) {
+        self.last = None;
+        self.first = None;
+    }
+}
+
+/// Debiases the bits of another extractor with Von Neumann's method.
+///
+/// The bits are split into disjoint pairs, of which `01` yields `0` and `10` yields `1`, while `00` and `11` are
+/// discarded. If the bits are independent, the output is unbiased, at the cost of yielding at most a quarter of the
+/// bits.
+///
+/// # Example
+/// ```
+/// use tdtp::extract::{Comparison, Extractor, VonNeumann};
+///
+/// let mut extractor = VonNeumann::new(Comparison::new());
+/// // the comparisons yield 1, 1, then 1, 0
+/// let bits = extractor.extract([0, 5, 8, 18, 20, 23, 25, 26, 29]);
+/// assert_eq!(bits, [true]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct VonNeumann<E> {
+    /// The extractor whose bits are debiased.
+    inner: E,
+    /// The first bit of the current pair, if it was taken already.
+    first: Option<bool>,
+    /// The bits the inner extractor yielded for the current event, before they are paired.
+    raw: Vec<bool>,
+}
+
+impl<E: Extractor> VonNeumann<E> {
+    /// Debias the bits of `inner`.
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            first: None,
+            raw: Vec::new(),
+        }
+    }
+
+    /// The extractor which yields the bits that are paired.
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// Mutable access to the extractor which yields the bits that are paired. A pair which was started is completed
+    /// by the next bit this extractor takes from it.
+    pub fn inner_mut(&mut self) -> &mut E {
+        &mut self.inner
+    }
+}
+
+impl<E: Extractor> Extractor for VonNeumann<E> {
+    fn push(&mut self, timestamp: IncomingDataPacket, bits: &mut Vec<bool>) {
+        self.raw.clear();
+        self.inner.push(timestamp, &mut self.raw);
+
+        for &bit in &self.raw {
+            match self.first.take() {
+                None => self.first = Some(bit),
+                Some(first) if first != bit => bits.push(first),
+                Some(_) => (),
+            }
+        }
+    }
+
+    /// Discard the first bit of the current pair, and forward the gap to the inner extractor.
+    fn gap(&mut self) {
+        self.first = None;
+        self.inner.gap();
+    }
+
+    fn is_halted(&self) -> bool {
+        self.inner.is_halted()
+    }
+}
+
+/// Debiases the bits of another extractor with Peres' method, which iterates Von Neumann's method.
+///
+/// Like [`VonNeumann`], this yields the first bit of every pair of differing bits. Additionally, the bits discarded by
+/// it are recycled: the sequence of the XORs of all pairs, and the sequence of the bits of the pairs of equal bits are
+/// each debiased in the same way. This yields close to the entropy of the bits if they are independent, but only works
+/// on blocks of bits, so that the bits are yielded once a block of [`DEFAULT_PERES_BLOCK_LEN`] bits is complete.
+///
+/// # Example
+/// ```
+/// use tdtp::extract::{Comparison, Extractor, Peres};
+///
+/// let mut extractor = Peres::new(Comparison::new()).with_block_len(4);
+/// // the comparisons yield 1, 1, then 1, 0
+/// let bits = extractor.extract([0, 5, 8, 18, 20, 23, 25, 26, 29]);
+/// // Von Neumann's method only yields the 1 of 10, Peres' recovers a 0 from the 01 of the XORs
+/// assert_eq!(bits, [true, false]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Peres<E> {
+    /// The extractor whose bits are debiased.
+    inner: E,
+    /// How many bits are debiased at once.
+    block_len: usize,
+    /// The bits of the current block.
+    block: Vec<bool>,
+}
+
+impl<E: Extractor> Peres<E> {
+    /// Debias the bits of `inner`.
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            block_len: DEFAULT_PERES_BLOCK_LEN,
+            block: Vec::with_capacity(DEFAULT_PERES_BLOCK_LEN),
+        }
+    }
+
+    /// Set how many bits are debiased at once. Longer blocks yield more bits, but the bits are yielded later. Defaults
+    /// to [`DEFAULT_PERES_BLOCK_LEN`].
+    ///
+    /// # Panics
+    /// Panics if `block_len` is less than `2`.
+    #[must_use]
+    pub fn with_block_len(mut self, block_len: usize) -> Self {
+        assert!(block_len >= 2, "a block needs at least one pair of bits");
+        self.block_len = block_len;
+        self
+    }
+
+    /// The extractor which yields the bits of the blocks.
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// Mutable access to the extractor which yields the bits of the blocks. The bits of the current block are kept.
+    pub fn inner_mut(&mut self) -> &mut E {
+        &mut self.inner
+    }
+}
+
+impl<E: Extractor> Extractor for Peres<E> {
+    fn push(&mut self, timestamp: IncomingDataPacket, bits: &mut Vec<bool>) {
+        self.inner.push(timestamp, &mut self.block);
+
+        let complete = self.block.len() / self.block_len * self.block_len;
+        for block in self.block[..complete].chunks_exact(self.block_len) {
+            peres(block, bits);
+        }
+        self.block.drain(..complete);
+    }
+
+    /// Discard the bits of the current block, so that no pair spans the gap, and forward the gap to the inner
+    /// extractor.
+    fn gap(&mut self) {
+        self.block.clear();
+        self.inner.gap();
+    }
+
+    fn is_halted(&self) -> bool {
+        self.inner.is_halted()
+    }
+}
+
+/// Apply Peres' method to the given bits, appending its output to `out`.
+fn peres(bits: &[bool], out: &mut Vec<bool>) {
+    if bits.len() < 2 {
+        return;
+    }
+
+    let mut xors = Vec::with_capacity(bits.len() / 2);
+    let mut equal = Vec::new();
+    for pair in bits.chunks_exact(2) {
+        let (first, second) = (pair[0], pair[1]);
+        if first == second {
+            equal.push(first);
+        } else {
+            out.push(first);
+        }
+        xors.push(first ^ second);
+    }
+
+    peres(&xors, out);
+    peres(&equal, out);
+}
+
+/// Counts the events taken and the bits yielded by another extractor, to compare the yield of extractors.
+///
+/// # Example
+/// ```
+/// use tdtp::extract::{Comparison, Extractor, Metered};
+///
+/// let mut extractor = Metered::new(Comparison::new());
+/// extractor.extract([0, 5, 8, 10, 17]);
+/// assert_eq!(extractor.events(), 5);
+/// assert_eq!(extractor.bits(), 2);
+/// assert_eq!(extractor.bits_per_event(), 0.4);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Metered<E> {
+    /// The extractor which is measured.
+    inner: E,
+    /// The events taken so far.
+    events: u64,
+    /// The bits yielded so far.
+    bits: u64,
+}
+
+impl<E: Extractor> Metered<E> {
+    /// Measure the yield of `inner`.
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            events: 0,
+            bits: 0,
+        }
+    }
+
+    /// The extractor which is measured, e.g. to compare its bins or rebaselines with its yield.
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// Mutable access to the extractor which is measured. Only the events pushed through this extractor are counted.
+    pub fn inner_mut(&mut self) -> &mut E {
+        &mut self.inner
+    }
+
+    /// The events taken so far.
+    #[must_use]
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    /// The bits yielded so far.
+    #[must_use]
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    /// The bits yielded per event taken so far, or `0` if no event was taken yet.
+    #[must_use]
+    #[expect(clippy::cast_precision_loss)]
+    pub fn bits_per_event(&self) -> f64 {
+        if self.events == 0 {
+            return 0.0;
+        }
+        self.bits as f64 / self.events as f64
+    }
+}
+
+impl<E: Extractor> Extractor for Metered<E> {
+    fn push(&mut self, timestamp: IncomingDataPacket, bits: &mut Vec<bool>) {
+        let start = bits.len();
+        self.inner.push(timestamp, bits);
+
+        self.events += 1;
+        self.bits += (bits.len() - start) as u64;
+    }
+
+    fn gap(&mut self) {
+        self.inner.gap();
+    }
+
+    fn is_halted(&self) -> bool {
+        self.inner.is_halted()
+    }
+}
+
+/// The interval between the previous timestamp `last` and `timestamp`, in microseconds, or `None` if there is no
+/// previous timestamp or `timestamp` precedes it. Unless it precedes it, `timestamp` becomes the previous timestamp.
+pub(crate) fn interval(
+    last: &mut Option<IncomingDataPacket>,
+    timestamp: IncomingDataPacket,
+) -> Option<IncomingDataPacket> {
+    let Some(previous) = *last else {
+        *last = Some(timestamp);
+        return None;
+    };
+
+    let Some(interval) = timestamp.checked_sub(previous) else {
+        warn!("Timestamp {timestamp} precedes the previous one {previous}, skipping it");
+        return None;
+    };
+
+    *last = Some(timestamp);
+    Some(interval)
+}
+
+/// The bits yielded per interval for a baseline of the given length, which are those of the largest power of two not
+/// exceeding `round(sqrt(baseline_len))`, but at least one.
+#[expect(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn bits_for(baseline_len: usize) -> u32 {
+    let bins = (baseline_len as f64).sqrt().round() as u64;
+    bins.max(2).ilog2()
+}
+
+/// The mean and the sample variance of the given values, of which there must be at least two.
+#[expect(clippy::cast_precision_loss)]
+fn mean_variance(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+
+    (mean, variance)
+}
+
+/// C-compatible constructor of an [`IntervalToBits`] extractor whose baseline consists of `baseline_len` intervals.
+/// Returns a null pointer if `baseline_len` is less than `2`. The extractor must be freed with
+/// [`c_free_interval_extractor`].
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub extern "C" fn c_interval_extractor(baseline_len: usize) -> *mut () {
+    if baseline_len < 2 {
+        return std::ptr::null_mut();
+    }
+
+    let extractor = IntervalToBits::new().with_baseline_len(baseline_len);
+    Box::into_raw(Box::new(extractor)).cast()
+}
+
+/// C-compatible wrapper around [`IntervalToBits::push`]. Returns the amount of bits extracted from the interval ending
+/// at `timestamp`, which is `0` while the baseline is collected. Otherwise, the bits are written to `bin`, as the index
+/// of the bin the interval fell into.
+///
+/// # Safety
+/// `extractor` must be a valid pointer to an extractor, and `bin` a valid pointer.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_interval_extractor_push(
+    extractor: *mut (),
+    timestamp: IncomingDataPacket,
+    bin: *mut u32,
+) -> u32 {
+    let extractor = unsafe { &mut *extractor.cast::<IntervalToBits>() };
+    let Some(interval) = interval(&mut extractor.last, timestamp) else {
+        return 0;
+    };
+
+    #[expect(clippy::cast_precision_loss)]
+    match extractor.take_interval(interval as f64) {
+        Some(index) => {
+            unsafe { *bin = index };
+            extractor.bits
+        }
+        None => 0,
+    }
+}
+
+/// Safely drop the passed extractor.
+///
+/// # Safety
+/// `extractor` must be a valid pointer.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn c_free_interval_extractor(extractor: *mut ()) {
+    drop(unsafe { Box::from_raw(extractor.cast::<IntervalToBits>()) });
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests of the extractors and of their handling of gaps.
+
+    use super::{Comparison, Extractor, IntervalToBits, Metered, Peres, VonNeumann};
+    use crate::client::IncomingDataPacket;
+
+    /// Yields one bit per event, which is set for the timestamp `1` only, and counts the gaps it was told about.
+    #[derive(Default)]
+    struct Bits {
+        /// How many gaps it was told about.
+        gaps: u32,
+    }
+
+    impl Extractor for Bits {
+        fn push(&mut self, timestamp: IncomingDataPacket, bits: &mut Vec<bool>) {
+            bits.push(timestamp == 1);
+        }
+
+        fn gap(&mut self) {
+            self.gaps += 1;
+        }
+    }
+
+    /// The interval spanning a gap is not compared, and the pair started before it is discarded.
+    #[test]
+    fn comparison_skips_gaps() {
+        let timestamps = [0, 5, 100, 103, 104];
+        assert_eq!(Comparison::new().extract(timestamps), [false, true]);
+
+        let mut extractor = Comparison::new();
+        extractor.extract(timestamps[..2].iter().copied());
+        extractor.gap();
+        assert_eq!(extractor.extract(timestamps[2..].iter().copied()), [true]);
+    }
+
+    /// The interval spanning a gap is not sorted into a bin, nor taken into the baseline.
+    #[test]
+    fn interval_to_bits_skips_gaps() {
+        let mut extractor = IntervalToBits::new().with_baseline_len(5);
+        let bits = extractor.extract([0, 10, 20, 30, 40]);
+        assert!(bits.is_empty());
+        assert!(extractor.quantiles().is_empty());
+
+        extractor.gap();
+        assert!(extractor.extract([1000]).is_empty());
+        assert!(extractor.quantiles().is_empty());
+        assert!(extractor.extract([1010]).is_empty());
+        assert_eq!(extractor.extract([1020]).len(), 1);
+    }
+
+    /// Debiasing extractors discard the bits pending before a gap, and every wrapper forwards the gap.
+    #[test]
+    fn debiasing_discards_pending_bits() {
+        let mut extractor = VonNeumann::new(Bits::default());
+        assert!(extractor.extract([1]).is_empty());
+        extractor.gap();
+        assert!(extractor.extract([0]).is_empty());
+        assert_eq!(extractor.extract([1]), [false]);
+        assert_eq!(extractor.inner().gaps, 1);
+
+        let mut extractor = Peres::new(Bits::default()).with_block_len(2);
+        extractor.extract([1]);
+        extractor.gap();
+        // the pair spanning the gap would have been 10
+        assert_eq!(extractor.extract([0, 1]), [false]);
+
+        let mut extractor = Metered::new(Box::new(Peres::new(Bits::default())));
+        extractor.gap();
+        assert_eq!(extractor.inner().inner().gaps, 1);
+    }
+}
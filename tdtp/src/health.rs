@@ -0,0 +1,735 @@
+//! Continuous health tests of extracted bits, following NIST SP 800-90B, section 4.4.
+//!
+//! A detector which stopped working, e.g. because of a stuck GPIO pin or a dead tube, may still produce events, but
+//! their timestamps are no longer random. [`HealthTests`] run the Repetition Count and the Adaptive Proportion test on
+//! the bits extracted from them, and [`HealthChecked`] halts the output of an extractor once a test fails.
+//!
+//! A detector may also stop producing events altogether, while the server keeps the connection alive with heartbeats.
+//! The starvation test fails once no event arrived for [`DEFAULT_STARVATION_TIMEOUT`].
+//!
+//! Before any bit is output, the tests are run on [`DEFAULT_STARTUP_LEN`] bits, which are discarded (the startup test).
+
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use log::{error, info};
+
+use crate::{client::IncomingDataPacket, extract::Extractor};
+
+/// The default entropy claimed per bit, from which the cutoffs of the tests are derived.
+pub const DEFAULT_ENTROPY: f64 = 1.0;
+
+/// The binary logarithm of the probability of a false positive of either test per bit, which is `2^-20` as recommended
+/// by SP 800-90B.
+pub const FALSE_POSITIVE_LOG2: i32 = -20;
+
+/// The default size of the window of the Adaptive Proportion test, which SP 800-90B prescribes for binary samples.
+pub const DEFAULT_APT_WINDOW: u32 = 1024;
+
+/// The default amount of bits the tests are run on before any bit is output.
+pub const DEFAULT_STARTUP_LEN: u64 = 1024;
+
+/// The default time no event may arrive for before the starvation test fails.
+pub const DEFAULT_STARVATION_TIMEOUT: Duration = Duration::from_mins(1);
+
+/// The failure of a health test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthFailure {
+    /// The Repetition Count test failed: the same bit was repeated as often in a row as its cutoff allows.
+    RepetitionCount {
+        /// The repeated bit.
+        bit: bool,
+        /// How often it was repeated.
+        count: u32,
+    },
+    /// The Adaptive Proportion test failed: the first bit of a window recurred in it as often as its cutoff allows.
+    AdaptiveProportion {
+        /// The recurring bit.
+        bit: bool,
+        /// How often it occurred in the window so far.
+        count: u32,
+    },
+    /// The starvation test failed: no event arrived for as long as its timeout allows, e.g. because the detector died
+    /// and the server only sends heartbeats.
+    Starvation {
+        /// How long no event arrived.
+        idle: Duration,
+    },
+}
+
+impl Display for HealthFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RepetitionCount { bit, count } => {
+                write!(
+                    f,
+                    "repetition count test failed: {} repeated {count} times",
+                    u8::from(*bit)
+                )
+            }
+            Self::AdaptiveProportion { bit, count } => write!(
+                f,
+                "adaptive proportion test failed: {} occurred {count} times in a window",
+                u8::from(*bit)
+            ),
+            Self::Starvation { idle } => write!(f, "starvation test failed: no event for {idle:?}"),
+        }
+    }
+}
+
+/// The state of the health tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthStatus {
+    /// The startup test is running, so that no bit may be output yet.
+    Startup,
+    /// All tests passed so far, and bits may be output.
+    Healthy,
+    /// A test failed, so that no bit may be output until the tests are reset.
+    Failed(HealthFailure),
+}
+
+/// The Repetition Count and Adaptive Proportion tests of SP 800-90B, run on a sequence of bits, and the starvation
+/// test, run on the time elapsed without an event.
+///
+/// Once a test fails, the tests stay failed until they are [reset](HealthTests::reset), which restarts the startup
+/// test.
+///
+/// # Example
+/// ```
+/// use tdtp::health::{HealthFailure, HealthStatus, HealthTests};
+///
+/// let mut tests = HealthTests::new().with_startup_len(4);
+/// assert_eq!(tests.rct_cutoff(), 21);
+///
+/// assert_eq!(tests.test(true), HealthStatus::Startup);
+/// let status = (0..20).map(|_| tests.test(true)).last();
+/// assert_eq!(
+///     status,
+///     Some(HealthStatus::Failed(HealthFailure::RepetitionCount { bit: true, count: 21 }))
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct HealthTests {
+    /// How often the same bit may be repeated in a row, exclusive.
+    rct_cutoff: u32,
+    /// How often the first bit of a window may occur in it, exclusive.
+    apt_cutoff: u32,
+    /// The size of a window of the Adaptive Proportion test.
+    apt_window: u32,
+    /// How many bits the startup test consists of.
+    startup_len: u64,
+    /// How long no event may arrive before the starvation test fails, if it is run at all.
+    starvation_timeout: Option<Duration>,
+    /// The bit repeated last, and how often it was repeated.
+    run: Option<(bool, u32)>,
+    /// The first bit of the current window, how often it occurred so far, and the size of the window so far.
+    window: Option<(bool, u32, u32)>,
+    /// How many bits were tested since the tests were reset.
+    tested: u64,
+    /// The failure of a test, if any failed.
+    failure: Option<HealthFailure>,
+}
+
+impl Default for HealthTests {
+    fn default() -> Self {
+        Self::for_entropy(DEFAULT_ENTROPY)
+    }
+}
+
+impl HealthTests {
+    /// Create the tests for bits of [`DEFAULT_ENTROPY`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the tests for bits of which each carries the given min-entropy, deriving the cutoffs as SP 800-90B
+    /// prescribes, for a false positive probability of `2^-20`.
+    ///
+    /// # Panics
+    /// Panics if `entropy` is not within `(0, 1]`.
+    #[must_use]
+    pub fn for_entropy(entropy: f64) -> Self {
+        assert!(
+            entropy > 0.0 && entropy <= 1.0,
+            "a bit carries between 0 and 1 bits of entropy"
+        );
+
+        let alpha = 2_f64.powi(FALSE_POSITIVE_LOG2);
+        #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let rct_cutoff = 1 + (-f64::from(FALSE_POSITIVE_LOG2) / entropy).ceil() as u32;
+        let apt_cutoff = 1 + critical_binomial(DEFAULT_APT_WINDOW, (-entropy).exp2(), 1.0 - alpha);
+
+        Self {
+            rct_cutoff,
+            apt_cutoff,
+            apt_window: DEFAULT_APT_WINDOW,
+            startup_len: DEFAULT_STARTUP_LEN,
+            starvation_timeout: Some(DEFAULT_STARVATION_TIMEOUT),
+            run: None,
+            window: None,
+            tested: 0,
+            failure: None,
+        }
+    }
+
+    /// Set how often the same bit may be repeated in a row before the Repetition Count test fails.
+    ///
+    /// # Panics
+    /// Panics if `cutoff` is less than `2`.
+    #[must_use]
+    pub fn with_rct_cutoff(mut self, cutoff: u32) -> Self {
+        assert!(cutoff >= 2, "a single bit is always repeated once");
+        self.rct_cutoff = cutoff;
+        self
+    }
+
+    /// Set how often the first bit of a window may occur in it before the Adaptive Proportion test fails.
+    ///
+    /// # Panics
+    /// Panics if `cutoff` is less than `2`.
+    #[must_use]
+    pub fn with_apt_cutoff(mut self, cutoff: u32) -> Self {
+        assert!(cutoff >= 2, "the first bit of a window always occurs once");
+        self.apt_cutoff = cutoff;
+        self
+    }
+
+    /// Set the size of a window of the Adaptive Proportion test. Defaults to [`DEFAULT_APT_WINDOW`]. The cutoff is not
+    /// adjusted, see [`HealthTests::with_apt_cutoff`].
+    #[must_use]
+    pub fn with_apt_window(mut self, window: u32) -> Self {
+        self.apt_window = window;
+        self
+    }
+
+    /// Set how many bits the startup test consists of. Defaults to [`DEFAULT_STARTUP_LEN`].
+    #[must_use]
+    pub fn with_startup_len(mut self, startup_len: u64) -> Self {
+        self.startup_len = startup_len;
+        self
+    }
+
+    /// Set how long no event may arrive before the starvation test fails, or `None` to never fail it. Defaults to
+    /// [`DEFAULT_STARVATION_TIMEOUT`].
+    #[must_use]
+    pub fn with_starvation_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.starvation_timeout = timeout;
+        self
+    }
+
+    /// How often the same bit may be repeated in a row before the Repetition Count test fails.
+    #[must_use]
+    pub fn rct_cutoff(&self) -> u32 {
+        self.rct_cutoff
+    }
+
+    /// How often the first bit of a window may occur in it before the Adaptive Proportion test fails.
+    #[must_use]
+    pub fn apt_cutoff(&self) -> u32 {
+        self.apt_cutoff
+    }
+
+    /// Run the tests on the next bit, and return their state afterwards.
+    ///
+    /// Every bit of the startup test yields [`HealthStatus::Startup`], including the one completing it, so that it is
+    /// discarded like the others, even though [`HealthTests::status`] is healthy afterwards.
+    pub fn test(&mut self, bit: bool) -> HealthStatus {
+        if self.failure.is_some() {
+            return self.status();
+        }
+
+        let failure = match &mut self.run {
+            Some((value, count)) if *value == bit => {
+                *count += 1;
+                (*count >= self.rct_cutoff)
+                    .then_some(HealthFailure::RepetitionCount { bit, count: *count })
+            }
+            run => {
+                *run = Some((bit, 1));
+                None
+            }
+        };
+
+        let failure = failure.or(match &mut self.window {
+            Some((value, count, size)) if *size < self.apt_window => {
+                *size += 1;
+                *count += u32::from(*value == bit);
+                (*count >= self.apt_cutoff).then_some(HealthFailure::AdaptiveProportion {
+                    bit: *value,
+                    count: *count,
+                })
+            }
+            window => {
+                *window = Some((bit, 1, 1));
+                None
+            }
+        });
+
+        self.tested += 1;
+        if let Some(failure) = failure {
+            self.fail(failure);
+        } else if self.tested == self.startup_len {
+            info!("Startup health test passed");
+            // the bit completing the startup test is part of it, and is discarded as well
+            return HealthStatus::Startup;
+        }
+
+        self.status()
+    }
+
+    /// Run the starvation test on the time elapsed without an event, and return the state of the tests afterwards.
+    pub fn idle(&mut self, idle: Duration) -> HealthStatus {
+        if self.failure.is_none()
+            && self
+                .starvation_timeout
+                .is_some_and(|timeout| idle >= timeout)
+        {
+            self.fail(HealthFailure::Starvation { idle });
+        }
+
+        self.status()
+    }
+
+    /// Record the failure of a test, which halts the output.
+    fn fail(&mut self, failure: HealthFailure) {
+        error!("Health test failed, halting output: {failure}");
+        self.failure = Some(failure);
+    }
+
+    /// The state of the tests.
+    #[must_use]
+    pub fn status(&self) -> HealthStatus {
+        match self.failure {
+            Some(failure) => HealthStatus::Failed(failure),
+            None if self.tested < self.startup_len => HealthStatus::Startup,
+            None => HealthStatus::Healthy,
+        }
+    }
+
+    /// Reset the tests after a failure, which restarts the startup test.
+    pub fn reset(&mut self) {
+        self.run = None;
+        self.window = None;
+        self.tested = 0;
+        self.failure = None;
+    }
+}
+
+/// The smallest `k` for which the probability of at most `k` successes in `n` trials of probability `p` is at least
+/// `q`, i.e. Excel's `CRITBINOM`.
+fn critical_binomial(n: u32, p: f64, q: f64) -> u32 {
+    let mut cdf = 0.0;
+    // the logarithm of the binomial coefficient of `n` and `k`
+    let mut ln_choose = 0.0;
+
+    for k in 0..=n {
+        if k > 0 {
+            ln_choose += f64::from(n - k + 1).ln() - f64::from(k).ln();
+        }
+        cdf += (ln_choose + f64::from(k) * p.ln() + f64::from(n - k) * (-p).ln_1p()).exp();
+        if cdf >= q {
+            return k;
+        }
+    }
+
+    n
+}
+
+/// Runs the health tests on the bits of another extractor, and only outputs them while the tests are healthy.
+///
+/// The bits of the startup test are discarded. Once a test fails, the output halts until the tests are
+/// [reset](HealthChecked::reset). Besides [`HealthChecked::status`], the failure is logged, and returned by
+/// [`HealthChecked::try_push`] and [`HealthChecked::check`].
+///
+/// The interval between the timestamps of successive events is subject to the starvation test. Since no event is
+/// pushed while the detector is dead, [`HealthChecked::check`] runs it on the time elapsed since the latest event was
+/// pushed, and should be called whenever no event arrived for a while.
+///
+/// # Example
+/// ```
+/// use tdtp::{
+///     extract::{Comparison, Extractor},
+///     health::{HealthChecked, HealthStatus, HealthTests},
+/// };
+///
+/// let mut extractor = HealthChecked::new(Comparison::new())
+///     .with_tests(HealthTests::new().with_startup_len(0));
+///
+/// // a detector producing periodic garbage, whose intervals grow and never compare the other way
+/// let timestamps = (0..100).map(|i: u128| i * i * 10);
+/// let bits = extractor.extract(timestamps);
+///
+/// assert_eq!(bits.len(), 20);
+/// assert!(matches!(extractor.status(), HealthStatus::Failed(_)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct HealthChecked<E> {
+    /// The extractor whose bits are tested.
+    inner: E,
+    /// The health tests.
+    tests: HealthTests,
+    /// The bits the inner extractor yielded for the current event, before they are tested.
+    raw: Vec<bool>,
+    /// The timestamp of the latest event, unless there was none since the tests were reset or events were lost.
+    latest: Option<IncomingDataPacket>,
+    /// When the latest event was pushed, or the tests were reset.
+    pushed: Instant,
+}
+
+impl<E: Extractor> HealthChecked<E> {
+    /// Run the default health tests on the bits of `inner`.
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            tests: HealthTests::new(),
+            raw: Vec::new(),
+            latest: None,
+            pushed: Instant::now(),
+        }
+    }
+
+    /// Set the health tests to run.
+    #[must_use]
+    pub fn with_tests(mut self, tests: HealthTests) -> Self {
+        self.tests = tests;
+        self
+    }
+
+    /// The extractor whose bits are tested.
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// Mutable access to the extractor whose bits are tested. Its bits are only tested when they are pushed through
+    /// this extractor, so it must not be used to extract bits directly.
+    pub fn inner_mut(&mut self) -> &mut E {
+        &mut self.inner
+    }
+
+    /// The state of the health tests.
+    pub fn status(&self) -> HealthStatus {
+        self.tests.status()
+    }
+
+    /// Reset the health tests after a failure, which resumes the output once the startup test passed again.
+    pub fn reset(&mut self) {
+        self.tests.reset();
+        self.latest = None;
+        self.pushed = Instant::now();
+    }
+
+    /// Like [`Extractor::push`], but return the failure of the health tests.
+    ///
+    /// # Errors
+    /// Returns the failure if a test failed, now or before, upon which no more bits are appended.
+    pub fn try_push(
+        &mut self,
+        timestamp: IncomingDataPacket,
+        bits: &mut Vec<bool>,
+    ) -> Result<(), HealthFailure> {
+        self.pushed = Instant::now();
+        if let Some(latest) = self.latest.replace(timestamp) {
+            let idle = u64::try_from(timestamp.saturating_sub(latest)).unwrap_or(u64::MAX);
+            self.tests.idle(Duration::from_micros(idle));
+        }
+
+        self.raw.clear();
+        self.inner.push(timestamp, &mut self.raw);
+
+        for &bit in &self.raw {
+            match self.tests.test(bit) {
+                HealthStatus::Healthy => bits.push(bit),
+                HealthStatus::Startup => (),
+                HealthStatus::Failed(_) => break,
+            }
+        }
+
+        self.result()
+    }
+
+    /// Run the starvation test on the time elapsed since the latest event was pushed, or the tests were reset.
+    ///
+    /// # Errors
+    /// Returns the failure if a test failed, now or before.
+    pub fn check(&mut self) -> Result<(), HealthFailure> {
+        self.tests.idle(self.pushed.elapsed());
+        self.result()
+    }
+
+    /// The failure of the health tests, if any failed.
+    fn result(&self) -> Result<(), HealthFailure> {
+        match self.tests.status() {
+            HealthStatus::Failed(failure) => Err(failure),
+            HealthStatus::Startup | HealthStatus::Healthy => Ok(()),
+        }
+    }
+}
+
+impl<E: Extractor> Extractor for HealthChecked<E> {
+    /// Like [`HealthChecked::try_push`], whose failure is logged and reflected by [`Extractor::is_halted`].
+    fn push(&mut self, timestamp: IncomingDataPacket, bits: &mut Vec<bool>) {
+        let _ = self.try_push(timestamp, bits);
+    }
+
+    /// Forward the gap to the inner extractor. The interval spanning the lost events is not subject to the starvation
+    /// test, but the other tests keep their state, since a detector which fails does not recover by losing events.
+    fn gap(&mut self) {
+        self.inner.gap();
+        self.latest = None;
+    }
+
+    fn is_halted(&self) -> bool {
+        matches!(self.tests.status(), HealthStatus::Failed(_)) || self.inner.is_halted()
+    }
+}
+
+/// The value of a [`HealthStatus`] returned by the C-compatible functions: `0` if healthy, `1` during the startup test,
+/// `-1` if the Repetition Count test failed, `-2` if the Adaptive Proportion test failed and `-3` if the starvation test
+/// failed.
+#[cfg(feature = "interop")]
+fn c_health_status_code(status: HealthStatus) -> i32 {
+    match status {
+        HealthStatus::Healthy => 0,
+        HealthStatus::Startup => 1,
+        HealthStatus::Failed(HealthFailure::RepetitionCount { .. }) => -1,
+        HealthStatus::Failed(HealthFailure::AdaptiveProportion { .. }) => -2,
+        HealthStatus::Failed(HealthFailure::Starvation { .. }) => -3,
+    }
+}
+
+/// C-compatible wrapper around [`HealthTests::for_entropy`]. Returns a null pointer if `entropy` is not within
+/// `(0, 1]`. The tests must be freed with [`c_free_health_tests`].
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub extern "C" fn c_health_tests(entropy: f64) -> *mut () {
+    if !(entropy > 0.0 && entropy <= 1.0) {
+        return std::ptr::null_mut();
+    }
+
+    Box::into_raw(Box::new(HealthTests::for_entropy(entropy))).cast()
+}
+
+/// Run the health tests on the `bits` lowest bits of `bin`, most significant bit first, as extracted by
+/// [`c_interval_extractor_push`](crate::extract::c_interval_extractor_push).
+///
+/// Returns `0` if the bits may be output, `1` if they must be discarded because the startup test is running, and `-1`,
+/// `-2` or `-3` if the Repetition Count, Adaptive Proportion or starvation test failed, after which no bits may be
+/// output until [`c_health_reset`] is called.
+///
+/// # Safety
+/// `tests` must be a valid pointer to health tests.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_health_test(tests: *mut (), bin: u32, bits: u32) -> i32 {
+    let tests = unsafe { &mut *tests.cast::<HealthTests>() };
+    let mut startup = false;
+
+    for bit in (0..bits.min(u32::BITS)).rev() {
+        match tests.test(bin >> bit & 1 == 1) {
+            HealthStatus::Startup => startup = true,
+            HealthStatus::Healthy => (),
+            status @ HealthStatus::Failed(_) => return c_health_status_code(status),
+        }
+    }
+
+    if startup {
+        c_health_status_code(HealthStatus::Startup)
+    } else {
+        c_health_status_code(tests.status())
+    }
+}
+
+/// C-compatible wrapper around [`HealthTests::idle`], which takes the microseconds elapsed without an event, e.g.
+/// between the timestamps of successive events, or since the latest event while only heartbeats arrive. Returns the
+/// state of the tests, as [`c_health_test`] does.
+///
+/// # Safety
+/// `tests` must be a valid pointer to health tests.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_health_idle(tests: *mut (), micros: u64) -> i32 {
+    c_health_status_code(
+        unsafe { &mut *tests.cast::<HealthTests>() }.idle(Duration::from_micros(micros)),
+    )
+}
+
+/// The state of the health tests, as returned by [`c_health_test`].
+///
+/// # Safety
+/// `tests` must be a valid pointer to health tests.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_health_status(tests: *const ()) -> i32 {
+    c_health_status_code(unsafe { &*tests.cast::<HealthTests>() }.status())
+}
+
+/// C-compatible wrapper around [`HealthTests::reset`].
+///
+/// # Safety
+/// `tests` must be a valid pointer to health tests.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn c_health_reset(tests: *mut ()) {
+    unsafe { &mut *tests.cast::<HealthTests>() }.reset();
+}
+
+/// Safely drop the passed health tests.
+///
+/// # Safety
+/// `tests` must be a valid pointer.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn c_free_health_tests(tests: *mut ()) {
+    drop(unsafe { Box::from_raw(tests.cast::<HealthTests>()) });
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests of the health tests and of their cutoffs.
+
+    use std::time::Duration;
+
+    use super::{HealthChecked, HealthFailure, HealthStatus, HealthTests, critical_binomial};
+    use crate::{client::IncomingDataPacket, extract::Extractor};
+
+    /// Yields the lowest bit of every timestamp.
+    struct LowBit;
+
+    impl Extractor for LowBit {
+        fn push(&mut self, timestamp: IncomingDataPacket, bits: &mut Vec<bool>) {
+            bits.push(timestamp & 1 == 1);
+        }
+    }
+
+    /// The cutoffs are those SP 800-90B tabulates for binary samples.
+    #[test]
+    fn cutoffs_follow_the_entropy() {
+        let tests = HealthTests::new();
+        assert_eq!((tests.rct_cutoff(), tests.apt_cutoff()), (21, 589));
+
+        let tests = HealthTests::for_entropy(0.5);
+        assert_eq!(tests.rct_cutoff(), 41);
+        assert!(tests.apt_cutoff() > 589);
+
+        assert_eq!(critical_binomial(10, 0.5, 0.5), 5);
+        assert_eq!(critical_binomial(10, 0.5, 1.0), 10);
+    }
+
+    /// The tests report the startup test until it is complete, and alternating bits pass them.
+    #[test]
+    fn startup_then_healthy() {
+        let mut tests = HealthTests::new().with_startup_len(10);
+
+        for i in 0..10 {
+            assert_eq!(tests.test(i % 2 == 0), HealthStatus::Startup);
+        }
+        assert_eq!(tests.status(), HealthStatus::Healthy);
+        for i in 10..100_000 {
+            assert_eq!(tests.test(i % 2 == 0), HealthStatus::Healthy);
+        }
+    }
+
+    /// A bit recurring too often in a window fails the Adaptive Proportion test, even without long runs, and the
+    /// failure lasts until the tests are reset.
+    #[test]
+    fn biased_bits_fail_the_adaptive_proportion_test() {
+        let mut tests = HealthTests::new().with_startup_len(0);
+
+        // two thirds of the bits are set, in runs of two
+        let status = (0..1024)
+            .map(|i| tests.test(i % 3 != 2))
+            .find(|status| *status != HealthStatus::Healthy);
+        assert_eq!(
+            status,
+            Some(HealthStatus::Failed(HealthFailure::AdaptiveProportion {
+                bit: true,
+                count: 589
+            }))
+        );
+        assert!(matches!(tests.test(false), HealthStatus::Failed(_)));
+
+        tests.reset();
+        assert_eq!(tests.test(false), HealthStatus::Healthy);
+    }
+
+    /// Only the bits tested after the startup test are output, and none once a test failed.
+    #[test]
+    fn health_checked_halts_output() {
+        let mut extractor = HealthChecked::new(LowBit)
+            .with_tests(HealthTests::new().with_startup_len(4).with_rct_cutoff(3));
+
+        assert_eq!(extractor.extract(0..6), [false, true]);
+        assert!(!extractor.is_halted());
+
+        // the last bit output starts the run
+        assert_eq!(extractor.extract([1, 1, 1, 0]), [true]);
+        assert!(extractor.is_halted());
+        assert_eq!(
+            extractor.status(),
+            HealthStatus::Failed(HealthFailure::RepetitionCount {
+                bit: true,
+                count: 3
+            })
+        );
+
+        extractor.reset();
+        assert_eq!(extractor.extract(0..6), [false, true]);
+    }
+
+    /// An interval between events as long as the timeout fails the starvation test, and the failure is returned,
+    /// unless events were lost during it.
+    #[test]
+    fn long_intervals_fail_the_starvation_test() {
+        let tests = HealthTests::new()
+            .with_startup_len(0)
+            .with_starvation_timeout(Some(Duration::from_secs(1)));
+        let mut extractor = HealthChecked::new(LowBit).with_tests(tests);
+        let mut bits = Vec::new();
+
+        assert_eq!(extractor.try_push(0, &mut bits), Ok(()));
+        extractor.gap();
+        assert_eq!(extractor.try_push(2_000_001, &mut bits), Ok(()));
+        assert_eq!(extractor.try_push(2_999_999, &mut bits), Ok(()));
+        assert_eq!(bits, [false, true, true]);
+
+        assert_eq!(
+            extractor.try_push(3_999_999, &mut bits),
+            Err(HealthFailure::Starvation {
+                idle: Duration::from_secs(1)
+            })
+        );
+        assert_eq!(bits.len(), 3);
+        assert!(extractor.is_halted());
+    }
+
+    /// Without events, checking the tests runs the starvation test on the time elapsed since the latest one.
+    #[test]
+    fn check_notices_missing_events() {
+        let mut extractor = HealthChecked::new(LowBit);
+        assert_eq!(extractor.check(), Ok(()));
+
+        let mut extractor = HealthChecked::new(LowBit)
+            .with_tests(HealthTests::new().with_starvation_timeout(Some(Duration::ZERO)));
+        assert!(matches!(
+            extractor.check(),
+            Err(HealthFailure::Starvation { .. })
+        ));
+        assert!(extractor.is_halted());
+
+        let mut tests = HealthTests::new().with_starvation_timeout(None);
+        assert_eq!(tests.idle(Duration::MAX), HealthStatus::Startup);
+    }
+}
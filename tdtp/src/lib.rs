@@ -2,10 +2,13 @@
 //!
 //! ## Features
 //!
-//! + `client`: Enables client-side functions and data types
+//! + `client`: Enables client-side functions and data types, including the extraction of random bits and the estimation of their entropy
 //! + `server`: Enables server-side functions and data types
 //! + `interop`: Enables interoperability interfaces for C/C++ code.
 //! + `full`: Enables all of the above
+//! + `async`: Enables asynchronous equivalents of the client and server, built on [tokio](https://tokio.rs)
+//! + `conditioning`: Enables the cryptographic conditioning of extracted bits, and a source of random numbers
+//!   implementing the traits of [rand_core](https://docs.rs/rand_core), on top of `client`
 //!
 //! View the module-level docs for more information on usage.
 
@@ -19,25 +22,104 @@
 #![deny(clippy::pedantic)]
 
 use std::{
-    io::{self, Write as _},
+    io,
     net::TcpStream,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use log::info;
+use socket2::{SockRef, TcpKeepalive};
 
-use crate::consts::SIG_EXIT;
+use crate::codec::Frame;
 
 #[cfg(feature = "client")]
 pub mod client;
+pub mod codec;
+#[cfg(feature = "conditioning")]
+pub mod condition;
 pub mod consts;
+#[cfg(feature = "client")]
+pub mod entropy;
+#[cfg(feature = "client")]
+pub mod extract;
+#[cfg(feature = "client")]
+pub mod health;
 #[cfg(feature = "server")]
 pub mod server;
 
-/// Close the given TCP stream by shutting down both R/W sides.
-fn close(mut stream: TcpStream) -> io::Result<()> {
+/// Close the given TCP stream by sending the given exit frame and shutting down both R/W sides.
+///
+/// The server signals an exit with a bare [`SIG_EXIT`](consts::SIG_EXIT) ([`Frame::Exit`]), the client with
+/// `[CTRL, SIG_EXIT]` ([`Frame::Control`]).
+fn close(mut stream: TcpStream, exit: &Frame) -> io::Result<()> {
     info!("Closing stream");
-    stream.write_all(&[SIG_EXIT])?;
-    stream.shutdown(std::net::Shutdown::Both)
+    codec::encode(exit, &mut stream)?;
+
+    match stream.shutdown(std::net::Shutdown::Both) {
+        // the other side closed the connection first
+        Err(e) if e.kind() == io::ErrorKind::NotConnected => Ok(()),
+        result => result,
+    }
+}
+
+/// The options applied to every TCP stream of a client or server, see [`client::ClientConfig`] and
+/// [`server::ServerConfig`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SocketOptions {
+    /// Whether Nagle's algorithm is disabled (`TCP_NODELAY`).
+    nodelay: bool,
+    /// After how long an idle connection is probed with TCP keepalive, or `None` to not probe it.
+    keepalive: Option<Duration>,
+    /// How long a write may block before it fails, or `None` to block indefinitely.
+    write_timeout: Option<Duration>,
+    /// The size of the send buffer of the socket, or `None` to keep the system default.
+    send_buffer: Option<usize>,
+    /// The size of the receive buffer of the socket, or `None` to keep the system default.
+    recv_buffer: Option<usize>,
+}
+
+impl SocketOptions {
+    /// Apply the options to the given stream.
+    fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        stream.set_write_timeout(self.write_timeout)?;
+
+        let socket = SockRef::from(stream);
+        if let Some(time) = self.keepalive {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
+        if let Some(size) = self.send_buffer {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer {
+            socket.set_recv_buffer_size(size)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A random number, which is good enough for jitter and identifiers, but not for cryptography.
+fn random() -> u64 {
+    use std::hash::{BuildHasher, Hasher, RandomState};
+
+    // `RandomState` is randomly seeded
+    RandomState::new().build_hasher().finish()
+}
+
+/// The error returned if an address did not resolve to any socket address.
+fn no_addresses() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "address did not resolve to any socket address",
+    )
+}
+
+/// The amount of microseconds elapsed since the unix epoch, according to the system clock.
+fn unix_micros() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros())
 }
 
 /// A channel pair.
@@ -50,6 +132,29 @@ pub struct ChannelPair {
     pub rx: *mut (),
 }
 
+/// Resolve the given C string, which is either an IPv4 or IPv6 address or a hostname, to the socket addresses it
+/// resolves to on the given port. Returns `None` if the string is null, not valid UTF-8, or cannot be resolved.
+///
+/// # Safety
+/// `host` must be null or a valid pointer to a nul-terminated string.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+unsafe fn c_resolve(host: *const std::ffi::c_char, port: u16) -> Option<Vec<std::net::SocketAddr>> {
+    use std::{ffi::CStr, net::ToSocketAddrs};
+
+    if host.is_null() {
+        return None;
+    }
+
+    let host = unsafe { CStr::from_ptr(host) }.to_str().ok()?;
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .inspect_err(|e| log::error!("Failed to resolve {host}: {e}"))
+        .ok()?;
+
+    Some(addrs.collect())
+}
+
 /// Initialise a logging framework. This is meant for external callers who cannot instantiate a Rust logging framework.
 #[cfg(feature = "interop")]
 #[expect(clippy::missing_panics_doc)]
@@ -63,47 +168,103 @@ pub extern "C" fn init_logger_framework() {
 
 pub mod client_mpsc {
     //! MPSC channels for client side of the protocol. These differ from normal channel ([`std::sync::mpsc`]) in the way that the sender keeps track of whether an associated receiver exists.
+    //!
+    //! Both ends also keep track of how many items are in the channel, which lets a data connection hold back the
+    //! server while the receiver does not keep up. Since this count is updated by every sending and receiving method,
+    //! the inner [`std::sync::mpsc`] ends are not exposed. An item is counted once it was sent, so that a sender waiting
+    //! for space in a full channel never makes it appear to hold more items than its capacity.
 
     use std::{
-        ops::{Deref, DerefMut},
-        sync::{Arc, atomic::AtomicBool, mpsc},
+        sync::{
+            Arc,
+            atomic::{AtomicBool, AtomicIsize, Ordering},
+            mpsc::{self, RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError},
+        },
+        time::Duration,
     };
 
-    use crate::client::IncomingDataPacket;
+    use crate::client::{ChannelItem, ClientEvent, IncomingDataPacket};
 
     /// Type alias for the inner receiver of [`ClientReceiver`].
     ///
     /// cbindgen:ignore
-    type InnerRecv = mpsc::Receiver<IncomingDataPacket>;
+    type InnerRecv<T> = mpsc::Receiver<T>;
     /// Type alias for the inner sender of [`ClientSender`].
     ///
     /// cbindgen:ignore
-    type InnerSender = mpsc::SyncSender<IncomingDataPacket>;
+    type InnerSender<T> = mpsc::SyncSender<T>;
 
     /// An MPSC receiver. Check the documentation of [`std::sync::mpsc::Receiver`] for more information.
+    ///
+    /// A receiver created with [`client_channel`] receives packets, one created with [`client_event_channel`] also
+    /// receives gaps in the packets.
     #[repr(C)]
-    pub struct ClientReceiver {
+    pub struct ClientReceiver<T = IncomingDataPacket> {
         /// The inner [`std::sync::mpsc::Receiver`].
-        inner: InnerRecv,
+        inner: InnerRecv<T>,
         /// Whether this receiver has been dropped. This is updated in [`ClientReceiver::drop`].
         drop_flag: Arc<AtomicBool>,
+        /// The amount of items in the channel, see [`count`].
+        len: Arc<AtomicIsize>,
     }
 
-    impl Deref for ClientReceiver {
-        type Target = InnerRecv;
+    impl<T> ClientReceiver<T> {
+        /// Wait for an item. See [`std::sync::mpsc::Receiver::recv`].
+        ///
+        /// # Errors
+        /// Returns an error if the sender hung up.
+        pub fn recv(&self) -> Result<T, RecvError> {
+            self.inner.recv().inspect(|_| self.received())
+        }
 
-        fn deref(&self) -> &Self::Target {
-            &self.inner
+        /// Receive an item if one is available. See [`std::sync::mpsc::Receiver::try_recv`].
+        ///
+        /// # Errors
+        /// Returns an error if the channel is empty or the sender hung up.
+        pub fn try_recv(&self) -> Result<T, TryRecvError> {
+            self.inner.try_recv().inspect(|_| self.received())
+        }
+
+        /// Wait up to `timeout` for an item. See [`std::sync::mpsc::Receiver::recv_timeout`].
+        ///
+        /// # Errors
+        /// Returns an error if no item arrived in time or the sender hung up.
+        pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+            self.inner
+                .recv_timeout(timeout)
+                .inspect(|_| self.received())
+        }
+
+        /// An iterator waiting for items until the sender hangs up. See [`std::sync::mpsc::Receiver::iter`].
+        pub fn iter(&self) -> impl Iterator<Item = T> {
+            std::iter::from_fn(|| self.recv().ok())
+        }
+
+        /// An iterator over the items which are available without waiting. See
+        /// [`std::sync::mpsc::Receiver::try_iter`].
+        pub fn try_iter(&self) -> impl Iterator<Item = T> {
+            std::iter::from_fn(|| self.try_recv().ok())
+        }
+
+        /// The amount of items in the channel.
+        #[must_use]
+        pub fn len(&self) -> usize {
+            count(&self.len)
+        }
+
+        /// Whether the channel is empty.
+        #[must_use]
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
         }
-    }
 
-    impl DerefMut for ClientReceiver {
-        fn deref_mut(&mut self) -> &mut Self::Target {
-            &mut self.inner
+        /// Count an item as taken out of the channel.
+        fn received(&self) {
+            self.len.fetch_sub(1, Ordering::Relaxed);
         }
     }
 
-    impl Drop for ClientReceiver {
+    impl<T> Drop for ClientReceiver<T> {
         fn drop(&mut self) {
             self.drop_flag
                 .store(true, std::sync::atomic::Ordering::Relaxed);
@@ -112,19 +273,62 @@ pub mod client_mpsc {
 
     /// An MPSC sender. Check the documentation of [`std::sync::mpsc::Sender`] for more information.
     #[repr(C)]
-    pub struct ClientSender {
+    pub struct ClientSender<T = IncomingDataPacket> {
         /// The inner [`std::sync::mpsc::Sender`].
-        inner: InnerSender,
+        inner: InnerSender<T>,
         /// Whether the receiver has been dropped.
         drop_flag: Arc<AtomicBool>,
+        /// The amount of items in the channel, see [`count`].
+        len: Arc<AtomicIsize>,
+        /// How many items the channel holds at most.
+        capacity: usize,
     }
 
-    impl ClientSender {
+    impl<T> ClientSender<T> {
         /// Check if this sender still has an associated receiver.
         pub(crate) fn has_receiver(&self) -> bool {
             !self.drop_flag.load(std::sync::atomic::Ordering::Relaxed)
         }
 
+        /// Send an item, waiting while the channel is full. See [`std::sync::mpsc::SyncSender::send`].
+        ///
+        /// # Errors
+        /// Returns the item if the receiver hung up.
+        pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+            self.inner.send(item).inspect(|()| self.sent())
+        }
+
+        /// Send an item if there is space in the channel. See [`std::sync::mpsc::SyncSender::try_send`].
+        ///
+        /// # Errors
+        /// Returns the item if the channel is full or the receiver hung up.
+        pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+            self.inner.try_send(item).inspect(|()| self.sent())
+        }
+
+        /// Count an item as put into the channel.
+        fn sent(&self) {
+            self.len.fetch_add(1, Ordering::Relaxed);
+        }
+
+        /// The amount of items in the channel.
+        #[must_use]
+        pub fn len(&self) -> usize {
+            count(&self.len)
+        }
+
+        /// Whether the channel is empty.
+        #[must_use]
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// How many items the channel holds at most, i.e., the buffer size it was created with.
+        #[must_use]
+        pub fn capacity(&self) -> usize {
+            self.capacity
+        }
+
         /// A C-compatible wrapper around [`Self::has_receiver`].
         #[cfg(feature = "interop")]
         #[must_use]
@@ -133,31 +337,61 @@ pub mod client_mpsc {
         }
     }
 
-    impl Deref for ClientSender {
-        type Target = InnerSender;
+    /// The amount of items in a channel according to its count `len`.
+    ///
+    /// Since an item is counted after it was sent, the receiver may take it out before it is counted, in which case the
+    /// count briefly drops below zero.
+    fn count(len: &AtomicIsize) -> usize {
+        usize::try_from(len.load(Ordering::Relaxed)).unwrap_or(0)
+    }
 
-        fn deref(&self) -> &Self::Target {
-            &self.inner
-        }
+    /// Create an MPSC channel for receiving and sending data.
+    #[must_use]
+    pub fn client_channel(buffer: usize) -> (ClientSender, ClientReceiver) {
+        channel(buffer)
     }
 
-    impl DerefMut for ClientSender {
-        fn deref_mut(&mut self) -> &mut Self::Target {
-            &mut self.inner
-        }
+    /// Create an MPSC channel for receiving and sending data, which also receives the gaps in the packets of a
+    /// connection as [`ClientEvent::Gap`].
+    #[must_use]
+    pub fn client_event_channel(
+        buffer: usize,
+    ) -> (ClientSender<ClientEvent>, ClientReceiver<ClientEvent>) {
+        channel(buffer)
     }
 
-    /// Create an MPSC channel for receiving and sending data.
+    /// Create an MPSC channel of any item type a data connection can send, see [`ChannelItem`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use tdtp::{client::{ClientEvent, data}, client_mpsc::channel, codec::Packet};
+    ///
+    /// // receive the detector metadata of the packets along with the gaps between them
+    /// let (tx, rx) = channel::<ClientEvent<Packet>>(8192);
+    ///
+    /// std::thread::spawn(move || {
+    ///     while let Ok(event) = rx.recv() {
+    ///         if let ClientEvent::Packet(packet) = event {
+    ///             println!("Got a packet from channel {:?}", packet.channel);
+    ///         }
+    ///     }
+    /// });
+    ///
+    /// data("localhost:8000", tx);
+    /// ```
     #[must_use]
-    pub fn client_channel(buffer: usize) -> (ClientSender, ClientReceiver) {
+    pub fn channel<T: ChannelItem>(buffer: usize) -> (ClientSender<T>, ClientReceiver<T>) {
         let (tx, rx) = mpsc::sync_channel(buffer);
         let tx = ClientSender {
             inner: tx,
             drop_flag: Arc::new(AtomicBool::new(false)),
+            len: Arc::new(AtomicIsize::new(0)),
+            capacity: buffer,
         };
         let rx = ClientReceiver {
             inner: rx,
             drop_flag: Arc::clone(&tx.drop_flag),
+            len: Arc::clone(&tx.len),
         };
 
         (tx, rx)
@@ -242,4 +476,56 @@ pub mod client_mpsc {
             Err(TryRecvError::Empty) => 2,
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        //! Tests of the item count of the channels.
+
+        use std::{
+            sync::mpsc::{TryRecvError, TrySendError},
+            time::Duration,
+        };
+
+        use super::client_channel;
+
+        /// Every sending and receiving method keeps the count, including the ones which fail.
+        #[test]
+        fn counts_items() {
+            let (tx, rx) = client_channel(2);
+            tx.send(1).unwrap();
+            tx.try_send(2).unwrap();
+            assert!(matches!(tx.try_send(3), Err(TrySendError::Full(3))));
+            assert_eq!((tx.len(), rx.len()), (2, 2));
+
+            assert_eq!(rx.try_iter().collect::<Vec<_>>(), [1, 2]);
+            assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+            assert!(tx.is_empty() && rx.is_empty());
+
+            drop(rx);
+            assert!(!tx.has_receiver());
+            assert!(tx.send(4).is_err());
+            assert!(matches!(tx.try_send(5), Err(TrySendError::Disconnected(5))));
+            assert!(tx.is_empty());
+        }
+
+        /// An item is only counted once it was sent, so a sender waiting for space does not exceed the capacity.
+        #[test]
+        fn waiting_sender_is_not_counted() {
+            let (tx, rx) = client_channel(1);
+            tx.send(1).unwrap();
+
+            let sender = std::thread::spawn(move || {
+                tx.send(2).unwrap();
+                tx
+            });
+            std::thread::sleep(Duration::from_millis(50));
+            assert_eq!(rx.len(), 1);
+
+            assert_eq!(rx.recv(), Ok(1));
+            let tx = sender.join().unwrap();
+            assert_eq!((tx.len(), rx.len()), (1, 1));
+            assert_eq!(rx.recv(), Ok(2));
+            assert!(tx.is_empty());
+        }
+    }
 }
@@ -1,26 +1,111 @@
 //! Server-side functions and data types.
 //!
-//! To instantiate a server, see [`Server::run`].
+//! To instantiate a server, see [`server`], or [`spawn_server`] to run it on its own thread until it is shut down. Each
+//! has a `_with_config` variant taking a [`ServerConfig`]. With the `async` feature, an asynchronous equivalent is
+//! available in [`asynchronous`].
 
 use std::{
+    collections::{HashMap, VecDeque},
     convert::Infallible,
     fmt::Display,
-    io::{self, BufReader, ErrorKind, Read, Write},
-    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
-    sync::mpsc::{Receiver, TryRecvError},
+    io::{self, ErrorKind, Read, Write},
+    net::{self, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, SyncSender, TryRecvError, TrySendError, sync_channel},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 use log::{debug, error, info, warn};
 
 use crate::{
-    close,
-    consts::{CONN_DATA, CTRL, ConnectionType, EMP, SIG_EXIT, SIG_PACKET},
+    SocketOptions, close,
+    codec::{ControlDecoder, Frame, FrameReader, Packet, Position, encode},
+    consts::{
+        CAP_BATCH, CAP_CREDIT, CAP_DELTA, CAP_EPOCH, CAP_EVENT, CAP_RESUME, CAP_SYNC, CAPABILITIES,
+        CONN_DATA, ConnectionType, HELLO_ACCEPT, HELLO_REJECT, MAGIC, MAX_BATCH,
+        MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, RejectReason, SIG_EXIT, SIG_SYNC,
+    },
+    no_addresses, random, unix_micros,
 };
 
+#[cfg(feature = "async")]
+pub mod asynchronous;
+
 /// An outgoing data packet, i.e., one which the server intends to send.
 /// This must represent the amount of microseconds elapsed since the unix epoch.
+///
+/// Servers also accept [`Packet`]s, which carry detector metadata along with the timestamp. The metadata is only sent to
+/// clients which negotiated [`CAP_EVENT`].
 pub type OutgoingDataPacket = u128;
 
+/// How long the server waits for the client to echo a [`SIG_SYNC`] signal before giving up.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the server waits for the resume request of a client which negotiated [`CAP_RESUME`].
+const RESUME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a server checks whether it was shut down while its supplier is idle.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+/// How long a server which was shut down keeps taking the packets its supplier has queued, if the supplier does not run
+/// empty before.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a server which was shut down waits to connect to its listeners, which wakes them so that they close.
+const WAKE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often a broadcast server reports the packets dropped for a client whose queue is full.
+const DROP_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How many of the most recently sent packets a server keeps to replay them to resuming clients.
+pub const DEFAULT_REPLAY_SIZE: usize = 65536;
+
+/// The default interval after which an idle server sends an [`EMP`](crate::consts::EMP) heartbeat to the client.
+pub const DEFAULT_HEARTBEAT: Duration = Duration::from_secs(1);
+
+/// The default amount of packets queued for each client of a [`broadcast_server`].
+pub const DEFAULT_QUEUE_SIZE: usize = 8192;
+
+/// The default time a server waits for a connecting client to send its hello.
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An event a data handler reacts to.
+#[derive(Clone, Copy)]
+enum Event {
+    /// A packet from the supplier, along with its sequence number.
+    Packet(u64, Packet),
+    /// A control signal sent by the client of the connection with the given ID.
+    Control(u64, u8),
+    /// The resume request of the client of the connection with the given ID. See [`Frame::Resume`].
+    Resume(u64, Option<Position>),
+    /// Credit granted by the client of the connection with the given ID. See [`Frame::Credit`].
+    Credit(u64, u64),
+    /// The client of the connection with the given ID closed its side of the stream.
+    Closed(u64),
+    /// The supplier hung up.
+    Terminated,
+    /// The server was shut down, and the packets queued before were sent.
+    Shutdown,
+}
+
+/// The event channel of a single connection.
+struct Connection<'a> {
+    /// The ID of this connection, used to tell its events apart from those of previous connections.
+    id: u64,
+    /// The events for this connection.
+    events: &'a Receiver<Event>,
+    /// A sender for the events of this connection.
+    notify: &'a SyncSender<Event>,
+    /// The configuration of the server.
+    config: &'a ServerConfig,
+    /// The packets to replay to a resuming client.
+    replay: &'a Mutex<Replay>,
+}
+
 /// A server error.
 #[derive(Debug)]
 pub enum ServerError {
@@ -45,12 +130,457 @@ impl From<io::Error> for ServerError {
     }
 }
 
+/// Decides which errors of a single connection are fatal to the whole server.
+///
+/// A connection which fails with an error which is not fatal is logged and dropped, and the server keeps accepting
+/// connections. By default, no error of a connection is fatal; a server only exits on its own if its supplier hangs up.
+///
+/// # Example
+/// ```
+/// use std::io::{self, ErrorKind};
+/// use tdtp::server::ErrorPolicy;
+///
+/// let policy = ErrorPolicy::fatal_kinds([ErrorKind::OutOfMemory]);
+/// assert!(policy.is_fatal(&io::Error::from(ErrorKind::OutOfMemory)));
+/// assert!(!policy.is_fatal(&io::Error::from(ErrorKind::ConnectionReset)));
+/// ```
+#[derive(Clone, Default)]
+pub struct ErrorPolicy {
+    /// Whether an error is fatal, or `None` if none is.
+    fatal: Option<Arc<IsFatal>>,
+}
+
+/// A predicate deciding whether an error is fatal, see [`ErrorPolicy::fatal_if`].
+type IsFatal = dyn Fn(&io::Error) -> bool + Send + Sync;
+
+impl ErrorPolicy {
+    /// A policy under which the errors for which `fatal` returns `true` are fatal.
+    pub fn fatal_if(fatal: impl Fn(&io::Error) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            fatal: Some(Arc::new(fatal)),
+        }
+    }
+
+    /// A policy under which the errors of the given kinds are fatal.
+    pub fn fatal_kinds(kinds: impl IntoIterator<Item = ErrorKind>) -> Self {
+        let kinds: Vec<_> = kinds.into_iter().collect();
+        Self::fatal_if(move |e| kinds.contains(&e.kind()))
+    }
+
+    /// A policy under which every error is fatal, so that the server exits with the first error of any connection.
+    #[must_use]
+    pub fn all_fatal() -> Self {
+        Self::fatal_if(|_| true)
+    }
+
+    /// Whether the given error of a connection is fatal to the server.
+    #[must_use]
+    pub fn is_fatal(&self, e: &io::Error) -> bool {
+        self.fatal.as_ref().is_some_and(|fatal| fatal(e))
+    }
+}
+
+impl std::fmt::Debug for ErrorPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ErrorPolicy")
+            .field("fatal", &self.fatal.as_ref().map(|_| "<predicate>"))
+            .finish()
+    }
+}
+
+/// The connections of a server and the errors they failed with, see [`ServerHandle::stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConnectionStats {
+    /// How many connections were accepted.
+    pub accepted: u64,
+    /// How many connections were refused because the server was full, see [`ServerConfig::with_max_connections`].
+    pub refused: u64,
+    /// How many connections failed with an error.
+    pub failed: u64,
+    /// How many connections failed with an error of each kind.
+    pub errors: HashMap<ErrorKind, u64>,
+}
+
+/// How a server handles the errors of its connections.
+#[derive(Clone, Default)]
+struct ErrorHandling {
+    /// Which errors are fatal.
+    policy: ErrorPolicy,
+    /// The connections of the server so far.
+    stats: Arc<Mutex<ConnectionStats>>,
+}
+
+impl ErrorHandling {
+    /// Handle errors according to `policy`, starting without any connections.
+    fn new(policy: ErrorPolicy) -> Self {
+        Self {
+            policy,
+            stats: Arc::default(),
+        }
+    }
+
+    /// Count a connection which was accepted.
+    fn accepted(&self) {
+        lock(&self.stats).accepted += 1;
+    }
+
+    /// Count a connection which was refused because the server was full.
+    fn refused(&self) {
+        lock(&self.stats).refused += 1;
+    }
+
+    /// Log and count the result of the connection with `addr`. Returns the error if it is fatal to the server, which
+    /// [`ServerError::ChannelTermination`] always is.
+    fn conclude(
+        &self,
+        addr: SocketAddr,
+        result: Result<(), ServerError>,
+    ) -> Result<(), ServerError> {
+        let e = match result {
+            Ok(()) => {
+                info!("Closed connection to {addr}");
+                return Ok(());
+            }
+            Err(e @ ServerError::ChannelTermination) => return Err(e),
+            Err(ServerError::IoError(e)) => e,
+        };
+
+        let mut stats = lock(&self.stats);
+        stats.failed += 1;
+        *stats.errors.entry(e.kind()).or_default() += 1;
+        drop(stats);
+
+        if self.policy.is_fatal(&e) {
+            error!("{addr} handler encountered a fatal error: {e}");
+            Err(e.into())
+        } else {
+            error!("{addr} handler encountered an error, dropping the connection: {e}");
+            Ok(())
+        }
+    }
+}
+
+/// The configuration of a server, see [`server_with_config`] and [`broadcast_server_with_config`].
+///
+/// A configuration is created for the address to listen at, and adjusted with its `with_` methods. Every setting which
+/// is not adjusted keeps the default used by [`server`].
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use tdtp::server::{ErrorPolicy, ServerConfig};
+///
+/// let config = ServerConfig::new("0.0.0.0:8000")
+///     .expect("the address did not resolve")
+///     .with_heartbeat(Duration::from_millis(500))
+///     .with_nodelay(true)
+///     .with_write_timeout(Some(Duration::from_secs(10)))
+///     .with_max_connections(Some(16))
+///     .with_error_policy(ErrorPolicy::all_fatal());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// The addresses to listen at.
+    addrs: Vec<SocketAddr>,
+    /// The interval after which an idle connection is sent a heartbeat.
+    heartbeat: Duration,
+    /// How long the server waits for the hello of a client, or `None` to wait indefinitely.
+    handshake_timeout: Option<Duration>,
+    /// The options of the stream of every connection.
+    socket: SocketOptions,
+    /// How many packets are queued for each client of a broadcast server.
+    queue_size: usize,
+    /// How many of the packets sent most recently are kept for resuming clients.
+    replay_size: usize,
+    /// How many connections a broadcast server serves at once, or `None` for no limit.
+    max_connections: Option<usize>,
+    /// The connection types clients may request.
+    connection_types: Vec<ConnectionType>,
+    /// Which errors of a connection are fatal.
+    policy: ErrorPolicy,
+}
+
+impl ServerConfig {
+    /// The default configuration of a server listening at every address `addr` resolves to.
+    ///
+    /// # Errors
+    /// Returns an I/O error if `addr` could not be resolved.
+    pub fn new(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            addrs: addr.to_socket_addrs()?.collect(),
+            heartbeat: DEFAULT_HEARTBEAT,
+            handshake_timeout: Some(DEFAULT_HANDSHAKE_TIMEOUT),
+            socket: SocketOptions::default(),
+            queue_size: DEFAULT_QUEUE_SIZE,
+            replay_size: DEFAULT_REPLAY_SIZE,
+            max_connections: None,
+            connection_types: vec![ConnectionType::Data],
+            policy: ErrorPolicy::default(),
+        })
+    }
+
+    /// Set the interval after which an idle client is sent an [`EMP`](crate::consts::EMP) heartbeat. Defaults to
+    /// [`DEFAULT_HEARTBEAT`].
+    #[must_use]
+    pub fn with_heartbeat(mut self, heartbeat: Duration) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Set how long the server waits for a connecting client to send its hello before the connection fails, or `None`
+    /// to wait indefinitely. Defaults to [`DEFAULT_HANDSHAKE_TIMEOUT`].
+    ///
+    /// Without a timeout, a client which never sends its hello stalls a [`server`], and keeps a server which was shut
+    /// down from exiting.
+    #[must_use]
+    pub fn with_handshake_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Set how long a write to a client may block, e.g. because the client stopped reading, before the connection
+    /// fails. By default, writes block indefinitely.
+    #[must_use]
+    pub fn with_write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.socket.write_timeout = timeout;
+        self
+    }
+
+    /// Set whether Nagle's algorithm is disabled (`TCP_NODELAY`), so that every frame is sent right away instead of
+    /// being coalesced with the following ones. Defaults to `false`.
+    #[must_use]
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.socket.nodelay = nodelay;
+        self
+    }
+
+    /// Set after how long an idle connection is probed with TCP keepalive. By default, connections are not probed.
+    #[must_use]
+    pub fn with_keepalive(mut self, keepalive: Option<Duration>) -> Self {
+        self.socket.keepalive = keepalive;
+        self
+    }
+
+    /// Set the size of the send buffer of every connection's socket in bytes, or `None` to keep the system default.
+    #[must_use]
+    pub fn with_send_buffer(mut self, size: Option<usize>) -> Self {
+        self.socket.send_buffer = size;
+        self
+    }
+
+    /// Set the size of the receive buffer of every connection's socket in bytes, or `None` to keep the system default.
+    #[must_use]
+    pub fn with_recv_buffer(mut self, size: Option<usize>) -> Self {
+        self.socket.recv_buffer = size;
+        self
+    }
+
+    /// Set how many packets are queued for each client of a broadcast server. A client whose queue is full misses
+    /// packets. Defaults to [`DEFAULT_QUEUE_SIZE`].
+    ///
+    /// A queue holds at least one packet, so `0` is treated as `1`.
+    #[must_use]
+    pub fn with_queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size.max(1);
+        self
+    }
+
+    /// Set how many of the packets sent most recently are kept to be replayed to resuming clients. Defaults to
+    /// [`DEFAULT_REPLAY_SIZE`].
+    #[must_use]
+    pub fn with_replay_size(mut self, replay_size: usize) -> Self {
+        self.replay_size = replay_size;
+        self
+    }
+
+    /// Set how many connections a broadcast server serves at once, or `None` for no limit, which is the default.
+    /// Further clients are rejected with [`RejectReason::ServerFull`].
+    ///
+    /// A server which is not a broadcast server serves a single connection at a time regardless.
+    #[must_use]
+    pub fn with_max_connections(mut self, max_connections: Option<usize>) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Set the connection types clients may request. A client requesting any other is rejected with
+    /// [`RejectReason::UnsupportedConnection`]. By default, every connection type is allowed.
+    #[must_use]
+    pub fn with_connection_types(
+        mut self,
+        types: impl IntoIterator<Item = ConnectionType>,
+    ) -> Self {
+        self.connection_types = types.into_iter().collect();
+        self
+    }
+
+    /// Set which errors of a connection are fatal to the server. By default, none is.
+    #[must_use]
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
 /// Listen for a connection at the given address.
 ///
-/// The server will relay the packets sent over the given `supplier` to the connector.
+/// The server will relay the packets sent over the given `supplier` to the connector. These are either timestamps
+/// ([`OutgoingDataPacket`]) or [`Packet`]s carrying detector metadata.
 /// If `supplier` hangs up, the server will exit with `Err(ServerError::ChannelTermination)`.
 ///
+/// While no packets are available, the server sends an [`EMP`](crate::consts::EMP) heartbeat every [`DEFAULT_HEARTBEAT`]. To configure the
+/// interval, see [`server_with_heartbeat`], and for all other settings, see [`server_with_config`].
+///
+/// The last [`DEFAULT_REPLAY_SIZE`] packets sent are kept, so that a client which lost its connection can resume
+/// where it left off and is sent the packets it missed.
+///
+/// If a connection fails, e.g. because the client disappeared, the error is logged and the server accepts the next
+/// connection. To make some errors fatal, pass an [`ErrorPolicy`] to [`ServerConfig::with_error_policy`] and run the
+/// server with [`server_with_config`].
+///
 /// Note: this is a single-threaded server, it does not support multiple simultaneous connections.
+/// For that, see [`broadcast_server`].
+///
+/// # Errors
+/// Returns either an I/O error or an error indicating that the receiver to the supplied sender hung up.
+///
+/// # Examples
+/// ```no_run
+/// use std::{thread::spawn, sync::mpsc};
+/// use tdtp::server::{OutgoingDataPacket, server};
+///
+/// let (tx, rx) = mpsc::channel::<OutgoingDataPacket>();
+///
+/// let supplier_thread = spawn(move || loop {
+///     tx.send(todo!()); // send a packet to the server
+/// });
+///
+/// server("localhost:8000", rx).expect("an I/O error occurred");
+/// ```
+pub fn server(
+    addr: impl ToSocketAddrs,
+    supplier: Receiver<impl Into<Packet> + Send + 'static>,
+) -> Result<Infallible, ServerError> {
+    server_with_heartbeat(addr, supplier, DEFAULT_HEARTBEAT)
+}
+
+/// Like [`server`], but sends an [`EMP`](crate::consts::EMP) heartbeat once no packet has been sent for `heartbeat`.
+///
+/// # Errors
+/// See [`server`].
+pub fn server_with_heartbeat(
+    addr: impl ToSocketAddrs,
+    supplier: Receiver<impl Into<Packet> + Send + 'static>,
+    heartbeat: Duration,
+) -> Result<Infallible, ServerError> {
+    server_with_config(ServerConfig::new(addr)?.with_heartbeat(heartbeat), supplier)
+}
+
+/// Like [`server`], but configured by `config`. See [`ServerConfig`].
+///
+/// # Errors
+/// See [`server`]. Additionally, a connection which fails with an error the [`ErrorPolicy`] of `config` makes fatal
+/// is returned.
+///
+/// # Example
+/// ```no_run
+/// use std::{sync::mpsc, time::Duration};
+/// use tdtp::server::{OutgoingDataPacket, ServerConfig, server_with_config};
+///
+/// let (tx, rx) = mpsc::channel::<OutgoingDataPacket>();
+/// let config = ServerConfig::new("localhost:8000")
+///     .expect("the address did not resolve")
+///     .with_handshake_timeout(Some(Duration::from_secs(5)))
+///     .with_nodelay(true);
+///
+/// server_with_config(config, rx).expect("an I/O error occurred");
+/// ```
+#[expect(clippy::needless_pass_by_value)]
+pub fn server_with_config(
+    config: ServerConfig,
+    supplier: Receiver<impl Into<Packet> + Send + 'static>,
+) -> Result<Infallible, ServerError> {
+    let listener = Listeners::bind(config.addrs.as_slice())?;
+    info!("Now listening for connections");
+
+    let errors = ErrorHandling::new(config.policy.clone());
+    serve(&listener, supplier, &config, &errors)?;
+    unreachable!("only a spawned server can be shut down")
+}
+
+/// Serve one connection at a time, until the supplier hangs up, a connection fails with a fatal error or the server
+/// is shut down. See [`server`].
+fn serve(
+    listener: &Listeners,
+    supplier: Receiver<impl Into<Packet> + Send + 'static>,
+    config: &ServerConfig,
+    errors: &ErrorHandling,
+) -> Result<(), ServerError> {
+    // the supplier is drained by a separate thread, so that a handler can wait for packets and client input at once.
+    // the channel holds a full batch, so that a backlog of the supplier can be sent in batches
+    let (notify, events) = sync_channel(usize::from(MAX_BATCH));
+    let forward = notify.clone();
+    let replay = Arc::new(Mutex::new(Replay::new(config.replay_size)));
+    let numbering = Arc::clone(&replay);
+    let shutdown = Arc::clone(&listener.shutdown);
+    thread::spawn(move || {
+        let mut supply = Supply::new(&supplier, &shutdown);
+        let end = loop {
+            match supply.next() {
+                Ok(packet) => {
+                    let seq = lock(&numbering).push(packet);
+                    if forward.send(Event::Packet(seq, packet)).is_err() {
+                        return;
+                    }
+                }
+                Err(end) => break end,
+            }
+        };
+
+        forward.send(end).ok();
+    });
+
+    let mut id = 0;
+    loop {
+        let (conn, addr) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(_) if listener.shutdown.requested() => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if listener.shutdown.requested() {
+            debug!("Server is shutting down, dropping connection from {addr}");
+            continue;
+        }
+
+        info!("Received connection from {addr}");
+        errors.accepted();
+        id += 1;
+
+        let connection = Connection {
+            id,
+            events: &events,
+            notify: &notify,
+            config,
+            replay: &replay,
+        };
+
+        errors.conclude(addr, router(conn, addr, &connection))?;
+    }
+}
+
+/// Listen for connections at the given address, serving every connection simultaneously on its own thread.
+///
+/// Every packet sent over `supplier` is relayed to all connected clients. Each client has its own queue holding up to
+/// `queue_size` packets; if a client does not keep up and its queue is full, packets are dropped for that client only,
+/// so a slow client cannot stall the others. An idle client is sent an [`EMP`](crate::consts::EMP) heartbeat every `heartbeat`.
+///
+/// Like in [`server`], the last [`DEFAULT_REPLAY_SIZE`] packets are kept for clients which resume their connection, and
+/// a connection which fails does not affect the others. To configure the server further, see
+/// [`broadcast_server_with_config`].
+///
+/// If `supplier` hangs up, all connected clients are disconnected and the server exits with
+/// `Err(ServerError::ChannelTermination)`.
 ///
 /// # Errors
 /// Returns either an I/O error or an error indicating that the receiver to the supplied sender hung up.
@@ -58,138 +588,1380 @@ impl From<io::Error> for ServerError {
 /// # Examples
 /// ```no_run
 /// use std::{thread::spawn, sync::mpsc};
-/// use core::net::{IpAddr, Ipv4Addr};
-/// use tdtp_impl::server::Server;
+/// use tdtp::{codec::Packet, server::{DEFAULT_HEARTBEAT, broadcast_server}};
+///
+/// let (tx, rx) = mpsc::channel::<Packet>();
+///
+/// let supplier_thread = spawn(move || loop {
+///     // send a packet, along with the detector which registered it, to all connected clients
+///     tx.send(Packet::new(todo!()).with_channel(1));
+/// });
+///
+/// broadcast_server("0.0.0.0:8000", rx, 8192, DEFAULT_HEARTBEAT)
+///     .expect("an I/O error occurred");
+/// ```
+pub fn broadcast_server(
+    addr: impl ToSocketAddrs,
+    supplier: Receiver<impl Into<Packet> + Send + 'static>,
+    queue_size: usize,
+    heartbeat: Duration,
+) -> Result<Infallible, ServerError> {
+    let config = ServerConfig::new(addr)?
+        .with_queue_size(queue_size)
+        .with_heartbeat(heartbeat);
+    broadcast_server_with_config(config, supplier)
+}
+
+/// Like [`broadcast_server`], but configured by `config`. See [`ServerConfig`].
+///
+/// # Errors
+/// See [`server_with_config`].
+///
+/// # Example
+/// ```no_run
+/// use std::sync::mpsc;
+/// use tdtp::server::{OutgoingDataPacket, ServerConfig, broadcast_server_with_config};
+///
+/// let (tx, rx) = mpsc::channel::<OutgoingDataPacket>();
+/// let config = ServerConfig::new("0.0.0.0:8000")
+///     .expect("the address did not resolve")
+///     .with_queue_size(65536)
+///     .with_max_connections(Some(8));
+///
+/// broadcast_server_with_config(config, rx).expect("an I/O error occurred");
+/// ```
+pub fn broadcast_server_with_config(
+    config: ServerConfig,
+    supplier: Receiver<impl Into<Packet> + Send + 'static>,
+) -> Result<Infallible, ServerError> {
+    let listener = Listeners::bind(config.addrs.as_slice())?;
+    info!("Now broadcasting to connections");
+
+    let errors = ErrorHandling::new(config.policy.clone());
+    broadcast(&listener, supplier, &Arc::new(config), &errors)?;
+    unreachable!("only a spawned server can be shut down")
+}
+
+/// Serve every connection on its own thread, until the supplier hangs up or the server is shut down. See
+/// [`broadcast_server`].
+///
+/// A connection which fails with a fatal error shuts the server down, which then exits with that error. Once the server
+/// is shut down, this waits for all handlers to disconnect their clients.
+fn broadcast(
+    listener: &Listeners,
+    supplier: Receiver<impl Into<Packet> + Send + 'static>,
+    config: &Arc<ServerConfig>,
+    errors: &ErrorHandling,
+) -> Result<(), ServerError> {
+    let fatal = Arc::new(Mutex::new(None));
+    let subscribers = Subscribers::new(config.replay_size);
+    let fan_out = subscribers.clone();
+    let shutdown = Arc::clone(&listener.shutdown);
+    let fan_out = thread::spawn(move || fan_out.broadcast(&supplier, &shutdown));
+    let mut handlers: Vec<JoinHandle<()>> = Vec::new();
+
+    let mut id = 0;
+    loop {
+        let (conn, addr) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(_) if listener.shutdown.requested() => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!("Received connection from {addr}");
+        handlers.retain(|handler| !handler.is_finished());
+        if config
+            .max_connections
+            .is_some_and(|max| handlers.len() >= max)
+        {
+            errors.refused();
+            refuse(conn, addr, Arc::clone(config));
+            continue;
+        }
+        id += 1;
+
+        let Some((notify, events)) =
+            subscribers.subscribe(addr, conn.try_clone()?, config.queue_size)
+        else {
+            close(conn, &Frame::Exit)?;
+            if listener.shutdown.requested() {
+                continue;
+            }
+
+            warn!("Data packet supplier hung up, refused connection from {addr}");
+            return Err(ServerError::ChannelTermination);
+        };
+
+        errors.accepted();
+        let replay = Arc::clone(&subscribers.replay);
+        let (config, errors, fatal, shutdown) = (
+            Arc::clone(config),
+            errors.clone(),
+            Arc::clone(&fatal),
+            Arc::clone(&listener.shutdown),
+        );
+        handlers.push(thread::spawn(move || {
+            let connection = Connection {
+                id,
+                events: &events,
+                notify: &notify,
+                config: &config,
+                replay: &replay,
+            };
+
+            match errors.conclude(addr, router(conn, addr, &connection)) {
+                Ok(()) => (),
+                // the other clients are disconnected by their own handlers
+                Err(e @ ServerError::ChannelTermination) => {
+                    error!("{addr} handler encountered an error: {e}");
+                }
+                Err(e @ ServerError::IoError(_)) => {
+                    lock(&fatal).get_or_insert(e);
+                    shutdown.request();
+                }
+            }
+        }));
+    }
+
+    // the handlers disconnect their clients once the fan-out has queued the shutdown for them
+    fan_out.join().ok();
+    for handler in handlers {
+        handler.join().ok();
+    }
+
+    match lock(&fatal).take() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Reject the client of the given connection on its own thread, because the server is full.
+fn refuse(mut stream: TcpStream, addr: SocketAddr, config: Arc<ServerConfig>) {
+    warn!("Server is full, refusing connection from {addr}");
+    thread::spawn(move || {
+        if let Err(e) = handshake(&mut stream, addr, &config, true) {
+            debug!("Failed to reject {addr}: {e}");
+        }
+    });
+}
+
+/// Like [`server`], but runs the server on its own thread, and returns a [`ServerHandle`] to shut it down with. An
+/// idle client is sent an [`EMP`](crate::consts::EMP) heartbeat every `heartbeat`.
+///
+/// The server is listening once this function returns. A connection which fails with an error `policy` makes fatal
+/// stops the server, which is then returned by [`ServerHandle::join`]. Other errors only drop their connection, and are
+/// counted in [`ServerHandle::stats`].
+///
+/// # Errors
+/// Returns an I/O error if the server could not listen at `addr`. The errors encountered while running are returned by
+/// [`ServerHandle::join`].
+///
+/// # Example
+/// ```no_run
+/// use std::sync::mpsc;
+/// use tdtp::server::{DEFAULT_HEARTBEAT, ErrorPolicy, OutgoingDataPacket, spawn_server};
+///
+/// let (tx, rx) = mpsc::channel::<OutgoingDataPacket>();
+/// let server = spawn_server("localhost:8000", rx, DEFAULT_HEARTBEAT, ErrorPolicy::default())
+///     .expect("an I/O error occurred");
+///
+/// // hand `tx` to the thread which produces packets, and once the application is done:
+/// server.shutdown();
+/// server.join().expect("the server encountered an error");
+/// ```
+pub fn spawn_server(
+    addr: impl ToSocketAddrs,
+    supplier: Receiver<impl Into<Packet> + Send + 'static>,
+    heartbeat: Duration,
+    policy: ErrorPolicy,
+) -> Result<ServerHandle, ServerError> {
+    let config = ServerConfig::new(addr)?
+        .with_heartbeat(heartbeat)
+        .with_error_policy(policy);
+    spawn_server_with_config(config, supplier)
+}
+
+/// Like [`spawn_server`], but configured by `config`. See [`ServerConfig`].
+///
+/// # Errors
+/// See [`spawn_server`].
+pub fn spawn_server_with_config(
+    config: ServerConfig,
+    supplier: Receiver<impl Into<Packet> + Send + 'static>,
+) -> Result<ServerHandle, ServerError> {
+    let listener = Listeners::bind(config.addrs.as_slice())?;
+    info!("Now listening for connections");
+
+    let errors = ErrorHandling::new(config.policy.clone());
+    Ok(ServerHandle::spawn(
+        listener,
+        errors,
+        move |listener, errors| serve(listener, supplier, &config, errors),
+    ))
+}
+
+/// Like [`broadcast_server`], but runs the server on its own thread, and returns a [`ServerHandle`] to shut it down
+/// with. See [`spawn_server`].
+///
+/// # Errors
+/// See [`spawn_server`].
+pub fn spawn_broadcast_server(
+    addr: impl ToSocketAddrs,
+    supplier: Receiver<impl Into<Packet> + Send + 'static>,
+    queue_size: usize,
+    heartbeat: Duration,
+    policy: ErrorPolicy,
+) -> Result<ServerHandle, ServerError> {
+    let config = ServerConfig::new(addr)?
+        .with_queue_size(queue_size)
+        .with_heartbeat(heartbeat)
+        .with_error_policy(policy);
+    spawn_broadcast_server_with_config(config, supplier)
+}
+
+/// Like [`spawn_broadcast_server`], but configured by `config`. See [`ServerConfig`].
+///
+/// # Errors
+/// See [`spawn_server`].
+pub fn spawn_broadcast_server_with_config(
+    config: ServerConfig,
+    supplier: Receiver<impl Into<Packet> + Send + 'static>,
+) -> Result<ServerHandle, ServerError> {
+    let listener = Listeners::bind(config.addrs.as_slice())?;
+    info!("Now broadcasting to connections");
+
+    let errors = ErrorHandling::new(config.policy.clone());
+    let config = Arc::new(config);
+    Ok(ServerHandle::spawn(
+        listener,
+        errors,
+        move |listener, errors| broadcast(listener, supplier, &config, errors),
+    ))
+}
+
+/// A handle to a server running on its own thread, see [`spawn_server`] and [`spawn_broadcast_server`].
+///
+/// Dropping the handle detaches the server, which then runs until its supplier hangs up.
+pub struct ServerHandle {
+    /// The shutdown state shared with the server.
+    shutdown: Arc<Shutdown>,
+    /// The connections of the server so far.
+    stats: Arc<Mutex<ConnectionStats>>,
+    /// The thread running the server.
+    thread: JoinHandle<Result<(), ServerError>>,
+}
+
+impl ServerHandle {
+    /// Run the given server on its own thread, handling the errors of its connections with `errors`.
+    fn spawn(
+        listener: Listeners,
+        errors: ErrorHandling,
+        server: impl FnOnce(&Listeners, &ErrorHandling) -> Result<(), ServerError> + Send + 'static,
+    ) -> Self {
+        Self {
+            shutdown: Arc::clone(&listener.shutdown),
+            stats: Arc::clone(&errors.stats),
+            thread: thread::spawn(move || server(&listener, &errors)),
+        }
+    }
+
+    /// The connections the server accepted so far, and the errors they failed with.
+    #[must_use]
+    pub fn stats(&self) -> ConnectionStats {
+        lock(&self.stats).clone()
+    }
+
+    /// The addresses the server listens at.
+    #[must_use]
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.shutdown.addrs
+    }
+
+    /// Shut the server down.
+    ///
+    /// The server stops accepting connections and closes its listeners. The packets which are queued, either by the
+    /// supplier or for a client, are still sent, after which every client is sent [`SIG_EXIT`] and disconnected. The
+    /// supplier is drained for at most five seconds, in case it does not run empty. A client of a broadcast server whose
+    /// queue is still full at that point is disconnected right away, without [`SIG_EXIT`], since it may not read at all.
+    ///
+    /// This function returns right away, see [`ServerHandle::join`] to wait for the server to exit.
+    pub fn shutdown(&self) {
+        self.shutdown.request();
+    }
+
+    /// Wait for the server to exit, which it does once it was shut down, once its supplier hung up, or once a connection
+    /// failed with a fatal error.
+    ///
+    /// # Errors
+    /// Returns either the fatal I/O error of a connection or an error indicating that the supplier hung up before the
+    /// server was shut down.
+    pub fn join(self) -> Result<(), ServerError> {
+        self.thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+/// The shutdown state of a server, shared by the server, its listeners and its [`ServerHandle`].
+struct Shutdown {
+    /// Whether the server was shut down.
+    requested: AtomicBool,
+    /// The addresses the listeners are bound to.
+    addrs: Vec<SocketAddr>,
+}
+
+impl Shutdown {
+    /// Whether the server was shut down.
+    fn requested(&self) -> bool {
+        self.requested.load(Ordering::Acquire)
+    }
+
+    /// Shut the server down, and wake its listeners, so that they close.
+    fn request(&self) {
+        if self.requested.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        info!("Shutting down the server");
+        self.wake();
+    }
+
+    /// Wake the listeners by connecting to them, so that the server handles the end of its supplier or its shutdown
+    /// without waiting for a client to connect.
+    fn wake(&self) {
+        for &addr in &self.addrs {
+            let mut wake = addr;
+            if addr.ip().is_unspecified() {
+                wake.set_ip(match addr {
+                    SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                    SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+                });
+            }
+
+            if let Err(e) = TcpStream::connect_timeout(&wake, WAKE_TIMEOUT) {
+                warn!("Failed to wake the listener at {addr}: {e}");
+            }
+        }
+    }
+}
+
+/// The packets of a supplier, which run out once the server is shut down.
+struct Supply<'a, P> {
+    /// The supplier.
+    supplier: &'a Receiver<P>,
+    /// The shutdown state of the server.
+    shutdown: &'a Shutdown,
+    /// Until when the supplier is drained, once the server was shut down.
+    deadline: Option<Instant>,
+}
+
+impl<'a, P: Into<Packet>> Supply<'a, P> {
+    /// Take the packets of `supplier`, until the server is shut down.
+    fn new(supplier: &'a Receiver<P>, shutdown: &'a Shutdown) -> Self {
+        Self {
+            supplier,
+            shutdown,
+            deadline: None,
+        }
+    }
+
+    /// Wait for the next packet. Once there is none, returns the event to end the connections with instead:
+    /// [`Event::Terminated`] if the supplier hung up, or [`Event::Shutdown`] if the server was shut down and the packets
+    /// the supplier had queued were taken.
+    fn next(&mut self) -> Result<Packet, Event> {
+        loop {
+            if self.deadline.is_none() && self.shutdown.requested() {
+                self.deadline = Some(Instant::now() + DRAIN_TIMEOUT);
+            }
+
+            if let Some(deadline) = self.deadline {
+                return match self.supplier.try_recv() {
+                    Ok(packet) if Instant::now() < deadline => Ok(packet.into()),
+                    Ok(_) | Err(TryRecvError::Empty | TryRecvError::Disconnected) => {
+                        Err(Event::Shutdown)
+                    }
+                };
+            }
+
+            match self.supplier.recv_timeout(SHUTDOWN_POLL) {
+                Ok(packet) => return Ok(packet.into()),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return Err(Event::Terminated),
+            }
+        }
+    }
+}
+
+/// The listeners of a server, one for every address the server was asked to bind to.
+///
+/// Every listener accepts connections on its own thread; the connections of all listeners are handed to the server
+/// through a single channel.
+struct Listeners {
+    /// The connections accepted by any listener.
+    incoming: Receiver<io::Result<(TcpStream, SocketAddr)>>,
+    /// The shutdown state of the server, which closes the listeners.
+    shutdown: Arc<Shutdown>,
+}
+
+impl Listeners {
+    /// Bind a listener to every address `addr` resolves to.
+    ///
+    /// An address which cannot be bound to is skipped with a warning. If no address can be bound to, the error of the
+    /// last attempt is returned.
+    fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let mut listeners = Vec::new();
+        let mut last_error = None;
+
+        info!("Starting listeners");
+        for addr in addr.to_socket_addrs()? {
+            match TcpListener::bind(addr) {
+                Ok(listener) => {
+                    info!("Started listener at {}", listener.local_addr()?);
+                    listeners.push(listener);
+                }
+                Err(e) => {
+                    warn!("Failed to bind to {addr}: {e}");
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        if listeners.is_empty() {
+            return Err(last_error.unwrap_or_else(no_addresses));
+        }
+
+        let shutdown = Arc::new(Shutdown {
+            requested: AtomicBool::new(false),
+            addrs: listeners
+                .iter()
+                .map(TcpListener::local_addr)
+                .collect::<io::Result<_>>()?,
+        });
+
+        let (tx, incoming) = sync_channel(0);
+        for listener in listeners {
+            let tx = tx.clone();
+            let shutdown = Arc::clone(&shutdown);
+            thread::spawn(move || {
+                // the listener is dropped once the server stops taking connections, or was shut down
+                loop {
+                    let accepted = listener.accept();
+                    if shutdown.requested() || tx.send(accepted).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Ok(Self { incoming, shutdown })
+    }
+
+    /// Wait for a connection on any of the listeners.
+    fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        self.incoming
+            .recv()
+            .unwrap_or_else(|_| Err(io::Error::from(ErrorKind::NotConnected)))
+    }
+}
+
+/// The queue of a single [`broadcast_server`] client.
+struct Subscriber {
+    /// The address of the client.
+    addr: SocketAddr,
+    /// The sending end of the queue.
+    queue: SyncSender<Event>,
+    /// The socket of the client, which is shut down if the end of the server does not fit into the queue.
+    socket: TcpStream,
+    /// How many packets were dropped because the queue was full, since they were last reported.
+    dropped: u64,
+    /// When the dropped packets were last reported.
+    reported: Instant,
+}
+
+impl Subscriber {
+    /// Queue the given event. Returns `false` if the client unsubscribed.
+    ///
+    /// If the queue is full, the event is dropped. Dropped packets are counted, and reported at most once every
+    /// [`DROP_REPORT_INTERVAL`], so that a client which does not keep up does not flood the log.
+    fn offer(&mut self, event: Event) -> bool {
+        match self.queue.try_send(event) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                if self.reported.elapsed() >= DROP_REPORT_INTERVAL {
+                    self.report();
+                }
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                debug!("{} unsubscribed", self.addr);
+                self.report();
+                false
+            }
+        }
+    }
+
+    /// Queue the event ending the connection. If the queue is full, the client may not read at all, leaving its handler
+    /// stuck writing to it. Rather than waiting for room in the queue, the client is then disconnected by shutting down
+    /// its socket, which fails the write.
+    fn end(&mut self, end: Event) {
+        self.report();
+        if let Err(TrySendError::Full(_)) = self.queue.try_send(end) {
+            warn!("Queue of {} is full, disconnecting it", self.addr);
+            if let Err(e) = self.socket.shutdown(net::Shutdown::Both) {
+                debug!("Failed to shut down the socket of {}: {e}", self.addr);
+            }
+        }
+    }
+
+    /// Log the packets dropped since they were last reported, if any.
+    fn report(&mut self) {
+        if self.dropped > 0 {
+            warn!(
+                "Queue of {} is full, dropped {} packets in the last {:?}",
+                self.addr,
+                self.dropped,
+                self.reported.elapsed()
+            );
+            self.dropped = 0;
+        }
+        self.reported = Instant::now();
+    }
+}
+
+/// The per-client queues of a [`broadcast_server`], along with the packets to replay to resuming clients.
+#[derive(Clone)]
+struct Subscribers {
+    /// The queues of all clients, or `None` once the supplier has hung up.
+    queues: Arc<Mutex<Option<Vec<Subscriber>>>>,
+    /// The packets most recently broadcast.
+    replay: Arc<Mutex<Replay>>,
+}
+
+impl Subscribers {
+    /// Create the queues of a server without any clients, which keeps `replay_size` packets for resuming clients.
+    fn new(replay_size: usize) -> Self {
+        Self {
+            queues: Arc::new(Mutex::new(Some(Vec::new()))),
+            replay: Arc::new(Mutex::new(Replay::new(replay_size))),
+        }
+    }
+
+    /// Register a new client connected over `socket` and return both ends of its queue, or `None` if the supplier has
+    /// hung up.
+    fn subscribe(
+        &self,
+        addr: SocketAddr,
+        socket: TcpStream,
+        queue_size: usize,
+    ) -> Option<(SyncSender<Event>, Receiver<Event>)> {
+        let mut subscribers = lock(&self.queues);
+        let (tx, rx) = sync_channel(queue_size);
+        subscribers.as_mut()?.push(Subscriber {
+            addr,
+            queue: tx.clone(),
+            socket,
+            dropped: 0,
+            reported: Instant::now(),
+        });
+
+        Some((tx, rx))
+    }
+
+    /// Relay every packet from `supplier` to all subscribers until it hangs up or the server is shut down, then
+    /// disconnect them.
+    fn broadcast(&self, supplier: &Receiver<impl Into<Packet>>, shutdown: &Shutdown) {
+        let mut supply = Supply::new(supplier, shutdown);
+        let end = loop {
+            let packet = match supply.next() {
+                Ok(packet) => packet,
+                Err(end) => break end,
+            };
+            let mut subscribers = lock(&self.queues);
+            let Some(subscribers) = subscribers.as_mut() else {
+                return;
+            };
+
+            // numbered while holding the queues, so that a resuming client either finds a packet in its queue or
+            // in the replay buffer
+            let seq = lock(&self.replay).push(packet);
+            subscribers.retain_mut(|subscriber| subscriber.offer(Event::Packet(seq, packet)));
+        };
+
+        if let Event::Terminated = end {
+            warn!("Data packet supplier hung up, disconnecting all clients");
+        } else {
+            info!("Server was shut down, disconnecting all clients");
+        }
+        let subscribers = lock(&self.queues).take();
+
+        for mut subscriber in subscribers.into_iter().flatten() {
+            subscriber.end(end);
+        }
+
+        // the server only learns that the supplier hung up when it accepts a connection
+        if let Event::Terminated = end {
+            shutdown.wake();
+        }
+    }
+}
+
+/// The packets most recently taken from the supplier, kept to be replayed to resuming clients.
+///
+/// Every packet is numbered with a sequence number, starting at `0` and increasing by one for every packet. Together
+/// with the randomly chosen session ID, it identifies the packet even across restarts of the server.
+struct Replay {
+    /// The ID of the numbering.
+    session: u64,
+    /// The packets kept, oldest first.
+    packets: VecDeque<Packet>,
+    /// How many packets are kept at most.
+    capacity: usize,
+    /// The sequence number of the next packet.
+    next: u64,
+}
+
+impl Replay {
+    /// Create an empty replay buffer keeping up to `capacity` packets.
+    fn new(capacity: usize) -> Self {
+        Self {
+            session: random(),
+            packets: VecDeque::with_capacity(capacity),
+            capacity,
+            next: 0,
+        }
+    }
+
+    /// Number the given packet and keep it, dropping the oldest packet if the buffer is full. Returns the sequence
+    /// number of the packet.
+    fn push(&mut self, packet: Packet) -> u64 {
+        if self.capacity > 0 {
+            if self.packets.len() == self.capacity {
+                self.packets.pop_front();
+            }
+            self.packets.push_back(packet);
+        }
+
+        let seq = self.next;
+        self.next += 1;
+        seq
+    }
+
+    /// The packets kept from the sequence number `from` onwards, along with their sequence numbers.
+    ///
+    /// If the packets before `from` are no longer kept, the returned packets start with the oldest one kept instead.
+    fn since(&self, from: u64) -> Vec<(u64, Packet)> {
+        let oldest = self.next - self.packets.len() as u64;
+        let start = from.clamp(oldest, self.next);
+
+        #[expect(clippy::cast_possible_truncation)]
+        let skip = (start - oldest) as usize;
+        (start..)
+            .zip(self.packets.iter().skip(skip).copied())
+            .collect()
+    }
+}
+
+/// Lock the given mutex, ignoring poisoning.
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Route the incoming connection to a handler.
+///
+/// Currently, this has only one handler registered.
+fn router(
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    connection: &Connection<'_>,
+) -> Result<(), ServerError> {
+    let Some((ConnectionType::Data, capabilities)) =
+        handshake(&mut stream, addr, connection.config, false)?
+    else {
+        return Ok(());
+    };
+
+    match data_handler(&mut stream, addr, connection, capabilities) {
+        Ok(()) => {
+            debug!("Writing transmission delimiter to connection");
+        }
+        Err(e @ ServerError::ChannelTermination) => {
+            close(stream, &Frame::Exit)?;
+            return Err(e);
+        }
+        Err(e @ ServerError::IoError(_)) => {
+            error!("Service encountered an I/O error: {e}");
+            return Err(e);
+        }
+    }
+
+    Ok(close(stream, &Frame::Exit)?)
+}
+
+/// Apply the socket options of `config` to the stream of a connecting client, and perform the hello exchange.
+///
+/// Returns the requested connection type and the negotiated capabilities, or `None` if the client was rejected, which
+/// it always is if the server is `full`. A client which sends a bare [`CONN_DATA`] flag instead of a hello message is
+/// treated as a legacy (version `0`) client without any capabilities, whose connection is closed instead of rejected.
+fn handshake(
+    stream: &mut TcpStream,
+    addr: SocketAddr,
+    config: &ServerConfig,
+    full: bool,
+) -> io::Result<Option<(ConnectionType, u16)>> {
+    config.socket.apply(stream)?;
+    stream.set_read_timeout(config.handshake_timeout)?;
+
+    let mut first = [0; 1];
+    stream.read_exact(&mut first)?;
+
+    if first[0] == CONN_DATA {
+        // a legacy client does not understand rejections
+        if full || !config.connection_types.contains(&ConnectionType::Data) {
+            warn!("Closing connection from legacy client {addr}");
+            return Ok(None);
+        }
+
+        info!("{addr} is a legacy client, skipping version negotiation");
+        stream.set_read_timeout(None)?;
+        return Ok(Some((ConnectionType::Data, 0)));
+    } else if first[0] != MAGIC[0] {
+        reject(stream, addr, RejectReason::BadMagic)?;
+        return Ok(None);
+    }
+
+    // the rest of the magic, version, capabilities and connection type
+    let mut hello = [0; 7];
+    stream.read_exact(&mut hello)?;
+    stream.set_read_timeout(None)?;
+
+    match negotiate(hello, &config.connection_types) {
+        Ok(_) if full => {
+            reject(stream, addr, RejectReason::ServerFull)?;
+            Ok(None)
+        }
+        Ok((conn_ty, version, capabilities)) => {
+            let [caps_lo, caps_hi] = capabilities.to_le_bytes();
+            stream.write_all(&[HELLO_ACCEPT, version, caps_lo, caps_hi])?;
+            debug!(
+                "Accepted {addr} with protocol version {version} and capabilities {capabilities:#06x}"
+            );
+
+            Ok(Some((conn_ty, capabilities)))
+        }
+        Err(reason) => {
+            reject(stream, addr, reason)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Validate the rest of a hello message after its first byte.
+///
+/// Returns the requested connection type, along with the protocol version and capabilities negotiated with the
+/// client, or the reason for which the client must be rejected. Only the connection types in `allowed` are accepted.
+fn negotiate(
+    hello: [u8; 7],
+    allowed: &[ConnectionType],
+) -> Result<(ConnectionType, u8, u16), RejectReason> {
+    if hello[..3] != MAGIC[1..] {
+        return Err(RejectReason::BadMagic);
+    }
+
+    let version = hello[3];
+    if version < MIN_PROTOCOL_VERSION {
+        return Err(RejectReason::UnsupportedVersion);
+    }
+
+    // more connection types may be added in the future
+    let conn_ty = match hello[6] {
+        CONN_DATA => ConnectionType::Data,
+        _ => return Err(RejectReason::UnsupportedConnection),
+    };
+    if !allowed.contains(&conn_ty) {
+        return Err(RejectReason::UnsupportedConnection);
+    }
+
+    let mut capabilities = u16::from_le_bytes([hello[4], hello[5]]) & CAPABILITIES;
+    if capabilities & CAP_SYNC == 0 {
+        // offsets need an epoch, which only exists once the clocks were synchronised
+        capabilities &= !CAP_EPOCH;
+    }
+    Ok((conn_ty, version.min(PROTOCOL_VERSION), capabilities))
+}
+
+/// Reject the client for the given reason.
+fn reject(stream: &mut TcpStream, addr: SocketAddr, reason: RejectReason) -> io::Result<()> {
+    warn!("Rejecting {addr}: {reason}");
+    stream.write_all(&[HELLO_REJECT, reason as u8])?;
+    stream.shutdown(std::net::Shutdown::Write)
+}
+
+/// The handler for the [`CONN_DATA`] connection.
+///
+/// The handler blocks until either a packet is available or the client sent a control signal. If neither happens
+/// within the heartbeat interval of the connection, an [`EMP`](crate::consts::EMP) heartbeat is sent. If the client supports it, packets
+/// which are already queued are sent together in a single [`SIG_BATCH`](crate::consts::SIG_BATCH) frame, and timestamps are delta-encoded.
+///
+/// If the client grants credit ([`SIG_CREDIT`](crate::consts::SIG_CREDIT)), packets it has no credit for are taken
+/// from the queue, but only kept in the replay buffer, from which they are sent once the client grants more.
+fn data_handler(
+    stream: &mut TcpStream,
+    addr: SocketAddr,
+    connection: &Connection<'_>,
+    capabilities: u16,
+) -> Result<(), ServerError> {
+    info!("Data connection with {addr} established");
+
+    let reader = stream.try_clone()?;
+    let (id, notify) = (connection.id, connection.notify.clone());
+    thread::spawn(move || read_controls(reader, id, &notify));
+
+    let sync = capabilities & CAP_SYNC != 0;
+    let batch = capabilities & CAP_BATCH != 0;
+    let mut encoder = Encoder::new(capabilities, lock(connection.replay).session);
+    // events which were received, but not handled yet
+    let mut backlog = VecDeque::new();
+    let mut credit = (capabilities & CAP_CREDIT != 0).then(Credit::new);
+
+    let mut next = None;
+    if capabilities & CAP_RESUME != 0 {
+        next = await_client(
+            connection,
+            &mut backlog,
+            RESUME_TIMEOUT,
+            "send a resume request",
+            |event| match event {
+                Event::Resume(_, next) => Some(*next),
+                _ => None,
+            },
+        )?;
+    }
+
+    // the first round sets the epoch, so it must precede any packet
+    if sync {
+        sync_clock(stream, connection, &mut encoder, &mut backlog)?;
+    }
+
+    if let Some(next) = next {
+        let packets = encoder.resume(next, &lock(connection.replay));
+        debug!("Replaying {} packets to {addr}", packets.len());
+
+        match (&mut credit, packets.first()) {
+            (Some(credit), Some(&(from, _))) => credit.hold(from),
+            _ => encoder.write_all(&packets, batch, stream)?,
+        }
+    }
+
+    loop {
+        let event = match backlog.pop_front() {
+            Some(event) => event,
+            None => match connection.events.recv_timeout(connection.config.heartbeat) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => {
+                    encode(&Frame::Empty, stream)?;
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => Event::Terminated,
+            },
+        };
+
+        match event {
+            Event::Packet(seq, packet) => {
+                let packets = if batch {
+                    collect_batch((seq, packet), connection, &mut backlog)
+                } else {
+                    vec![(seq, packet)]
+                };
+
+                match &mut credit {
+                    Some(credit) => match credit.take(&packets) {
+                        [] if credit.heartbeat_due(connection.config.heartbeat) => {
+                            encode(&Frame::Empty, stream)?;
+                        }
+                        [] => (),
+                        packets => encoder.write(packets, stream)?,
+                    },
+                    None => encoder.write(&packets, stream)?,
+                }
+            }
+            Event::Control(id, SIG_EXIT) if id == connection.id => {
+                info!("Client sent exit signal, disconnecting");
+                break Ok(());
+            }
+            Event::Control(id, SIG_SYNC) if sync && id == connection.id => {
+                debug!("Client requested clock synchronisation");
+                sync_clock(stream, connection, &mut encoder, &mut backlog)?;
+            }
+            Event::Credit(id, amount) if id == connection.id => {
+                if let Some(credit) = &mut credit {
+                    let packets = credit.grant(amount, &lock(connection.replay));
+                    encoder.write_all(&packets, batch, stream)?;
+                }
+            }
+            Event::Closed(id) if id == connection.id => {
+                info!("Client closed the connection, disconnecting");
+                break Ok(());
+            }
+            Event::Terminated => {
+                warn!("Data packet supplier hung up, terminating connection with client");
+                break Err(ServerError::ChannelTermination);
+            }
+            Event::Shutdown => {
+                info!("Server was shut down, disconnecting");
+                break Ok(());
+            }
+            // events of previous connections or unknown signals
+            Event::Control(..) | Event::Closed(_) | Event::Resume(..) | Event::Credit(..) => (),
+        }
+    }
+}
+
+/// Collect `first` and the packets queued after it, up to [`MAX_BATCH`] packets.
+///
+/// Collection stops at the first event which is not a packet, which is pushed back onto `backlog`.
+fn collect_batch(
+    first: (u64, Packet),
+    connection: &Connection<'_>,
+    backlog: &mut VecDeque<Event>,
+) -> Vec<(u64, Packet)> {
+    let mut packets = vec![first];
+
+    while packets.len() < usize::from(MAX_BATCH) {
+        let event = match backlog.pop_front() {
+            Some(event) => event,
+            None => match connection.events.try_recv() {
+                Ok(event) => event,
+                Err(_) => break,
+            },
+        };
+
+        match event {
+            Event::Packet(seq, packet) => packets.push((seq, packet)),
+            event => {
+                backlog.push_front(event);
+                break;
+            }
+        }
+    }
+
+    packets
+}
+
+/// The flow control of a connection whose client grants credit ([`SIG_CREDIT`](crate::consts::SIG_CREDIT)).
+struct Credit {
+    /// The amount of further packets the client accepts.
+    available: u64,
+    /// The sequence number of the first packet held back for lack of credit, if any.
+    held: Option<u64>,
+    /// The sequence number following the last packet sent. Packets sent from the replay buffer may still be queued, and
+    /// are skipped once they are taken from the queue.
+    next: Option<u64>,
+    /// When the last packet was sent to the client.
+    last_sent: Instant,
+}
+
+impl Credit {
+    /// The flow control of a connection whose client did not grant any credit yet.
+    fn new() -> Self {
+        Self {
+            available: 0,
+            held: None,
+            next: None,
+            last_sent: Instant::now(),
+        }
+    }
+
+    /// Hold back the packets from the given sequence number on, until the client grants credit for them.
+    fn hold(&mut self, from: u64) {
+        self.held.get_or_insert(from);
+    }
+
+    /// The leading part of `packets` the client has credit for. The rest is held back.
+    fn take<'p>(&mut self, packets: &'p [(u64, Packet)]) -> &'p [(u64, Packet)] {
+        let sent = packets.partition_point(|&(seq, _)| self.next.is_some_and(|next| seq < next));
+        let packets = &packets[sent..];
+        if self.held.is_some() {
+            return &[];
+        }
+
+        let count = usize::try_from(self.available)
+            .unwrap_or(usize::MAX)
+            .min(packets.len());
+        if let Some(&(seq, _)) = packets.get(count) {
+            self.held = Some(seq);
+        }
+        self.spend(&packets[..count])
+    }
+
+    /// Add the credit granted by the client, and return the packets held back which it covers, as far as they are
+    /// still kept in `replay`.
+    fn grant(&mut self, amount: u64, replay: &Replay) -> Vec<(u64, Packet)> {
+        self.available = self.available.saturating_add(amount);
+        let Some(from) = self.held else {
+            return Vec::new();
+        };
+
+        let mut packets = replay.since(from);
+        if let Some(&(start, _)) = packets.first()
+            && start > from
+        {
+            warn!("Packets {from}..{start} which were held back are no longer available");
+        }
+
+        let count = usize::try_from(self.available).unwrap_or(usize::MAX);
+        self.held = packets.get(count).map(|&(seq, _)| seq);
+        packets.truncate(count);
+        self.spend(&packets);
+        packets
+    }
+
+    /// Spend credit on the given packets, which are about to be sent.
+    fn spend<'p>(&mut self, packets: &'p [(u64, Packet)]) -> &'p [(u64, Packet)] {
+        if let Some(&(seq, _)) = packets.last() {
+            self.available -= packets.len() as u64;
+            self.next = Some(seq + 1);
+            self.last_sent = Instant::now();
+        }
+        packets
+    }
+
+    /// Whether a heartbeat is due while packets are held back, because none was sent for `heartbeat`. Since packets
+    /// keep the handler busy, the idle heartbeat does not cover this. A heartbeat which is due is counted as sent.
+    fn heartbeat_due(&mut self, heartbeat: Duration) -> bool {
+        let due = self.last_sent.elapsed() >= heartbeat;
+        if due {
+            self.last_sent = Instant::now();
+        }
+        due
+    }
+}
+
+/// Read the frames sent by the client of the connection with the given ID and forward its control signals as events,
+/// until the stream is closed.
+fn read_controls(stream: TcpStream, id: u64, notify: &SyncSender<Event>) {
+    let mut reader = FrameReader::with_decoder(stream, ControlDecoder::new());
+
+    loop {
+        match reader.next_frame::<io::Error>() {
+            Ok(Frame::Resume(next)) => {
+                if notify.send(Event::Resume(id, next)).is_err() {
+                    return;
+                }
+            }
+            Ok(Frame::Credit(amount)) => {
+                if notify.send(Event::Credit(id, amount)).is_err() {
+                    return;
+                }
+            }
+            Ok(frame) => {
+                if let Some(sig) = control(&frame)
+                    && notify.send(Event::Control(id, sig)).is_err()
+                {
+                    return;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                warn!("Client sent an invalid frame: {e}");
+            }
+            Err(_) => break,
+        }
+    }
+
+    notify.send(Event::Closed(id)).ok();
+}
+
+/// The control signal carried by a frame sent by the client, if it carries one.
 ///
-/// let (tx, rx) = mpsc::channel();
+/// Besides `[CTRL, signal]` frames, a bare [`SIG_EXIT`] as sent by older clients is understood as an exit. Unknown
+/// signals are rejected by the [`ControlDecoder`] already.
+fn control(frame: &Frame) -> Option<u8> {
+    match frame {
+        Frame::Control(sig) => Some(*sig),
+        Frame::Exit => Some(SIG_EXIT),
+        frame => {
+            warn!("Client sent unexpected frame {frame:?}");
+            None
+        }
+    }
+}
+
+/// Run a clock synchronisation round with the client.
 ///
-/// let supplier_thread = spawn(move || loop {
-///     tx.send(todo!()); // send a packet to the server
-/// });
+/// 1. The server sends [`SIG_SYNC`] and marks the instant `A_S`, which becomes the shared epoch.
+/// 2. The client echoes `[CTRL, SIG_SYNC]`, upon which the server measures `D_S`, the time elapsed since `A_S`.
+/// 3. The server sends `D_S` in microseconds, followed by the unix timestamp of `A_S`, both as little-endian `u128`s.
 ///
-/// Server::run(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8000, rx).expect("an I/O error occurred");
-/// ```
-#[expect(clippy::needless_pass_by_value)]
-pub fn server(
-    ip: IpAddr,
-    port: u16,
-    supplier: Receiver<OutgoingDataPacket>,
-) -> Result<Infallible, ServerError> {
-    info!("Starting listener");
-    let listener = TcpListener::bind((ip, port))?;
+/// Packets which arrive while waiting for the echo are pushed onto `backlog`. Once the round is done, `encoder` is
+/// told about its epoch, see [`Encoder::synchronised`].
+fn sync_clock(
+    writer: &mut impl Write,
+    connection: &Connection<'_>,
+    encoder: &mut Encoder,
+    backlog: &mut VecDeque<Event>,
+) -> Result<(), ServerError> {
+    debug!("Starting clock synchronisation");
 
-    info!("Started listener at {ip}:{port}, now listening for connections",);
+    let epoch_micros = unix_micros();
+    encode(&Frame::Sync, writer)?;
+    let epoch = Instant::now();
 
-    while let Ok((conn, addr)) = listener.accept() {
-        info!("Received connection from {addr}");
+    await_client(
+        connection,
+        backlog,
+        SYNC_TIMEOUT,
+        "echo the synchronisation signal",
+        |event| matches!(event, Event::Control(_, SIG_SYNC)).then_some(()),
+    )?;
 
-        match router(conn, addr, &supplier) {
-            Ok(()) => info!("Closed connection to {addr}"),
-            Err(e @ ServerError::ChannelTermination) => return Err(e),
-            Err(e) => {
-                error!("{addr} handler encoutered an error: {e}");
-                return Err(e);
+    let reply = Frame::SyncReply {
+        elapsed: epoch.elapsed().as_micros(),
+        epoch_micros,
+    };
+    encode(&reply, writer)?;
+    encoder.synchronised(epoch_micros);
+    Ok(())
+}
+
+/// Wait up to `timeout` for the client to send the frame expected by `accept`, which is handed every event the client
+/// causes and returns `Some` for the expected one. Any other frame of the client is an error, which is described as
+/// the client failing to do `expected`.
+///
+/// Packets which arrive while waiting are pushed onto `backlog`.
+fn await_client<T>(
+    connection: &Connection<'_>,
+    backlog: &mut VecDeque<Event>,
+    timeout: Duration,
+    expected: &str,
+    accept: impl Fn(&Event) -> Option<T>,
+) -> Result<T, ServerError> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        match connection
+            .events
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            Ok(event @ (Event::Control(id, _) | Event::Closed(id) | Event::Resume(id, _)))
+                if id == connection.id =>
+            {
+                return accept(&event).ok_or_else(|| {
+                    io::Error::new(ErrorKind::InvalidData, format!("client did not {expected}"))
+                        .into()
+                });
+            }
+            Ok(event @ (Event::Packet(..) | Event::Credit(..) | Event::Shutdown)) => {
+                backlog.push_back(event);
+            }
+            Ok(Event::Terminated) | Err(RecvTimeoutError::Disconnected) => {
+                return Err(ServerError::ChannelTermination);
+            }
+            Ok(Event::Control(..) | Event::Closed(_) | Event::Resume(..)) => (),
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(io::Error::new(
+                    ErrorKind::TimedOut,
+                    format!("client did not {expected} in time"),
+                )
+                .into());
             }
         }
     }
+}
 
-    unreachable!()
+/// Encodes packets into frames, according to the capabilities negotiated with the client.
+struct Encoder {
+    /// Whether timestamps may be delta-encoded.
+    delta: bool,
+    /// Whether packets carrying metadata are sent as [`Frame::Event`]s.
+    events: bool,
+    /// The last timestamp sent, which is the base of the next delta.
+    last: Option<OutgoingDataPacket>,
+    /// Whether sequence numbers are sent.
+    sequence: bool,
+    /// The ID of the session which numbered the packets.
+    session: u64,
+    /// The sequence number the client expects next, if it knows about any.
+    next: Option<u64>,
+    /// How timestamps are sent.
+    timestamps: Timestamps,
 }
 
-/// Route the incoming connection to a handler.
-///
-/// Currently, this has only one handler registered.
-fn router(
-    mut stream: TcpStream,
-    addr: SocketAddr,
-    supplier: &Receiver<OutgoingDataPacket>,
-) -> Result<(), ServerError> {
-    let mut conn_ty = [0; 1];
+/// How an [`Encoder`] sends timestamps.
+#[derive(Debug, Clone, Copy)]
+enum Timestamps {
+    /// As the unix timestamps they are.
+    Unix,
+    /// As offsets from the epoch, whose unix timestamp is known once the first synchronisation round set it.
+    Offsets(Option<OutgoingDataPacket>),
+}
 
-    stream.read_exact(&mut conn_ty)?;
+impl Encoder {
+    /// Create an encoder for the given negotiated capabilities, numbering packets in the given session.
+    fn new(capabilities: u16, session: u64) -> Self {
+        Self {
+            delta: capabilities & CAP_DELTA != 0,
+            events: capabilities & CAP_EVENT != 0,
+            last: None,
+            sequence: capabilities & CAP_RESUME != 0,
+            session,
+            next: None,
+            timestamps: if capabilities & CAP_EPOCH != 0 {
+                Timestamps::Offsets(None)
+            } else {
+                Timestamps::Unix
+            },
+        }
+    }
 
-    match conn_ty[0] {
-        CONN_DATA => ConnectionType::Data,
-        _ => {
-            return Ok(());
+    /// Handle the end of a synchronisation round whose epoch has the given unix timestamp. If offsets were negotiated,
+    /// the first round sets the epoch which all following timestamps are relative to.
+    fn synchronised(&mut self, epoch_micros: OutgoingDataPacket) {
+        if let Timestamps::Offsets(epoch) = &mut self.timestamps {
+            epoch.get_or_insert(epoch_micros);
         }
-    };
+    }
 
-    match data_handler(&mut stream, addr, supplier) {
-        Ok(()) => {
-            debug!("Writing transmission delimiter to connection");
+    /// Convert the given unix timestamp into the value sent for it, which is its offset from the epoch if offsets
+    /// are sent. Timestamps before the epoch wrap around, which the client reverts.
+    fn offset(&self, timestamp: OutgoingDataPacket) -> u128 {
+        match self.timestamps {
+            Timestamps::Offsets(Some(epoch)) => timestamp.wrapping_sub(epoch),
+            _ => timestamp,
         }
-        Err(e @ ServerError::ChannelTermination) => {
-            close(stream)?;
-            return Err(e);
+    }
+
+    /// Handle the resume request of a client expecting the packet at `position`. Returns the packets to replay.
+    ///
+    /// If some of the missed packets are no longer kept, the replay starts at the oldest one kept, and the client
+    /// learns about the gap from the [`SIG_SEQUENCE`](crate::consts::SIG_SEQUENCE) frame preceding it.
+    fn resume(&mut self, position: Position, replay: &Replay) -> Vec<(u64, Packet)> {
+        let next = position.seq;
+        if position.session != replay.session || next > replay.next {
+            // the client was numbered by another server, so it has to start over
+            warn!("Client expects packet {next} of another session, starting over");
+            return Vec::new();
         }
-        Err(e @ ServerError::IoError(_)) => {
-            error!("Service encountered an I/O error: {e}");
-            return Err(e);
+
+        let packets = replay.since(next);
+        if let Some(&(start, _)) = packets.first()
+            && start > next
+        {
+            warn!("Packets {next}..{start} are no longer available for replay");
         }
+
+        self.next = Some(next);
+        packets
     }
 
-    Ok(close(stream)?)
-}
+    /// Write frames holding the given packets into this sink. See [`Encoder::frames`].
+    fn write(&mut self, packets: &[(u64, Packet)], sink: &mut impl Write) -> io::Result<()> {
+        self.frames(packets)
+            .iter()
+            .try_for_each(|frame| encode(frame, sink))
+    }
 
-/// The handler for the [`CONN_DATA`] connection.
-pub(crate) fn data_handler(
-    stream: &mut TcpStream,
-    addr: SocketAddr,
-    supplier: &Receiver<OutgoingDataPacket>,
-) -> Result<(), ServerError> {
-    info!("Data connection with {addr} established");
+    /// Write the given packets into this sink, in frames of up to [`MAX_BATCH`] packets if `batch` is set, or of one
+    /// packet otherwise.
+    fn write_all(
+        &mut self,
+        packets: &[(u64, Packet)],
+        batch: bool,
+        sink: &mut impl Write,
+    ) -> io::Result<()> {
+        packets
+            .chunks(if batch { usize::from(MAX_BATCH) } else { 1 })
+            .try_for_each(|chunk| self.write(chunk, sink))
+    }
 
-    // we do not want to block, since the client may not send anything at all (see find_exit_sig)
-    stream.set_nonblocking(true)?;
-    let mut writer = stream.try_clone()?;
-    let mut reader = BufReader::new(&*stream);
+    /// Build the frames holding the given packets, which are paired with their sequence numbers.
+    ///
+    /// If sequence numbers were negotiated, packets the client already received are skipped, and every packet which
+    /// does not continue the numbering of the client is preceded by a [`Frame::Sequence`]. If events were negotiated,
+    /// every packet carrying metadata is sent as a [`Frame::Event`]. The timestamps of the other packets are put into
+    /// as few frames as possible, see [`Encoder::frame`].
+    fn frames(&mut self, packets: &[(u64, Packet)]) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        // the timestamps which are put into the same frame
+        let mut run = Vec::new();
 
-    loop {
-        if find_exit_sig(&mut reader)? {
-            info!("Client sent exit signal, disconnecting");
-            break Ok(());
-        }
+        for &(seq, packet) in packets {
+            if self.sequence {
+                if self.next.is_some_and(|next| seq < next) {
+                    continue;
+                }
 
-        let packet = match supplier.try_recv() {
-            Err(TryRecvError::Disconnected) => {
-                warn!("Data packet supplier hung up, terminating connection with client");
-                break Err(ServerError::ChannelTermination);
+                if self.next != Some(seq) {
+                    self.flush(&mut run, &mut frames);
+                    frames.push(Frame::Sequence(Position {
+                        session: self.session,
+                        seq,
+                    }));
+                }
+                self.next = Some(seq + 1);
             }
-            Err(TryRecvError::Empty) => {
-                write_nothing(&mut writer)?;
-                continue;
+
+            if self.events && packet.fields() != 0 {
+                self.flush(&mut run, &mut frames);
+                self.last = Some(packet.timestamp);
+                frames.push(Frame::Event(Packet {
+                    timestamp: self.offset(packet.timestamp),
+                    ..packet
+                }));
+            } else {
+                run.push(packet.timestamp);
             }
-            Ok(v) => v,
-        };
+        }
 
-        write_packet(packet, &mut writer)?;
+        self.flush(&mut run, &mut frames);
+        frames
     }
-}
 
-/// Try to read an exit signal from the given stream. If a signal is found, this will return `Ok(true)`.
-/// If not, it will return `Ok(false)`.
-fn find_exit_sig(reader: &mut impl Read) -> io::Result<bool> {
-    let mut buf = [0; 2];
-    match reader.read(&mut buf) {
-        Ok(2) if buf == [CTRL, SIG_EXIT] => Ok(true),
-        Ok(_) => Ok(false),
-        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
-        Err(e) => Err(e),
+    /// Put the timestamps of `run` into a frame appended to `frames`, and clear it. Does nothing if `run` is empty.
+    fn flush(&mut self, run: &mut Vec<OutgoingDataPacket>, frames: &mut Vec<Frame>) {
+        if !run.is_empty() {
+            frames.push(self.frame(run));
+            run.clear();
+        }
     }
-}
 
-/// Write the [`EMP`] byte to this sink. Convenience function.
-fn write_nothing(sink: &mut impl Write) -> io::Result<()> {
-    sink.write_all(&[EMP])
+    /// Build a single frame holding the given packets.
+    ///
+    /// Packets are delta-encoded if enabled and possible, i.e., if a base timestamp has been sent and the packets do
+    /// not decrease. Otherwise, they are sent as a [`SIG_PACKET`](crate::consts::SIG_PACKET) frame, or a [`SIG_BATCH`](crate::consts::SIG_BATCH) frame if there are
+    /// multiple, which also sets the base for the following deltas. Deltas are the same for unix timestamps and their
+    /// offsets, so only the timestamps of those frames are converted, see [`Encoder::offset`].
+    ///
+    /// `packets` must hold at least one and at most [`MAX_BATCH`] packets.
+    fn frame(&mut self, packets: &[OutgoingDataPacket]) -> Frame {
+        let deltas = if self.delta {
+            deltas(self.last, packets)
+        } else {
+            None
+        };
+        self.last = packets.last().copied().or(self.last);
+
+        match (packets, deltas) {
+            ([_], Some(deltas)) => Frame::Delta(deltas[0]),
+            (_, Some(deltas)) => Frame::DeltaBatch(deltas),
+            ([packet], None) => Frame::Packet(self.offset(*packet)),
+            (_, None) => Frame::Batch(packets.iter().map(|&packet| self.offset(packet)).collect()),
+        }
+    }
 }
 
-/// Write the given packet into this sink.
-fn write_packet(packet: OutgoingDataPacket, sink: &mut impl Write) -> io::Result<()> {
-    let mut data = [SIG_PACKET; 17];
-    let bytes = packet.to_le_bytes();
-    data[1..].copy_from_slice(&bytes);
-    sink.write_all(&data)
+/// Compute the difference of every packet to the one preceding it, starting with `base`.
+///
+/// Returns `None` if there is no base, or if the packets decrease at any point.
+fn deltas(base: Option<OutgoingDataPacket>, packets: &[OutgoingDataPacket]) -> Option<Vec<u128>> {
+    let mut prev = base?;
+    packets
+        .iter()
+        .map(|&packet| {
+            let delta = packet.checked_sub(prev)?;
+            prev = packet;
+            Some(delta)
+        })
+        .collect()
 }
 
 /// A C-compatible wrapper around [`Server::run`].
@@ -215,19 +1987,230 @@ pub unsafe extern "C" fn c_server(
 ) -> i32 {
     use std::net::Ipv4Addr;
 
-    match server(
-        IpAddr::V4(Ipv4Addr::new(ip_a, ip_b, ip_c, ip_d)),
-        port,
-        unsafe { *Box::from_raw(receiver.cast::<Receiver<OutgoingDataPacket>>()) },
-    ) {
-        Ok(_) | Err(ServerError::ChannelTermination) => 0,
+    c_server_result(server(
+        (Ipv4Addr::new(ip_a, ip_b, ip_c, ip_d), port),
+        unsafe { *Box::from_raw(receiver.cast::<Receiver<Packet>>()) },
+    ))
+}
+
+/// A C-compatible wrapper around [`server`], taking the address to bind to as a string.
+///
+/// `host` may be an IPv4 address (`0.0.0.0`), an IPv6 address (`::`) or a hostname, in which case the server listens
+/// on every address it resolves to. Returns the same values as [`c_server`], or `-4` if `host` could not be resolved.
+///
+/// # Safety
+/// `host` must be a valid pointer to a nul-terminated string, and `receiver` must be a valid pointer.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_server_host(
+    host: *const std::ffi::c_char,
+    port: u16,
+    receiver: *mut (),
+) -> i32 {
+    let receiver = unsafe { *Box::from_raw(receiver.cast::<Receiver<Packet>>()) };
+    let Some(addrs) = (unsafe { crate::c_resolve(host, port) }) else {
+        return -4;
+    };
+
+    c_server_result(server(addrs.as_slice(), receiver))
+}
+
+/// A C-compatible wrapper around [`broadcast_server`]. The heartbeat interval is given in milliseconds. Returns the
+/// same values as [`c_server`].
+///
+/// # Safety
+/// `receiver` must be a valid pointer.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_broadcast_server(
+    ip_a: u8,
+    ip_b: u8,
+    ip_c: u8,
+    ip_d: u8,
+    port: u16,
+    receiver: *mut (),
+    queue_size: usize,
+    heartbeat_ms: u64,
+) -> i32 {
+    use std::net::Ipv4Addr;
+
+    c_server_result(broadcast_server(
+        (Ipv4Addr::new(ip_a, ip_b, ip_c, ip_d), port),
+        unsafe { *Box::from_raw(receiver.cast::<Receiver<Packet>>()) },
+        queue_size,
+        Duration::from_millis(heartbeat_ms),
+    ))
+}
+
+/// A C-compatible wrapper around [`broadcast_server`], taking the address to bind to as a string. See
+/// [`c_server_host`] and [`c_broadcast_server`].
+///
+/// # Safety
+/// `host` must be a valid pointer to a nul-terminated string, and `receiver` must be a valid pointer.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_broadcast_server_host(
+    host: *const std::ffi::c_char,
+    port: u16,
+    receiver: *mut (),
+    queue_size: usize,
+    heartbeat_ms: u64,
+) -> i32 {
+    let receiver = unsafe { *Box::from_raw(receiver.cast::<Receiver<Packet>>()) };
+    let Some(addrs) = (unsafe { crate::c_resolve(host, port) }) else {
+        return -4;
+    };
+
+    c_server_result(broadcast_server(
+        addrs.as_slice(),
+        receiver,
+        queue_size,
+        Duration::from_millis(heartbeat_ms),
+    ))
+}
+
+/// A C-compatible wrapper around [`spawn_server`], taking the address to bind to as a string. The heartbeat interval is
+/// given in milliseconds.
+///
+/// If `0` is returned, the handle to the server was written to `handle`, which must be disposed of with
+/// [`c_server_join`]. Otherwise, the same values as [`c_server_host`] are returned. No error of a connection is fatal.
+///
+/// # Safety
+/// `host` must be a valid pointer to a nul-terminated string, and `receiver` and `handle` must be valid pointers.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_spawn_server_host(
+    host: *const std::ffi::c_char,
+    port: u16,
+    receiver: *mut (),
+    heartbeat_ms: u64,
+    handle: *mut *mut (),
+) -> i32 {
+    let receiver = unsafe { *Box::from_raw(receiver.cast::<Receiver<Packet>>()) };
+    let Some(addrs) = (unsafe { crate::c_resolve(host, port) }) else {
+        return -4;
+    };
+
+    let server = spawn_server(
+        addrs.as_slice(),
+        receiver,
+        Duration::from_millis(heartbeat_ms),
+        ErrorPolicy::default(),
+    );
+    unsafe { c_server_handle(server, handle) }
+}
+
+/// A C-compatible wrapper around [`spawn_broadcast_server`], taking the address to bind to as a string. See
+/// [`c_spawn_server_host`] and [`c_broadcast_server`].
+///
+/// # Safety
+/// `host` must be a valid pointer to a nul-terminated string, and `receiver` and `handle` must be valid pointers.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_spawn_broadcast_server_host(
+    host: *const std::ffi::c_char,
+    port: u16,
+    receiver: *mut (),
+    queue_size: usize,
+    heartbeat_ms: u64,
+    handle: *mut *mut (),
+) -> i32 {
+    let receiver = unsafe { *Box::from_raw(receiver.cast::<Receiver<Packet>>()) };
+    let Some(addrs) = (unsafe { crate::c_resolve(host, port) }) else {
+        return -4;
+    };
+
+    let server = spawn_broadcast_server(
+        addrs.as_slice(),
+        receiver,
+        queue_size,
+        Duration::from_millis(heartbeat_ms),
+        ErrorPolicy::default(),
+    );
+    unsafe { c_server_handle(server, handle) }
+}
+
+/// Write the handle of a spawned server to `handle`, and convert the result into the return value of the
+/// C-compatible wrapper.
+///
+/// # Safety
+/// `handle` must be a valid pointer.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+unsafe fn c_server_handle(server: Result<ServerHandle, ServerError>, handle: *mut *mut ()) -> i32 {
+    match server {
+        Ok(server) => {
+            unsafe { *handle = Box::into_raw(Box::new(server)).cast() };
+            0
+        }
+        Err(e) => c_server_result::<()>(Err(e)),
+    }
+}
+
+/// A C-compatible wrapper around [`ServerHandle::shutdown`].
+///
+/// # Safety
+/// `handle` must be a valid pointer to a handle which was not joined yet.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn c_server_shutdown(handle: *const ()) {
+    unsafe { &*handle.cast::<ServerHandle>() }.shutdown();
+}
+
+/// The amount of connections of a spawned server which failed with an error so far. See [`ServerHandle::stats`].
+///
+/// # Safety
+/// `handle` must be a valid pointer to a handle which was not joined yet.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_server_failed_connections(handle: *const ()) -> u64 {
+    unsafe { &*handle.cast::<ServerHandle>() }.stats().failed
+}
+
+/// A C-compatible wrapper around [`ServerHandle::join`], which also frees the handle.
+///
+/// Returns `0` if the server was shut down, or the same values as [`c_server`] otherwise, e.g. `-2` if the supplier
+/// hung up before.
+///
+/// # Safety
+/// `handle` must be a valid pointer to a handle which was not joined yet.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_server_join(handle: *mut ()) -> i32 {
+    let handle = unsafe { Box::from_raw(handle.cast::<ServerHandle>()) };
+    c_server_result(handle.join())
+}
 
+/// Convert the result of a server into the return value of its C-compatible wrapper.
+#[cfg(feature = "interop")]
+fn c_server_result<T>(result: Result<T, ServerError>) -> i32 {
+    match result {
+        Ok(_) => 0,
         Err(ServerError::IoError(io)) => io.raw_os_error().unwrap_or(-1),
+        Err(ServerError::ChannelTermination) => -2,
     }
 }
 
 /// Create an MPSC channel for the server.
 ///
+/// Packets are sent over it with [`c_server_channel_send`], or with [`c_server_channel_send_event`] to send detector
+/// metadata along with them.
+///
 /// # Safety
 /// The sender must be correctly disposed of with [`c_free_server_sender`].
 #[cfg(feature = "interop")]
@@ -235,7 +2218,7 @@ pub unsafe extern "C" fn c_server(
 #[must_use]
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn c_server_channel(buffer: usize) -> crate::ChannelPair {
-    let (tx, rx) = std::sync::mpsc::sync_channel::<OutgoingDataPacket>(buffer);
+    let (tx, rx) = sync_channel::<Packet>(buffer);
     let tx = Box::into_raw(Box::new(tx)).cast();
     let rx = Box::into_raw(Box::new(rx)).cast();
 
@@ -248,8 +2231,9 @@ pub unsafe extern "C" fn c_server_channel(buffer: usize) -> crate::ChannelPair {
 /// `sender` must be a valid pointer.
 #[cfg(feature = "interop")]
 #[expect(unsafe_code)]
+#[unsafe(no_mangle)]
 pub unsafe extern "C" fn c_free_server_sender(sender: *mut ()) {
-    drop(unsafe { Box::from_raw(sender.cast::<std::sync::mpsc::Sender<OutgoingDataPacket>>()) });
+    drop(unsafe { Box::from_raw(sender.cast::<SyncSender<Packet>>()) });
 }
 
 /// Send the given packet over the supplied server sender.
@@ -264,7 +2248,312 @@ pub unsafe extern "C" fn c_server_channel_send(
     packet: OutgoingDataPacket,
     sender: *const (),
 ) -> bool {
-    let sender = unsafe { &*sender.cast::<std::sync::mpsc::Sender<OutgoingDataPacket>>() };
+    let sender = unsafe { &*sender.cast::<SyncSender<Packet>>() };
+
+    sender.send(packet.into()).is_ok_and(|()| true)
+}
+
+/// Send the given packet over the supplied server sender, along with detector metadata.
+///
+/// `fields` holds the flags of the fields which are set, i.e., [`FIELD_CHANNEL`](crate::consts::FIELD_CHANNEL),
+/// [`FIELD_PULSE_WIDTH`](crate::consts::FIELD_PULSE_WIDTH) and [`FIELD_TICK`](crate::consts::FIELD_TICK). The values of
+/// the other fields are ignored.
+///
+/// # Safety
+/// `sender` must be a valid pointer.
+#[cfg(feature = "interop")]
+#[expect(unsafe_code)]
+#[unsafe(no_mangle)]
+#[must_use]
+pub unsafe extern "C" fn c_server_channel_send_event(
+    packet: OutgoingDataPacket,
+    fields: u8,
+    channel: u16,
+    pulse_width: u32,
+    tick: u32,
+    sender: *const (),
+) -> bool {
+    use crate::consts::{FIELD_CHANNEL, FIELD_PULSE_WIDTH, FIELD_TICK};
+
+    let sender = unsafe { &*sender.cast::<SyncSender<Packet>>() };
+    let packet = Packet {
+        timestamp: packet,
+        channel: (fields & FIELD_CHANNEL != 0).then_some(channel),
+        pulse_width: (fields & FIELD_PULSE_WIDTH != 0).then_some(pulse_width),
+        tick: (fields & FIELD_TICK != 0).then_some(tick),
+    };
 
     sender.send(packet).is_ok_and(|()| true)
 }
+
+#[cfg(test)]
+mod tests {
+    //! Tests of servers running on their own thread, driven by raw client streams.
+
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        sync::mpsc::{channel, sync_channel},
+        time::{Duration, Instant},
+    };
+
+    use super::{
+        DEFAULT_HANDSHAKE_TIMEOUT, Encoder, Event, OutgoingDataPacket, ServerConfig, ServerError,
+        Subscriber, spawn_broadcast_server_with_config, spawn_server_with_config,
+    };
+    use socket2::{Domain, Socket, Type};
+
+    use crate::{
+        codec::{Decoder, Frame, FrameReader, Packet, encode},
+        consts::{
+            CAP_BATCH, CAP_DELTA, CAP_EPOCH, CAP_EVENT, CAP_SYNC, CONN_DATA, HELLO_ACCEPT, MAGIC,
+            MAX_BATCH, PROTOCOL_VERSION,
+        },
+    };
+
+    /// Decode all frames in `buf`.
+    fn decode_all(buf: &[u8], mut decoder: Decoder) -> Vec<Frame> {
+        let (mut frames, mut pos) = (Vec::new(), 0);
+        while let Some((frame, len)) = decoder.decode(&buf[pos..]).unwrap() {
+            frames.push(frame);
+            pos += len;
+        }
+
+        assert_eq!(pos, buf.len());
+        frames
+    }
+
+    /// With [`CAP_EPOCH`], timestamps are sent as offsets from the epoch of the first synchronisation round, which
+    /// the decoder converts back into unix timestamps. Without it, they are sent as they are.
+    #[test]
+    fn epoch_offsets_round_trip() {
+        const EPOCH: u128 = 1_700_000_000_000_000;
+
+        let packets = [
+            Packet::new(EPOCH - 10),
+            Packet::new(EPOCH + 5),
+            Packet::new(EPOCH + 5).with_channel(3),
+            Packet::new(EPOCH + 20),
+            Packet::new(EPOCH + 1),
+            Packet::new(EPOCH + 2),
+        ];
+        let packets: Vec<_> = (0..).zip(packets).collect();
+
+        for offsets in [true, false] {
+            let capabilities = CAP_SYNC | CAP_DELTA | CAP_EVENT;
+            let capabilities = if offsets {
+                capabilities | CAP_EPOCH
+            } else {
+                capabilities
+            };
+            let mut encoder = Encoder::new(capabilities, 1);
+
+            let mut buf = Vec::new();
+            encode(&Frame::Sync, &mut buf).unwrap();
+            let reply = Frame::SyncReply {
+                elapsed: 10,
+                epoch_micros: EPOCH,
+            };
+            encode(&reply, &mut buf).unwrap();
+            encoder.synchronised(EPOCH);
+            encoder.write(&packets[..1], &mut buf).unwrap();
+            encoder.write(&packets[1..4], &mut buf).unwrap();
+            encoder.write(&packets[4..], &mut buf).unwrap();
+
+            // a later round does not move the epoch
+            encode(&Frame::Sync, &mut buf).unwrap();
+            let reply = Frame::SyncReply {
+                elapsed: 10,
+                epoch_micros: EPOCH + 100,
+            };
+            encode(&reply, &mut buf).unwrap();
+            encoder.synchronised(EPOCH + 100);
+            encoder
+                .write(&[(6, Packet::new(EPOCH + 50))], &mut buf)
+                .unwrap();
+
+            let decoder = if offsets {
+                Decoder::new().with_epoch_offsets()
+            } else {
+                Decoder::new()
+            };
+            let (mut received, mut last) = (Vec::new(), None);
+            for frame in decode_all(&buf, decoder) {
+                frame.events_into(&mut last, &mut received).unwrap();
+            }
+            let mut expected: Vec<_> = packets.iter().map(|&(_, packet)| packet).collect();
+            expected.push(Packet::new(EPOCH + 50));
+            assert_eq!(received, expected);
+
+            // a legacy decoder sees what is on the wire
+            let frames = decode_all(&buf, Decoder::new());
+            let wire = if offsets {
+                10u128.wrapping_neg()
+            } else {
+                EPOCH - 10
+            };
+            assert_eq!(frames[2], Frame::Packet(wire));
+            let wire = if offsets { 1 } else { EPOCH + 1 };
+            assert_eq!(frames[6], Frame::Batch(vec![wire, wire + 1]));
+        }
+
+        // offsets cannot be decoded before the epoch is known
+        let mut buf = Vec::new();
+        encode(&Frame::Packet(1), &mut buf).unwrap();
+        assert!(Decoder::new().with_epoch_offsets().decode(&buf).is_err());
+    }
+
+    /// A queue holds at least one packet, and the packets dropped for a full queue are counted until reported.
+    #[test]
+    fn full_queue_counts_drops() {
+        let config = ServerConfig::new("127.0.0.1:0").unwrap();
+        assert_eq!(config.with_queue_size(0).queue_size, 1);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (queue, events) = sync_channel(1);
+        let mut subscriber = Subscriber {
+            addr: "127.0.0.1:1".parse().unwrap(),
+            queue,
+            socket: TcpStream::connect(listener.local_addr().unwrap()).unwrap(),
+            dropped: 0,
+            reported: Instant::now(),
+        };
+
+        assert!((0..5).all(|_| subscriber.offer(Event::Shutdown)));
+        assert_eq!(subscriber.dropped, 4);
+        subscriber.report();
+        assert_eq!(subscriber.dropped, 0);
+
+        drop(events);
+        assert!(!subscriber.offer(Event::Shutdown));
+    }
+
+    /// Connect to `addr` and perform the hello exchange of a data connection with the given capabilities.
+    fn connect(addr: SocketAddr, capabilities: u16) -> TcpStream {
+        hello(TcpStream::connect(addr).unwrap(), capabilities)
+    }
+
+    /// Perform the hello exchange of a data connection with the given capabilities over `stream`.
+    fn hello(mut stream: TcpStream, capabilities: u16) -> TcpStream {
+        let [caps_lo, caps_hi] = capabilities.to_le_bytes();
+        stream.write_all(&MAGIC).unwrap();
+        stream
+            .write_all(&[PROTOCOL_VERSION, caps_lo, caps_hi, CONN_DATA])
+            .unwrap();
+
+        let mut reply = [0; 4];
+        stream.read_exact(&mut reply).unwrap();
+        assert_eq!(reply, [HELLO_ACCEPT, PROTOCOL_VERSION, caps_lo, caps_hi]);
+        stream
+    }
+
+    /// Packets the supplier queued before the client connected are sent in full batches.
+    #[test]
+    fn backlog_is_batched() {
+        let (tx, rx) = channel::<OutgoingDataPacket>();
+        let total = 3 * u128::from(MAX_BATCH);
+        (0..total).for_each(|packet| tx.send(packet).unwrap());
+        let server =
+            spawn_server_with_config(ServerConfig::new("127.0.0.1:0").unwrap(), rx).unwrap();
+
+        // the forwarder fills the channel to the connection
+        std::thread::sleep(Duration::from_millis(200));
+        let mut reader = FrameReader::new(connect(server.local_addrs()[0], CAP_BATCH));
+
+        let mut received = match reader.next_frame::<std::io::Error>().unwrap() {
+            Frame::Batch(batch) => batch,
+            frame => panic!("expected a batch, got {frame:?}"),
+        };
+        assert_eq!(received.len(), usize::from(MAX_BATCH));
+
+        while (received.len() as u128) < total {
+            match reader.next_frame::<std::io::Error>().unwrap() {
+                Frame::Batch(batch) => received.extend(batch),
+                Frame::Packet(packet) => received.push(packet),
+                frame => panic!("unexpected frame {frame:?}"),
+            }
+        }
+        assert!(received.into_iter().eq(0..total));
+
+        server.shutdown();
+        assert!(server.join().is_ok());
+    }
+
+    /// A client which connects but never sends its hello does not keep a server which was shut down from exiting.
+    #[test]
+    fn silent_client_times_out() {
+        assert_eq!(
+            ServerConfig::new("127.0.0.1:0").unwrap().handshake_timeout,
+            Some(DEFAULT_HANDSHAKE_TIMEOUT)
+        );
+
+        let (_tx, rx) = channel::<OutgoingDataPacket>();
+        let config = ServerConfig::new("127.0.0.1:0")
+            .unwrap()
+            .with_handshake_timeout(Some(Duration::from_millis(100)));
+        let server = spawn_server_with_config(config, rx).unwrap();
+
+        let _silent = TcpStream::connect(server.local_addrs()[0]).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        let start = Instant::now();
+        server.shutdown();
+
+        assert!(server.join().is_ok());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    /// A broadcast client which never reads does not keep a server which was shut down from exiting, even though the
+    /// end of the server does not fit into its full queue.
+    #[test]
+    fn stalled_client_is_disconnected() {
+        let (tx, rx) = sync_channel::<OutgoingDataPacket>(16);
+        let config = ServerConfig::new("127.0.0.1:0")
+            .unwrap()
+            .with_queue_size(1)
+            .with_send_buffer(Some(4096));
+        let server = spawn_broadcast_server_with_config(config, rx).unwrap();
+
+        let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+        socket.set_recv_buffer_size(4096).unwrap();
+        socket.connect(&server.local_addrs()[0].into()).unwrap();
+        let _stalled = hello(socket.into(), 0);
+
+        // enough packets to fill the socket buffers, after which the handler is stuck writing
+        let flood = Instant::now();
+        for packet in 0.. {
+            if flood.elapsed() > Duration::from_millis(200) {
+                break;
+            }
+            tx.send(packet).unwrap();
+        }
+
+        let start = Instant::now();
+        server.shutdown();
+        assert!(server.join().is_ok());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    /// A broadcast server whose supplier hangs up exits right away, rather than once the next client connects.
+    #[test]
+    fn broadcast_reports_hang_up() {
+        let (tx, rx) = channel::<OutgoingDataPacket>();
+        let config = ServerConfig::new("127.0.0.1:0").unwrap();
+        let server = spawn_broadcast_server_with_config(config, rx).unwrap();
+        let mut reader = FrameReader::new(connect(server.local_addrs()[0], 0));
+
+        tx.send(1).unwrap();
+        assert_eq!(
+            reader.next_frame::<std::io::Error>().unwrap(),
+            Frame::Packet(1)
+        );
+        let start = Instant::now();
+        drop(tx);
+
+        assert_eq!(reader.next_frame::<std::io::Error>().unwrap(), Frame::Exit);
+        assert!(matches!(
+            server.join(),
+            Err(ServerError::ChannelTermination)
+        ));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
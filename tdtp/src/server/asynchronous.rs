@@ -0,0 +1,516 @@
+//! Asynchronous server, built on [`tokio`].
+//!
+//! Instead of a channel receiver, the server takes any [`Stream`] of packets as its supplier.
+
+use std::{
+    convert::Infallible,
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use futures_util::{FutureExt, Stream, StreamExt, future::poll_fn};
+use log::{debug, error, info, warn};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream, ToSocketAddrs, lookup_host},
+    time,
+};
+
+use super::{
+    Credit, DEFAULT_HANDSHAKE_TIMEOUT, DEFAULT_HEARTBEAT, DEFAULT_REPLAY_SIZE, Encoder,
+    ErrorHandling, RESUME_TIMEOUT, Replay, SYNC_TIMEOUT, ServerError, control, negotiate,
+};
+use crate::{
+    codec::{ControlDecoder, Frame, FrameBuffer, Packet, Position, encode},
+    consts::{
+        CAP_BATCH, CAP_CREDIT, CAP_RESUME, CAP_SYNC, CONN_DATA, ConnectionType, HELLO_ACCEPT,
+        HELLO_REJECT, MAGIC, MAX_BATCH, RejectReason, SIG_EXIT, SIG_SYNC,
+    },
+    no_addresses, unix_micros,
+};
+
+/// How many bytes of client input are read at once.
+const READ_SIZE: usize = 64;
+
+/// Listen for a connection at the given address. This is the asynchronous equivalent of [`super::server`].
+///
+/// The server will relay the packets yielded by `supplier`, which are either timestamps or [`Packet`]s, to the
+/// connector. If `supplier` ends, the server will exit with `Err(ServerError::ChannelTermination)`. A
+/// [`tokio::sync::mpsc::Receiver`] can be turned into a supplier with [`futures_util::stream::poll_fn`], see the
+/// example below.
+///
+/// Like its blocking equivalent, this server handles a single connection at a time, and a connection which fails is
+/// logged and dropped.
+///
+/// # Errors
+/// Returns either an I/O error or an error indicating that the supplier ended.
+///
+/// # Example
+/// ```no_run
+/// use futures_util::stream::poll_fn;
+/// use tdtp::server::{OutgoingDataPacket, asynchronous::server};
+/// use tokio::sync::mpsc;
+///
+/// # async fn example() {
+/// let (tx, mut rx) = mpsc::channel::<OutgoingDataPacket>(8192);
+/// // hand `tx` to the task which produces packets
+///
+/// let supplier = poll_fn(move |cx| rx.poll_recv(cx));
+/// server("localhost:8000", supplier)
+///     .await
+///     .expect("an I/O error occurred");
+/// # }
+/// ```
+pub async fn server(
+    addr: impl ToSocketAddrs,
+    supplier: impl Stream<Item = impl Into<Packet>> + Unpin,
+) -> Result<Infallible, ServerError> {
+    server_with_heartbeat(addr, supplier, DEFAULT_HEARTBEAT).await
+}
+
+/// Like [`server`], but sends an [`EMP`](crate::consts::EMP) heartbeat once no packet has been sent for `heartbeat`.
+///
+/// # Errors
+/// See [`server`].
+pub async fn server_with_heartbeat(
+    addr: impl ToSocketAddrs,
+    mut supplier: impl Stream<Item = impl Into<Packet>> + Unpin,
+    heartbeat: Duration,
+) -> Result<Infallible, ServerError> {
+    let listeners = bind(addr).await?;
+    info!("Now listening for connections");
+
+    let mut replay = Replay::new(DEFAULT_REPLAY_SIZE);
+    let errors = ErrorHandling::default();
+
+    loop {
+        let (conn, addr) = accept(&listeners).await?;
+        info!("Received connection from {addr}");
+        errors.accepted();
+
+        let result = router(conn, addr, &mut supplier, heartbeat, &mut replay).await;
+        errors.conclude(addr, result)?;
+    }
+}
+
+/// Bind a listener to every address `addr` resolves to. See [`super::Listeners::bind`].
+async fn bind(addr: impl ToSocketAddrs) -> io::Result<Vec<TcpListener>> {
+    let mut listeners = Vec::new();
+    let mut last_error = None;
+
+    info!("Starting listeners");
+    for addr in lookup_host(addr).await? {
+        match TcpListener::bind(addr).await {
+            Ok(listener) => {
+                info!("Started listener at {}", listener.local_addr()?);
+                listeners.push(listener);
+            }
+            Err(e) => {
+                warn!("Failed to bind to {addr}: {e}");
+                last_error = Some(e);
+            }
+        }
+    }
+
+    match last_error {
+        Some(e) if listeners.is_empty() => Err(e),
+        None if listeners.is_empty() => Err(no_addresses()),
+        _ => Ok(listeners),
+    }
+}
+
+/// Wait for a connection on any of the given listeners.
+async fn accept(listeners: &[TcpListener]) -> io::Result<(TcpStream, SocketAddr)> {
+    poll_fn(|cx| {
+        listeners
+            .iter()
+            .find_map(|listener| match listener.poll_accept(cx) {
+                Poll::Ready(result) => Some(result),
+                Poll::Pending => None,
+            })
+            .map_or(Poll::Pending, Poll::Ready)
+    })
+    .await
+}
+
+/// Route the incoming connection to a handler. See [`super::router`].
+///
+/// A client which does not complete the hello exchange within [`DEFAULT_HANDSHAKE_TIMEOUT`] is dropped, so that it
+/// cannot keep the server from serving other clients.
+async fn router(
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    supplier: &mut (impl Stream<Item = impl Into<Packet>> + Unpin),
+    heartbeat: Duration,
+    replay: &mut Replay,
+) -> Result<(), ServerError> {
+    let hello = match time::timeout(DEFAULT_HANDSHAKE_TIMEOUT, handshake(&mut stream, addr)).await {
+        Ok(result) => result?,
+        Err(_) => {
+            return Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "client did not complete the hello exchange in time",
+            )
+            .into());
+        }
+    };
+    let Some((ConnectionType::Data, capabilities)) = hello else {
+        return Ok(());
+    };
+
+    match data_handler(&mut stream, addr, supplier, heartbeat, capabilities, replay).await {
+        Ok(()) => {
+            debug!("Writing transmission delimiter to connection");
+        }
+        Err(e @ ServerError::ChannelTermination) => {
+            close(stream).await?;
+            return Err(e);
+        }
+        Err(e @ ServerError::IoError(_)) => {
+            error!("Service encountered an I/O error: {e}");
+            return Err(e);
+        }
+    }
+
+    Ok(close(stream).await?)
+}
+
+/// Close the given TCP stream. See [`crate::close`].
+async fn close(mut stream: TcpStream) -> io::Result<()> {
+    info!("Closing stream");
+    send(&mut stream, &Frame::Exit).await?;
+    stream.shutdown().await
+}
+
+/// Perform the hello exchange with a connecting client. See [`super::handshake`].
+async fn handshake(
+    stream: &mut TcpStream,
+    addr: SocketAddr,
+) -> io::Result<Option<(ConnectionType, u16)>> {
+    let first = stream.read_u8().await?;
+
+    if first == CONN_DATA {
+        info!("{addr} is a legacy client, skipping version negotiation");
+        return Ok(Some((ConnectionType::Data, 0)));
+    } else if first != MAGIC[0] {
+        reject(stream, addr, RejectReason::BadMagic).await?;
+        return Ok(None);
+    }
+
+    // the rest of the magic, version, capabilities and connection type
+    let mut hello = [0; 7];
+    stream.read_exact(&mut hello).await?;
+
+    match negotiate(hello, &[ConnectionType::Data]) {
+        Ok((conn_ty, version, capabilities)) => {
+            let [caps_lo, caps_hi] = capabilities.to_le_bytes();
+            stream
+                .write_all(&[HELLO_ACCEPT, version, caps_lo, caps_hi])
+                .await?;
+            debug!(
+                "Accepted {addr} with protocol version {version} and capabilities {capabilities:#06x}"
+            );
+
+            Ok(Some((conn_ty, capabilities)))
+        }
+        Err(reason) => {
+            reject(stream, addr, reason).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Reject the client for the given reason.
+async fn reject(stream: &mut TcpStream, addr: SocketAddr, reason: RejectReason) -> io::Result<()> {
+    warn!("Rejecting {addr}: {reason}");
+    stream.write_all(&[HELLO_REJECT, reason as u8]).await?;
+    stream.shutdown().await
+}
+
+/// The handler for the [`CONN_DATA`] connection. See [`super::data_handler`].
+async fn data_handler(
+    stream: &mut TcpStream,
+    addr: SocketAddr,
+    supplier: &mut (impl Stream<Item = impl Into<Packet>> + Unpin),
+    heartbeat: Duration,
+    capabilities: u16,
+    replay: &mut Replay,
+) -> Result<(), ServerError> {
+    info!("Data connection with {addr} established");
+
+    let (mut reader, mut writer) = stream.split();
+    let sync = capabilities & CAP_SYNC != 0;
+    let batch = capabilities & CAP_BATCH != 0;
+    let mut encoder = Encoder::new(capabilities, replay.session);
+    let mut credit = (capabilities & CAP_CREDIT != 0).then(Credit::new);
+    let mut packets = Vec::new();
+    // the bytes sent by the client, but not decoded yet
+    let mut controls = FrameBuffer::with_decoder(ControlDecoder::new());
+
+    let mut next = None;
+    if capabilities & CAP_RESUME != 0 {
+        next = read_resume(&mut reader, &mut controls).await?;
+    }
+
+    // the first round sets the epoch, so it must precede any packet
+    let mut granted = 0;
+    if sync {
+        granted = sync_clock(&mut reader, &mut writer, &mut encoder, &mut controls).await?;
+    }
+
+    if let Some(next) = next {
+        let packets = encoder.resume(next, replay);
+        debug!("Replaying {} packets to {addr}", packets.len());
+
+        match (&mut credit, packets.first()) {
+            (Some(credit), Some(&(from, _))) => credit.hold(from),
+            _ => send_all(&mut writer, &mut encoder, &packets, batch).await?,
+        }
+    }
+
+    if sync {
+        grant(
+            &mut writer,
+            &mut encoder,
+            &mut credit,
+            granted,
+            replay,
+            batch,
+        )
+        .await?;
+    }
+
+    loop {
+        tokio::select! {
+            packet = supplier.next() => {
+                let Some(packet) = packet else {
+                    warn!("Data packet supplier hung up, terminating connection with client");
+                    return Err(ServerError::ChannelTermination);
+                };
+
+                let packet = packet.into();
+                packets.clear();
+                packets.push((replay.push(packet), packet));
+                let terminated = batch && collect_batch(supplier, replay, &mut packets);
+
+                let sendable = match &mut credit {
+                    Some(credit) => credit.take(&packets),
+                    None => &packets,
+                };
+                if !sendable.is_empty() {
+                    for frame in encoder.frames(sendable) {
+                        send(&mut writer, &frame).await?;
+                    }
+                } else if credit.as_mut().is_some_and(|credit| credit.heartbeat_due(heartbeat)) {
+                    send(&mut writer, &Frame::Empty).await?;
+                }
+
+                if terminated {
+                    warn!("Data packet supplier hung up, terminating connection with client");
+                    return Err(ServerError::ChannelTermination);
+                }
+            }
+            read = reader.read_buf(controls.buffer_mut()) => {
+                if read? == 0 {
+                    info!("Client closed the connection, disconnecting");
+                    return Ok(());
+                }
+
+                while let Some(frame) = next_control(&mut controls) {
+                    let granted = match frame {
+                        Frame::Credit(amount) => amount,
+                        frame => match control(&frame) {
+                            Some(SIG_EXIT) => {
+                                info!("Client sent exit signal, disconnecting");
+                                return Ok(());
+                            }
+                            Some(SIG_SYNC) if sync => {
+                                debug!("Client requested clock synchronisation");
+                                sync_clock(&mut reader, &mut writer, &mut encoder, &mut controls).await?
+                            }
+                            _ => continue,
+                        },
+                    };
+                    grant(&mut writer, &mut encoder, &mut credit, granted, replay, batch).await?;
+                }
+
+                controls.buffer_mut().reserve(READ_SIZE);
+            }
+            () = time::sleep(heartbeat) => send(&mut writer, &Frame::Empty).await?,
+        }
+    }
+}
+
+/// Append the packets which are ready right away to `packets`, up to [`MAX_BATCH`] packets, numbering them in
+/// `replay`. Returns whether the supplier ended.
+fn collect_batch(
+    supplier: &mut (impl Stream<Item = impl Into<Packet>> + Unpin),
+    replay: &mut Replay,
+    packets: &mut Vec<(u64, Packet)>,
+) -> bool {
+    while packets.len() < usize::from(MAX_BATCH) {
+        match supplier.next().now_or_never() {
+            Some(Some(packet)) => {
+                let packet = packet.into();
+                packets.push((replay.push(packet), packet));
+            }
+            Some(None) => return true,
+            None => break,
+        }
+    }
+
+    false
+}
+
+/// Decode the next frame the client sent, skipping invalid bytes. Returns `None` once `frames` does not hold a
+/// complete frame.
+fn next_control(frames: &mut FrameBuffer<ControlDecoder>) -> Option<Frame> {
+    loop {
+        match frames.next_frame() {
+            Ok(frame) => return frame,
+            Err(e) => warn!("Client sent an invalid frame: {e}"),
+        }
+    }
+}
+
+/// Read the resume request of the client, which it sends as its first frame if [`CAP_RESUME`] was negotiated.
+///
+/// Returns the position of the next packet the client expects, or `None` for a new connection. Bytes read
+/// past the request are left in `frames`.
+async fn read_resume(
+    reader: &mut (impl AsyncRead + Unpin),
+    frames: &mut FrameBuffer<ControlDecoder>,
+) -> Result<Option<Position>, ServerError> {
+    let read = async {
+        loop {
+            if let Some(frame) = frames.next_frame()? {
+                return match frame {
+                    Frame::Resume(next) => Ok(next),
+                    _ => Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "client did not send a resume request",
+                    )),
+                };
+            }
+
+            let buf = frames.buffer_mut();
+            buf.reserve(READ_SIZE);
+            if reader.read_buf(buf).await? == 0 {
+                return Err(io::Error::from(ErrorKind::UnexpectedEof));
+            }
+        }
+    };
+
+    match time::timeout(RESUME_TIMEOUT, read).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(io::Error::new(
+            ErrorKind::TimedOut,
+            "client did not send a resume request in time",
+        )
+        .into()),
+    }
+}
+
+/// Run a clock synchronisation round with the client. See [`super::sync_clock`].
+///
+/// The echo is decoded from the client input buffered in `frames`, like other frames. Returns the credit the client
+/// granted during the round.
+async fn sync_clock(
+    reader: &mut (impl AsyncRead + Unpin),
+    writer: &mut (impl AsyncWrite + Unpin),
+    encoder: &mut Encoder,
+    frames: &mut FrameBuffer<ControlDecoder>,
+) -> Result<u64, ServerError> {
+    debug!("Starting clock synchronisation");
+
+    let epoch_micros = unix_micros();
+    send(writer, &Frame::Sync).await?;
+    let epoch = Instant::now();
+
+    let echo = async {
+        let mut granted = 0u64;
+        loop {
+            match next_control(frames) {
+                Some(Frame::Credit(amount)) => granted = granted.saturating_add(amount),
+                Some(Frame::Control(SIG_SYNC)) => return Ok(granted),
+                Some(_) => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "client did not echo the synchronisation signal",
+                    ));
+                }
+                None => {
+                    let buf = frames.buffer_mut();
+                    buf.reserve(READ_SIZE);
+                    if reader.read_buf(buf).await? == 0 {
+                        return Err(io::Error::from(ErrorKind::UnexpectedEof));
+                    }
+                }
+            }
+        }
+    };
+
+    let granted = match time::timeout(SYNC_TIMEOUT, echo).await {
+        Ok(result) => result?,
+        Err(_) => {
+            return Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "client did not echo the synchronisation signal in time",
+            )
+            .into());
+        }
+    };
+
+    let reply = Frame::SyncReply {
+        elapsed: epoch.elapsed().as_micros(),
+        epoch_micros,
+    };
+    send(writer, &reply).await?;
+    encoder.synchronised(epoch_micros);
+    Ok(granted)
+}
+
+/// Add the credit granted by the client, if it grants credit at all, and send the packets held back which it covers.
+/// See [`Credit::grant`].
+async fn grant(
+    writer: &mut (impl AsyncWrite + Unpin),
+    encoder: &mut Encoder,
+    credit: &mut Option<Credit>,
+    amount: u64,
+    replay: &Replay,
+    batch: bool,
+) -> io::Result<()> {
+    match credit {
+        Some(credit) if amount > 0 => {
+            let packets = credit.grant(amount, replay);
+            send_all(writer, encoder, &packets, batch).await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Write the given packets to this writer, in frames of up to [`MAX_BATCH`] packets if `batch` is set. See
+/// [`Encoder::write_all`].
+async fn send_all(
+    writer: &mut (impl AsyncWrite + Unpin),
+    encoder: &mut Encoder,
+    packets: &[(u64, Packet)],
+    batch: bool,
+) -> io::Result<()> {
+    for chunk in packets.chunks(if batch { usize::from(MAX_BATCH) } else { 1 }) {
+        for frame in encoder.frames(chunk) {
+            send(writer, &frame).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Encode the given frame and write it to this writer.
+async fn send(writer: &mut (impl AsyncWrite + Unpin), frame: &Frame) -> io::Result<()> {
+    let mut data = Vec::new();
+    encode(frame, &mut data)?;
+    writer.write_all(&data).await
+}